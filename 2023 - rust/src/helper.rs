@@ -1,11 +1,8 @@
+pub use aoc_core::error::{parse_arg, AocError, GenResult, InputContext};
 use std::env;
-use std::error::Error;
-use std::io::{BufRead, Lines};
 use std::path::PathBuf;
 use std::time::SystemTime;
 
-pub type GenResult<A> = Result<A, Box<dyn Error>>;
-
 pub fn puzzle_input_path(puzzle_num: u8) -> GenResult<PathBuf> {
     let mut dir = env::current_dir()?;
     dir.push("inputs");
@@ -17,8 +14,8 @@ pub fn parse_u32(s: &str) -> Result<u32, String> {
     s.parse::<u32>().map_err(|e| e.to_string())
 }
 
-pub fn parse_u64(s: &str) -> Result<u64, String> {
-    s.parse::<u64>().map_err(|e| format!("Couldn't parse u64 from {} ({})", s, e.to_string()))
+pub fn parse_i64(s: &str) -> Result<i64, String> {
+    s.parse::<i64>().map_err(|e| format!("Couldn't parse i64 from {} ({})", s, e.to_string()))
 }
 
 pub fn print_elapsed(description: &str, time: SystemTime) {
@@ -37,15 +34,3 @@ macro_rules! timed {
         }
     }
 }
-
-pub trait EzLines {
-    fn expect_next_line(&mut self) -> Result<String, String>;
-}
-
-impl <B: BufRead> EzLines for Lines<B> {
-    fn expect_next_line(&mut self) -> Result<String, String> {
-        self.next()
-            .ok_or("missing expected line")?
-            .map_err(|io_err| io_err.to_string())
-    }
-}
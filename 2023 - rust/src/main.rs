@@ -1,6 +1,7 @@
 use std::env;
+use std::process::ExitCode;
 
-use crate::helper::{GenResult, puzzle_input_path};
+use crate::helper::{AocError, GenResult, InputContext, parse_arg, puzzle_input_path};
 
 mod helper;
 mod puzzle01;
@@ -11,7 +12,17 @@ mod puzzle05;
 mod puzzle06;
 mod puzzle07;
 
-fn main() -> GenResult<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> GenResult<()> {
 
     let mut args = env::args();
 
@@ -19,16 +30,16 @@ fn main() -> GenResult<()> {
     let _ = args.next();
 
     // first actual argument should be the puzzle number
-    let puzzle_num = args
-        .next()
-        .ok_or("Expected a puzzle number")?
-        .parse::<u8>()?;
+    let puzzle_num = parse_arg::<u8>(
+        "puzzle number",
+        &args.next().ok_or_else(|| AocError::Args("expected a puzzle number".to_string()))?,
+    )?;
 
     let mut debug_on = false;
     while let Some(arg) = args.next() {
         match arg.as_str() {
             "--debug" => { debug_on = true },
-            other => Err(format!("Unexpected argument '{}'", other))?,
+            other => Err(AocError::Args(format!("unexpected argument '{}'", other)))?,
         }
     }
 
@@ -37,7 +48,7 @@ fn main() -> GenResult<()> {
 
     // dispatch the appropriate puzzle script based on the argument
 
-    match puzzle_num {
+    let result = match puzzle_num {
         1 => puzzle01::run(&input_path),
         2 => puzzle02::run(&input_path),
         3 => puzzle03::run(&input_path),
@@ -46,5 +57,7 @@ fn main() -> GenResult<()> {
         6 => puzzle06::run(&input_path, debug_on),
         7 => puzzle07::run(&input_path, debug_on),
         _ => Ok(()),
-    }
+    };
+
+    result.with_input_file(&input_path)
 }
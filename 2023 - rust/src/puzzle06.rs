@@ -2,8 +2,10 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-use crate::{helper, timed};
-use crate::helper::{EzLines, GenResult};
+use aoc_core::parse::{self, InputSpan};
+
+use crate::timed;
+use crate::helper::GenResult;
 
 pub fn run(path: &Path, debug_on: bool) -> GenResult<()> {
     let races = timed!("parsed input", {
@@ -77,42 +79,40 @@ fn win_count(min_hold: u64, max_hold: u64) -> u64 {
     u64::abs_diff(min_hold, max_hold) + 1
 }
 
-fn parse_input(path: &Path) -> Result<Vec<Race>, String> {
-    let mut lines = BufReader::new(File::open(path).map_err(|e| e.to_string())?).lines();
+fn read_input_lines(path: &Path) -> GenResult<Vec<String>> {
+    Ok(BufReader::new(File::open(path)?).lines().collect::<Result<Vec<_>, _>>()?)
+}
+
+fn parse_input(path: &Path) -> GenResult<Vec<Race>> {
+    let lines = read_input_lines(path)?;
+    let [time_line, dist_line] = parse::expect_lines(&parse::spans(&lines).collect::<Vec<_>>())?;
 
-    let time_nums = parse_input_nums(lines.expect_next_line()?.as_str())?;
-    let dist_nums = parse_input_nums(lines.expect_next_line()?.as_str())?;
+    let time_nums = time_line.key_value("Time")?.list::<u64>(' ')?;
+    let dist_nums = dist_line.key_value("Distance")?.list::<u64>(' ')?;
 
     if time_nums.len() == dist_nums.len() {
         Ok(time_nums.into_iter().zip(dist_nums).map(|(time, dist)| {
             Race { time, dist }
         }).collect())
     } else {
-        Err(format!("mismatched number of numbers: {:?} vs {:?}", time_nums, dist_nums))
+        Err(dist_line.error(format!("mismatched number of numbers: {:?} vs {:?}", time_nums, dist_nums)))?
     }
 }
 
-fn parse_input_nums(nums: &str) -> Result<Vec<u64>, String> {
-    nums
-        .split(':').skip(1).next()
-        .ok_or("missing ':' in nums line")?
-        .split_whitespace()
-        .map(helper::parse_u64)
-        .collect()
-}
-
-fn parse_input_v2(path: &Path) -> Result<Race, String> {
-    let mut lines = BufReader::new(File::open(path).map_err(|e| e.to_string())?).lines();
-    let time = parse_input_nums_v2(lines.expect_next_line()?.as_str())?;
-    let dist = parse_input_nums_v2(lines.expect_next_line()?.as_str())?;
+fn parse_input_v2(path: &Path) -> GenResult<Race> {
+    let lines = read_input_lines(path)?;
+    let [time_line, dist_line] = parse::expect_lines(&parse::spans(&lines).collect::<Vec<_>>())?;
+    let time = parse_input_nums_v2(time_line.key_value("Time")?)?;
+    let dist = parse_input_nums_v2(dist_line.key_value("Distance")?)?;
     Ok(Race { time, dist })
 }
 
-fn parse_input_nums_v2(nums: &str) -> Result<u64, String> {
+/// Parse the numbers in the span as if the spaces between them weren't there
+fn parse_input_nums_v2(nums: InputSpan) -> GenResult<u64> {
     let concated_nums = nums
-        .split(':').skip(1).next()
-        .ok_or("missing ':' in nums line")?
-        .split_whitespace()
+        .split(' ')
+        .iter()
+        .map(InputSpan::text)
         .collect::<String>();
-    helper::parse_u64(concated_nums.as_str())
+    Ok(concated_nums.parse::<u64>().map_err(|e| nums.error(e.to_string()))?)
 }
@@ -3,15 +3,19 @@ use std::fmt::{Debug, Display, Formatter, Write};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use crate::helper::{GenResult, parse_u32};
+use crate::helper::{GenResult, InputContext, parse_u32};
 
 pub fn run(path: &Path, debug_on: bool) -> GenResult<()> {
 
     let hands = BufReader::new(File::open(path)?)
         .lines()
-        .map(|l| l.map_err(|e| e.to_string()))
-        .map(|l| Hand::try_from(l?.as_str()))
-        .collect::<Result<Vec<_>, _>>()?;
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line?;
+            let hand: GenResult<Hand> = Hand::try_from(line.as_str()).map_err(Into::into);
+            hand.at_input_line(i, &line)
+        })
+        .collect::<GenResult<Vec<_>>>()?;
 
     for hand in &hands {
         println!("{:?} -> {:?}", hand, calc_hand_type(hand));
@@ -1,5 +1,8 @@
-use colored::ColoredString;
+use colored::{ColoredString, Colorize};
+use std::collections::HashSet;
+use std::env;
 use std::fmt::{Display, Formatter, Write};
+use std::io::IsTerminal;
 use std::ops::{Add, AddAssign, Index, IndexMut};
 
 /// Represents one of the four cardinal directions
@@ -370,6 +373,28 @@ impl<Tile> Grid<Tile> {
     }
 }
 
+impl<Tile: PartialEq> Grid<Tile> {
+    /// List the addresses whose tiles differ between `self` and `other`, along with the
+    /// tile from each grid. Addresses that only exist in one of the grids count as
+    /// differing, with `None` for the grid that's missing them.
+    pub fn diff<'a>(
+        &'a self,
+        other: &'a Grid<Tile>,
+    ) -> Vec<(GridAddress, Option<&'a Tile>, Option<&'a Tile>)> {
+        let row_len = |grid: &Grid<Tile>, y: usize| grid.rows.get(y).map_or(0, Vec::len);
+        let mut out = Vec::new();
+        for y in 0..self.height().max(other.height()) {
+            for x in 0..row_len(self, y).max(row_len(other, y)) {
+                let (old, new) = (self.get(x, y), other.get(x, y));
+                if old != new {
+                    out.push((GridAddress(x, y), old, new));
+                }
+            }
+        }
+        out
+    }
+}
+
 impl<Tile> Index<GridAddress> for Grid<Tile> {
     type Output = Tile;
 
@@ -390,6 +415,12 @@ impl<Tile> IndexMut<GridAddress> for Grid<Tile> {
 
 pub trait RenderTileChar<Tile> {
     fn render_tile_char(&self, tile: &Tile, x: usize, y: usize) -> ColoredString;
+
+    /// Pairs of `(glyph, meaning)` describing the characters emitted by `render_tile_char`,
+    /// shown when a `CharGridBuilder` is asked to include a legend.
+    fn legend(&self) -> Vec<(ColoredString, &'static str)> {
+        Vec::new()
+    }
 }
 
 /// Wrapper struct used for displaying a Grid<Tile>
@@ -410,3 +441,497 @@ where
         Ok(())
     }
 }
+
+/// Wrapper struct used for displaying the second of two `Grid<Tile>`s, highlighting the
+/// tiles which differ from the first grid: `DiffCharGrid(&before, &after, style)`
+pub struct DiffCharGrid<'g, Tile, Style>(pub &'g Grid<Tile>, pub &'g Grid<Tile>, pub Style);
+
+impl<Tile, Style> Display for DiffCharGrid<'_, Tile, Style>
+where
+    Tile: PartialEq,
+    Style: RenderTileChar<Tile>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let DiffCharGrid(before, after, style) = self;
+        let changed = before
+            .diff(after)
+            .into_iter()
+            .map(|(addr, _, _)| addr)
+            .collect::<HashSet<_>>();
+        for (y, row) in after.rows.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let c = style.render_tile_char(tile, x, y);
+                if changed.contains(&GridAddress(x, y)) {
+                    write!(f, "{}", c.on_red())?;
+                } else {
+                    write!(f, "{}", c)?;
+                }
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+/// Panics if the two grids differ, with a message showing the differing rows side-by-side
+/// (rendered without color), with `^` marking the differing columns.
+#[cfg(test)]
+#[track_caller]
+pub fn assert_grids_eq<Tile, Style>(expected: &Grid<Tile>, actual: &Grid<Tile>, style: Style)
+where
+    Tile: PartialEq,
+    Style: RenderTileChar<Tile>,
+{
+    let changes = expected.diff(actual);
+    let same_size = expected.width() == actual.width() && expected.height() == actual.height();
+    if changes.is_empty() && same_size {
+        return;
+    }
+
+    let render_row = |grid: &Grid<Tile>, y: usize| -> String {
+        match grid.rows.get(y) {
+            Some(row) => row
+                .iter()
+                .enumerate()
+                .map(|(x, tile)| style.render_tile_char(tile, x, y).clear().to_string())
+                .collect(),
+            None => String::new(),
+        }
+    };
+
+    let mut message = format!(
+        "grids differ at {} tile(s) (expected {}x{}, actual {}x{})\n",
+        changes.len(),
+        expected.width(),
+        expected.height(),
+        actual.width(),
+        actual.height(),
+    );
+    let row_width = expected.width().max("expected".len());
+    let num_width = expected.height().max(actual.height()).to_string().len();
+    message += &format!("{:num_width$}  {:row_width$}  actual\n", "", "expected");
+
+    let mut rows_with_changes = changes
+        .iter()
+        .map(|(addr, _, _)| addr.1)
+        .collect::<Vec<_>>();
+    // rows outside the overlap always count as changed, even if they're empty
+    let max_height = expected.height().max(actual.height());
+    rows_with_changes.extend(expected.height().min(actual.height())..max_height);
+    rows_with_changes.sort();
+    rows_with_changes.dedup();
+
+    for y in rows_with_changes {
+        let markers = (0..expected.width().max(actual.width()))
+            .map(|x| {
+                if expected.get(x, y) == actual.get(x, y) {
+                    ' '
+                } else {
+                    '^'
+                }
+            })
+            .collect::<String>();
+        message += &format!(
+            "{:>num_width$}  {:row_width$}  {}\n",
+            y,
+            render_row(expected, y),
+            render_row(actual, y)
+        );
+        message += &format!(
+            "{:num_width$}  {:row_width$}  {}\n",
+            "",
+            markers.trim_end(),
+            markers.trim_end()
+        );
+    }
+
+    panic!("{}", message);
+}
+
+impl<'g, Tile, Style> CharGrid<'g, Tile, Style> {
+    /// Start building a customized display of this grid, e.g. cropped to a viewport
+    pub fn builder(self) -> CharGridBuilder<'g, Tile, Style> {
+        CharGridBuilder {
+            char_grid: self,
+            viewport: None,
+            axis_interval: None,
+            show_legend: false,
+            color: ColorMode::detect(),
+        }
+    }
+}
+
+/// An inclusive rectangular region of a `Grid`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct GridRect {
+    pub min: GridAddress,
+    pub max: GridAddress,
+}
+
+impl GridRect {
+    /// The square region extending `radius` tiles from `focus` in each direction
+    pub fn around(focus: GridAddress, radius: usize) -> Self {
+        GridRect {
+            min: GridAddress(
+                focus.0.saturating_sub(radius),
+                focus.1.saturating_sub(radius),
+            ),
+            max: GridAddress(
+                focus.0.saturating_add(radius),
+                focus.1.saturating_add(radius),
+            ),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn contains(&self, addr: GridAddress) -> bool {
+        (self.min.0..=self.max.0).contains(&addr.0) && (self.min.1..=self.max.1).contains(&addr.1)
+    }
+}
+
+/// Controls whether a `CharGridBuilder` emits colors
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ColorMode {
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Colored unless the `NO_COLOR` environment variable is set,
+    /// or stderr (where the log output goes) isn't a terminal
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stderr().is_terminal() {
+            ColorMode::Always
+        } else {
+            ColorMode::Never
+        }
+    }
+}
+
+/// Displays a `Grid` like `CharGrid` does, with optional cropping, axis labels, and a legend.
+///
+/// ```text
+/// CharGrid(&grid, style)
+///     .builder()
+///     .viewport(GridRect::around(focus, 5))
+///     .axis_labels(5)
+///     .legend()
+/// ```
+pub struct CharGridBuilder<'g, Tile, Style> {
+    char_grid: CharGrid<'g, Tile, Style>,
+    viewport: Option<GridRect>,
+    axis_interval: Option<usize>,
+    show_legend: bool,
+    color: ColorMode,
+}
+
+impl<Tile, Style> CharGridBuilder<'_, Tile, Style> {
+    /// Only render the tiles within the given region (clamped to the grid's bounds)
+    pub fn viewport(mut self, rect: GridRect) -> Self {
+        self.viewport = Some(rect);
+        self
+    }
+
+    /// Label the column and row numbers every `interval` tiles
+    pub fn axis_labels(mut self, interval: usize) -> Self {
+        self.axis_interval = Some(interval.max(1));
+        self
+    }
+
+    /// Print the style's legend below the grid
+    pub fn legend(mut self) -> Self {
+        self.show_legend = true;
+        self
+    }
+
+    /// Override the color mode detected from the environment
+    #[cfg(test)]
+    pub fn color(mut self, mode: ColorMode) -> Self {
+        self.color = mode;
+        self
+    }
+
+    /// The (inclusive) x and y ranges of tiles to be rendered, or `None` if no tiles are visible
+    fn visible_rect(&self) -> Option<GridRect> {
+        let grid = self.char_grid.0;
+        let max_x = grid.width().checked_sub(1)?;
+        let max_y = grid.height().checked_sub(1)?;
+        let rect = self.viewport.unwrap_or(GridRect {
+            min: GridAddress(0, 0),
+            max: GridAddress(max_x, max_y),
+        });
+        let min = rect.min;
+        let max = GridAddress(rect.max.0.min(max_x), rect.max.1.min(max_y));
+        if min.0 > max.0 || min.1 > max.1 {
+            None
+        } else {
+            Some(GridRect { min, max })
+        }
+    }
+}
+
+impl<Tile, Style> Display for CharGridBuilder<'_, Tile, Style>
+where
+    Style: RenderTileChar<Tile>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let CharGrid(grid, style) = &self.char_grid;
+        let use_color = self.color == ColorMode::Always;
+        let paint = |s: ColoredString| if use_color { s } else { s.clear() };
+
+        let Some(GridRect { min, max }) = self.visible_rect() else {
+            return Ok(());
+        };
+
+        // row labels go in a left-hand gutter, wide enough for the biggest row number
+        let gutter = match self.axis_interval {
+            Some(_) => max.1.to_string().len() + 1,
+            None => 0,
+        };
+
+        // column labels are written left-aligned above their column, skipping any that would overlap
+        if let Some(interval) = self.axis_interval {
+            let mut header = String::new();
+            for x in min.0..=max.0 {
+                let column = x - min.0;
+                if x % interval == 0 && header.len() <= column {
+                    header.extend(std::iter::repeat_n(' ', column - header.len()));
+                    header.push_str(&x.to_string());
+                    header.push(' ');
+                }
+            }
+            writeln!(
+                f,
+                "{:gutter$}{}",
+                "",
+                paint(header.trim_end().bright_black())
+            )?;
+        }
+
+        for y in min.1..=max.1 {
+            if let Some(interval) = self.axis_interval {
+                let label = if y % interval == 0 {
+                    y.to_string()
+                } else {
+                    String::new()
+                };
+                write!(
+                    f,
+                    "{}",
+                    paint(format!("{:>width$} ", label, width = gutter - 1).bright_black())
+                )?;
+            }
+            for x in min.0..=max.0 {
+                if let Some(tile) = grid.get(x, y) {
+                    write!(f, "{}", paint(style.render_tile_char(tile, x, y)))?;
+                }
+            }
+            f.write_char('\n')?;
+        }
+
+        if self.show_legend {
+            let entries = style.legend();
+            if !entries.is_empty() {
+                f.write_str("legend:")?;
+                for (glyph, meaning) in entries {
+                    write!(f, "  {} {}", paint(glyph), meaning)?;
+                }
+                f.write_char('\n')?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+
+    fn char_grid(text: &str) -> Grid<char> {
+        Grid {
+            rows: text.lines().map(|line| line.chars().collect()).collect(),
+        }
+    }
+
+    /// The message `assert_grids_eq` panics with, or `None` if it doesn't panic
+    fn grids_eq_message(expected: &Grid<char>, actual: &Grid<char>) -> Option<String> {
+        let err = catch_unwind(|| assert_grids_eq(expected, actual, ())).err()?;
+        Some(err.downcast_ref::<String>().unwrap().clone())
+    }
+
+    #[test]
+    fn equal_grids_pass() {
+        let grid = char_grid("ab\ncd");
+        assert_eq!(grids_eq_message(&grid, &grid.clone()), None);
+    }
+
+    #[test]
+    fn changed_tile_is_marked() {
+        let message = grids_eq_message(&char_grid("ab\ncd"), &char_grid("ab\nxd")).unwrap();
+        assert!(message.starts_with("grids differ at 1 tile(s)"), "{}", message);
+        assert!(message.contains("cd        xd"), "{}", message);
+        assert!(!message.contains("ab"), "unchanged rows shouldn't be shown: {}", message);
+    }
+
+    #[test]
+    fn width_mismatch_shows_every_row() {
+        let message = grids_eq_message(&char_grid("ab\ncd"), &char_grid("abc\ncde")).unwrap();
+        assert!(message.contains("(expected 2x2, actual 3x2)"), "{}", message);
+        assert!(message.contains("ab        abc"), "{}", message);
+        assert!(message.contains("cd        cde"), "{}", message);
+    }
+
+    #[test]
+    fn height_mismatch_shows_extra_rows() {
+        let message = grids_eq_message(&char_grid("ab"), &char_grid("ab\ncd")).unwrap();
+        assert!(message.contains("(expected 2x1, actual 2x2)"), "{}", message);
+        assert!(message.contains("cd"), "{}", message);
+    }
+
+    /// A grid where each tile is a letter, counting up along both axes: `abc\nbcd\n...`
+    fn lettered_grid(width: usize, height: usize) -> Grid<char> {
+        Grid {
+            rows: (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| (b'a' + (x + y) as u8 % 26) as char)
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Shows `#` as a colored wall, with a legend
+    struct Walls;
+
+    impl RenderTileChar<char> for Walls {
+        fn render_tile_char(&self, tile: &char, _x: usize, _y: usize) -> ColoredString {
+            match tile {
+                '#' => "#".red(),
+                c => c.to_string().normal(),
+            }
+        }
+
+        fn legend(&self) -> Vec<(ColoredString, &'static str)> {
+            vec![("#".red(), "wall"), (".".normal(), "floor")]
+        }
+    }
+
+    #[test]
+    fn builder_without_options_matches_char_grid() {
+        let grid = char_grid("#.#\n.#.");
+        let built = CharGrid(&grid, Walls).builder().color(ColorMode::Never);
+        assert_eq!(built.to_string(), "#.#\n.#.\n");
+        assert!(!built.legend().to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn viewport_is_clamped_to_the_grid() {
+        let grid = lettered_grid(12, 12);
+        let render = |rect| {
+            CharGrid(&grid, ())
+                .builder()
+                .viewport(rect)
+                .color(ColorMode::Never)
+                .to_string()
+        };
+        assert_eq!(
+            render(GridRect::around(GridAddress(5, 5), 1)),
+            "ijk\njkl\nklm\n"
+        );
+        // cut off at the top left, where `around` saturates at 0
+        assert_eq!(
+            render(GridRect::around(GridAddress(0, 1), 2)),
+            "abc\nbcd\ncde\ndef\n"
+        );
+        // cut off at the bottom right
+        assert_eq!(render(GridRect::around(GridAddress(11, 11), 1)), "uv\nvw\n");
+        // entirely outside the grid
+        let outside = GridRect {
+            min: GridAddress(20, 0),
+            max: GridAddress(30, 5),
+        };
+        assert_eq!(render(outside), "");
+    }
+
+    #[test]
+    fn axis_labels_line_up_with_their_tiles() {
+        let grid = lettered_grid(12, 12);
+        let rendered = CharGrid(&grid, ())
+            .builder()
+            .axis_labels(5)
+            .color(ColorMode::Never)
+            .to_string();
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "   0    5    10");
+        assert_eq!(lines[1], " 0 abcdefghijkl");
+        assert_eq!(lines[2], "   bcdefghijklm");
+        assert_eq!(lines[6], " 5 fghijklmnopq");
+        assert_eq!(lines[11], "10 klmnopqrstuv");
+
+        // labels are for the grid's coordinates, not the viewport's, so rows 8 and 9 have none
+        let rendered = CharGrid(&grid, ())
+            .builder()
+            .viewport(GridRect::around(GridAddress(4, 9), 1))
+            .axis_labels(5)
+            .color(ColorMode::Never)
+            .to_string();
+        assert_eq!(rendered, "     5\n   lmn\n   mno\n10 nop\n");
+    }
+
+    #[test]
+    fn legend_follows_the_grid() {
+        let grid = char_grid("#.\n.#");
+        let rendered = CharGrid(&grid, Walls)
+            .builder()
+            .legend()
+            .color(ColorMode::Never)
+            .to_string();
+        assert_eq!(rendered, "#.\n.#\nlegend:  # wall  . floor\n");
+
+        // styles without a legend don't get an empty one
+        let rendered = CharGrid(&grid, ())
+            .builder()
+            .legend()
+            .color(ColorMode::Never)
+            .to_string();
+        assert_eq!(rendered, "#.\n.#\n");
+    }
+
+    #[test]
+    fn grid_rect_around_and_contains() {
+        let rect = GridRect::around(GridAddress(1, 4), 2);
+        assert_eq!(rect.min, GridAddress(0, 2));
+        assert_eq!(rect.max, GridAddress(3, 6));
+        assert!(rect.contains(GridAddress(0, 2)));
+        assert!(rect.contains(GridAddress(3, 6)));
+        assert!(!rect.contains(GridAddress(4, 4)));
+        assert!(!rect.contains(GridAddress(2, 1)));
+    }
+
+    #[test]
+    fn diff_includes_tiles_outside_the_overlap() {
+        let before = char_grid("ab\ncd");
+        assert!(before.diff(&before.clone()).is_empty());
+
+        let after = char_grid("ab\nxde\nf");
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                (GridAddress(0, 1), Some(&'c'), Some(&'x')),
+                (GridAddress(2, 1), None, Some(&'e')),
+                (GridAddress(0, 2), None, Some(&'f')),
+            ]
+        );
+        assert_eq!(
+            after.diff(&before)[1..],
+            [
+                (GridAddress(2, 1), Some(&'e'), None),
+                (GridAddress(0, 2), Some(&'f'), None),
+            ]
+        );
+    }
+}
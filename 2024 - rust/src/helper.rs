@@ -1,16 +1,14 @@
+pub use aoc_core::error::{parse_arg, AocError, GenError, GenResult, InputContext};
+pub use aoc_core::parse::ParseError;
 use log::{debug, info};
 use std::env;
-use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
-pub type GenError = Box<dyn Error>;
-pub type GenResult<A> = Result<A, GenError>;
-
 pub fn get_input(day: u32) -> GenResult<PathBuf> {
-    let mut parent = env::current_dir().unwrap();
+    let mut parent = env::current_dir()?;
     parent.push("inputs");
 
     if !fs::exists(&parent)? {
@@ -39,8 +37,9 @@ pub fn get_input(day: u32) -> GenResult<PathBuf> {
             .get(format!("https://adventofcode.com/2024/day/{}/input", day))
             .header("User-Agent", "@dylemma AdventOfCode 2024")
             .header("Cookie", format!("session={}", session_string))
-            .send()?
-            .copy_to(&mut input_file)?;
+            .send()
+            .and_then(|mut response| response.copy_to(&mut input_file))
+            .map_err(|err| AocError::Download(Box::new(err)))?;
         debug!("Downloaded {} bytes!", input_size);
         info!("Download complete!");
     }
@@ -21,21 +21,38 @@ mod puzzle18;
 mod puzzle19;
 mod puzzle20;
 mod puzzle21;
+mod viewer;
 
 use crate::helper::*;
 use env_logger::Builder;
 use log::{debug, error, info, LevelFilter};
 use std::env;
+use std::process::ExitCode;
 
-fn main() -> GenResult<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> GenResult<()> {
     let mut args = env::args().skip(1);
 
-    let puzzle_num = args.next().ok_or("Expected puzzle number")?.parse::<u32>().map_err(|err| {
-        format!("Expected a puzzle number, but got a parsing error: {}", err)
-    })?;
+    let puzzle_num = parse_arg::<u32>(
+        "puzzle number",
+        &args
+            .next()
+            .ok_or_else(|| AocError::Args("expected a puzzle number".to_string()))?,
+    )?;
 
     let mut log_level_filter = LevelFilter::Info;
     let mut is_example_input = false;
+    let mut use_viewer = false;
+    let mut puzzle_args = Vec::new();
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -48,7 +65,12 @@ fn main() -> GenResult<()> {
             "--example" => {
                 is_example_input = true;
             },
-            other => Err(format!("Unexpected argument: {}", other))?,
+            "--viewer" => {
+                use_viewer = true;
+            }
+            // non-flag arguments are passed along to the puzzle, e.g. to pick a tool to run
+            other if !other.starts_with("--") => puzzle_args.push(other.to_string()),
+            other => Err(AocError::Args(format!("unexpected argument: {}", other)))?,
         }
     }
 
@@ -68,30 +90,57 @@ fn main() -> GenResult<()> {
     };
     debug!("Input path: {:?}", puzzle_input_path);
 
-    match puzzle_num {
-        1 => puzzle01::run(&puzzle_input_path)?,
-        2 => puzzle02::run(&puzzle_input_path)?,
-        3 => puzzle03::run(&puzzle_input_path)?,
-        4 => puzzle04::run(&puzzle_input_path)?,
-        5 => puzzle05::run(&puzzle_input_path)?,
-        6 => puzzle06::run(&puzzle_input_path)?,
-        7 => puzzle07::run(&puzzle_input_path)?,
-        8 => puzzle08::run(&puzzle_input_path)?,
-        9 => puzzle09::run(&puzzle_input_path)?,
-        10 => puzzle10::run(&puzzle_input_path)?,
-        11 => puzzle11::run(&puzzle_input_path)?,
-        12 => puzzle12::run(&puzzle_input_path)?,
-        13 => puzzle13::run(&puzzle_input_path)?,
-        14 => puzzle14::run(&puzzle_input_path, is_example_input)?,
-        15 => puzzle15::run(&puzzle_input_path)?,
-        16 => puzzle16::run(&puzzle_input_path)?,
-        17 => puzzle17::run(&puzzle_input_path)?,
-        18 => puzzle18::run(&puzzle_input_path, is_example_input)?,
-        19 => puzzle19::run(&puzzle_input_path)?,
-        20 => puzzle20::run(&puzzle_input_path, is_example_input)?,
-        21 => puzzle21::run(&puzzle_input_path)?,
-        _ => error!("That puzzle isn't solved yet"),
+    if !puzzle_args.is_empty() && ![6, 7, 14, 15, 16, 17, 18, 19, 21].contains(&puzzle_num) {
+        Err(AocError::Args(format!(
+            "puzzle {} doesn't take any extra arguments",
+            puzzle_num
+        )))?;
     }
 
-    Ok(())
+    if use_viewer && ![6, 14, 15, 16, 18].contains(&puzzle_num) {
+        Err(AocError::Args(format!(
+            "puzzle {} doesn't have a viewer",
+            puzzle_num
+        )))?;
+    }
+
+    let result = match puzzle_num {
+        1 => puzzle01::run(&puzzle_input_path),
+        2 => puzzle02::run(&puzzle_input_path),
+        3 => puzzle03::run(&puzzle_input_path),
+        4 => puzzle04::run(&puzzle_input_path),
+        5 => puzzle05::run(&puzzle_input_path),
+        6 => puzzle06::run(&puzzle_input_path, use_viewer, &puzzle_args),
+        7 => puzzle07::run(&puzzle_input_path, &puzzle_args),
+        8 => puzzle08::run(&puzzle_input_path),
+        9 => puzzle09::run(&puzzle_input_path),
+        10 => puzzle10::run(&puzzle_input_path),
+        11 => puzzle11::run(&puzzle_input_path),
+        12 => puzzle12::run(&puzzle_input_path),
+        13 => puzzle13::run(&puzzle_input_path),
+        14 => puzzle14::run(
+            &puzzle_input_path,
+            is_example_input,
+            use_viewer,
+            &puzzle_args,
+        ),
+        15 => puzzle15::run(&puzzle_input_path, use_viewer, &puzzle_args),
+        16 => puzzle16::run(&puzzle_input_path, use_viewer, &puzzle_args),
+        17 => puzzle17::run(&puzzle_input_path, &puzzle_args),
+        18 => puzzle18::run(
+            &puzzle_input_path,
+            is_example_input,
+            use_viewer,
+            &puzzle_args,
+        ),
+        19 => puzzle19::run(&puzzle_input_path, &puzzle_args),
+        20 => puzzle20::run(&puzzle_input_path, is_example_input),
+        21 => puzzle21::run(&puzzle_input_path, &puzzle_args),
+        _ => {
+            error!("That puzzle isn't solved yet");
+            Ok(())
+        }
+    };
+
+    result.with_input_file(&puzzle_input_path)
 }
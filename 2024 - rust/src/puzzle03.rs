@@ -1,40 +1,22 @@
 use crate::helper::GenResult;
-use log::{debug, info, warn};
-use regex::{Captures, Regex};
+use log::{debug, info};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 
 pub fn run(input_path: &PathBuf) -> GenResult<()> {
-    // gobble the entire input to a String in memory
-    let input = {
-        let mut input_file = File::open(input_path)?;
-        let mut input = String::new();
-        input_file.read_to_string(&mut input)?;
-        input
-    };
-
-    // create an iterator over instructions in the input, using regex
-    let pattern = Instruction::pattern();
-    let instructions_itr = pattern.captures_iter(&input).map(|cap| {
-        Instruction::from_capture(&cap)
-            .ok_or_else(|| cap.iter().next().unwrap().unwrap().as_str().to_string())
-    });
-
-    // fold the instructions into both parts during a single iteration
+    // fold the instructions into both parts during a single pass over the input
     let mut part1_state = Part1Fold::new();
     let mut part2_state = Part2Fold::new();
-    for instruction in instructions_itr {
-        match instruction {
-            Err(bad_input) => {
-                warn!("Bad captured input: {}", bad_input);
-            }
-            Ok(instruction) => {
-                debug!("Instruction: {:?}", instruction);
-                part1_state.run(instruction);
-                part2_state.run(instruction);
-            }
-        }
+    let scanner = Scanner::new(BufReader::new(File::open(input_path)?), INSTRUCTION_KINDS);
+    for found in scanner {
+        let found = found?;
+        debug!(
+            "Instruction at byte {}: {:?}",
+            found.offset, found.instruction
+        );
+        part1_state.run(found.instruction);
+        part2_state.run(found.instruction);
     }
 
     // log results
@@ -44,37 +26,186 @@ pub fn run(input_path: &PathBuf) -> GenResult<()> {
     Ok(())
 }
 
-#[derive(Debug, Copy, Clone)]
+/// How much of the input the scanner reads at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Instruction {
     Mul(u32, u32),
     Deactivate,
     Activate,
 }
 
-static INSTRUCTION_PATTERN: &str = "mul\\((\\d+),(\\d+)\\)|do\\(\\)|don't\\(\\)";
+/// An instruction found by the `Scanner`, along with where it was in the input
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Scanned {
+    /// Byte offset of the start of the instruction
+    offset: u64,
+    /// Length of the instruction's text, in bytes
+    len: usize,
+    instruction: Instruction,
+}
+
+/// One kind of instruction the `Scanner` can recognize.
+/// Adding a new kind of instruction means implementing this and adding it to `INSTRUCTION_KINDS`.
+trait InstructionKind: Sync {
+    /// The longest text an instruction of this kind can take up, in bytes
+    fn max_len(&self) -> usize;
+
+    /// Read an instruction from the start of `text`, returning it along with how many bytes it took up.
+    /// `text` is at least `max_len` bytes long, unless it's the end of the input.
+    fn parse(&self, text: &[u8]) -> Option<(Instruction, usize)>;
+}
+
+/// `mul(a,b)`, where `a` and `b` have 1 to 3 digits
+struct MulKind;
+
+impl InstructionKind for MulKind {
+    fn max_len(&self) -> usize {
+        "mul(999,999)".len()
+    }
+
+    fn parse(&self, text: &[u8]) -> Option<(Instruction, usize)> {
+        let rest = text.strip_prefix(b"mul(")?;
+        let (a, rest) = parse_operand(rest)?;
+        let rest = rest.strip_prefix(b",")?;
+        let (b, rest) = parse_operand(rest)?;
+        rest.strip_prefix(b")")?;
+        let len = text.len() - rest.len() + 1;
+        Some((Instruction::Mul(a, b), len))
+    }
+}
+
+/// Parse a 1 to 3 digit number from the start of `text`, returning it along with the rest of the text
+fn parse_operand(text: &[u8]) -> Option<(u32, &[u8])> {
+    let digits = text
+        .iter()
+        .take(3)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    if digits == 0 {
+        return None;
+    }
+    let value = text[..digits]
+        .iter()
+        .fold(0, |n, b| n * 10 + u32::from(b - b'0'));
+    Some((value, &text[digits..]))
+}
+
+/// An instruction that's always written the same way, like `do()`
+struct KeywordKind {
+    keyword: &'static str,
+    instruction: Instruction,
+}
+
+impl InstructionKind for KeywordKind {
+    fn max_len(&self) -> usize {
+        self.keyword.len()
+    }
+
+    fn parse(&self, text: &[u8]) -> Option<(Instruction, usize)> {
+        text.starts_with(self.keyword.as_bytes())
+            .then_some((self.instruction, self.keyword.len()))
+    }
+}
+
+/// The kinds of instruction to look for, in order of priority where they could overlap
+static INSTRUCTION_KINDS: &[&dyn InstructionKind] = &[
+    &MulKind,
+    &KeywordKind {
+        keyword: "do()",
+        instruction: Instruction::Activate,
+    },
+    &KeywordKind {
+        keyword: "don't()",
+        instruction: Instruction::Deactivate,
+    },
+];
+
+/// Finds instructions in a stream of corrupted memory, one byte position at a time.
+/// Only a chunk of the input (plus at most one instruction's worth of leftovers) is held in
+/// memory at once, so inputs of any size can be scanned.
+///
+/// Like a regex search, the first kind of instruction that matches at a position wins, and the
+/// search resumes after the end of the instruction, so instructions never overlap.
+struct Scanner<R> {
+    reader: R,
+    kinds: &'static [&'static dyn InstructionKind],
+    /// The longest instruction any of the `kinds` can match
+    max_len: usize,
+    buf: Vec<u8>,
+    /// Position in `buf` of the next byte to scan
+    pos: usize,
+    /// Byte offset in the input of the start of `buf`
+    buf_offset: u64,
+    eof: bool,
+}
 
-impl Instruction {
-    fn pattern() -> Regex {
-        Regex::new(INSTRUCTION_PATTERN).unwrap()
+impl<R: BufRead> Scanner<R> {
+    fn new(reader: R, kinds: &'static [&'static dyn InstructionKind]) -> Self {
+        Scanner {
+            reader,
+            kinds,
+            max_len: kinds.iter().map(|kind| kind.max_len()).max().unwrap_or(0),
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            pos: 0,
+            buf_offset: 0,
+            eof: false,
+        }
     }
 
-    fn from_capture(capture: &Captures) -> Option<Instruction> {
-        let mut itr = capture.iter();
-        let whole_match = itr.next().unwrap().unwrap().as_str();
-        match whole_match {
-            "do()" => Some(Instruction::Activate),
-            "don't()" => Some(Instruction::Deactivate),
-            _ => {
-                let a = itr.next()??.as_str().parse::<u32>().ok()?;
-                let b = itr.next()??.as_str().parse::<u32>().ok()?;
-                Some(Instruction::Mul(a, b))
+    /// Make sure there are at least `max_len` bytes left to scan in `buf`, unless the input runs out first
+    fn refill(&mut self) -> std::io::Result<()> {
+        if self.buf.len() - self.pos >= self.max_len || self.eof {
+            return Ok(());
+        }
+        // drop the bytes that have already been scanned
+        self.buf.drain(..self.pos);
+        self.buf_offset += self.pos as u64;
+        self.pos = 0;
+
+        while self.buf.len() < CHUNK_SIZE && !self.eof {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                self.eof = true;
+            } else {
+                let n = available.len().min(CHUNK_SIZE - self.buf.len());
+                self.buf.extend_from_slice(&available[..n]);
+                self.reader.consume(n);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Scanner<R> {
+    type Item = std::io::Result<Scanned>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(e) = self.refill() {
+                return Some(Err(e));
+            }
+            let text = self.buf.get(self.pos..).filter(|text| !text.is_empty())?;
+            let found = self.kinds.iter().find_map(|kind| kind.parse(text));
+            let offset = self.buf_offset + self.pos as u64;
+            match found {
+                Some((instruction, len)) => {
+                    self.pos += len;
+                    return Some(Ok(Scanned {
+                        offset,
+                        len,
+                        instruction,
+                    }));
+                }
+                None => self.pos += 1,
             }
         }
     }
 }
 
 struct Part1Fold {
-    accum: u32,
+    accum: u64,
 }
 
 impl Part1Fold {
@@ -86,7 +217,7 @@ impl Part1Fold {
         match instruction {
             Instruction::Mul(a, b) => {
                 debug!("  part1: add {} * {}", a, b);
-                self.accum += a * b;
+                self.accum += u64::from(a * b);
             }
             Instruction::Activate => (),
             Instruction::Deactivate => (),
@@ -95,7 +226,7 @@ impl Part1Fold {
 }
 
 struct Part2Fold {
-    accum: u32,
+    accum: u64,
     active: bool,
 }
 
@@ -112,7 +243,7 @@ impl Part2Fold {
             Instruction::Mul(a, b) => {
                 if self.active {
                     debug!("  part2: add {} * {}", a, b);
-                    self.accum += a * b;
+                    self.accum += u64::from(a * b);
                 } else {
                     debug!("  part2: noop");
                 }
@@ -128,3 +259,154 @@ impl Part2Fold {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::rng::XorShift;
+    use regex::Regex;
+    use std::io::Read;
+
+    /// Equivalent to `INSTRUCTION_KINDS`, with operands limited to 1 to 3 ASCII digits
+    static INSTRUCTION_PATTERN: &str = "mul\\(([0-9]{1,3}),([0-9]{1,3})\\)|do\\(\\)|don't\\(\\)";
+
+    /// The original regex-based scan, which the `Scanner` should always agree with
+    fn regex_scan(input: &str) -> Vec<Scanned> {
+        let pattern = Regex::new(INSTRUCTION_PATTERN).unwrap();
+        pattern
+            .captures_iter(input)
+            .map(|cap| {
+                let whole_match = cap.get(0).unwrap();
+                let instruction = match whole_match.as_str() {
+                    "do()" => Instruction::Activate,
+                    "don't()" => Instruction::Deactivate,
+                    _ => Instruction::Mul(cap[1].parse().unwrap(), cap[2].parse().unwrap()),
+                };
+                Scanned {
+                    offset: whole_match.start() as u64,
+                    len: whole_match.len(),
+                    instruction,
+                }
+            })
+            .collect()
+    }
+
+    /// Hands out the input a few bytes at a time, so the scanner has to refill often
+    struct TinyChunks<'a> {
+        data: &'a [u8],
+        chunk_size: usize,
+    }
+
+    impl Read for TinyChunks<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.fill_buf()?.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for TinyChunks<'_> {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            Ok(&self.data[..self.chunk_size.min(self.data.len())])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.data = &self.data[amt..];
+        }
+    }
+
+    fn scan(input: &str, chunk_size: usize) -> Vec<Scanned> {
+        let reader = TinyChunks {
+            data: input.as_bytes(),
+            chunk_size,
+        };
+        Scanner::new(reader, INSTRUCTION_KINDS)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn example_memory() {
+        let input = std::fs::read_to_string("example_inputs/3.txt").unwrap();
+        let mut part1_state = Part1Fold::new();
+        let mut part2_state = Part2Fold::new();
+        for found in scan(&input, 7) {
+            part1_state.run(found.instruction);
+            part2_state.run(found.instruction);
+        }
+        assert_eq!(part1_state.accum, 161);
+        assert_eq!(part2_state.accum, 48);
+    }
+
+    #[test]
+    fn scanner_matches_regex_on_random_memory() {
+        // pieces that make near-misses likely, like `mul(1234,5)` or `do(don't()`
+        let pieces = [
+            "mul(", "mul", "do()", "don't()", "do(", "don't", "(", ")", ",", "1", "23", "456",
+            "7890", "m", "x", " ", "é", "\n",
+        ];
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        let mut total_found = 0;
+        for _ in 0..300 {
+            let len = rng.below(60);
+            let input = (0..len)
+                .map(|_| pieces[rng.below(pieces.len())])
+                .collect::<String>();
+            let expected = regex_scan(&input);
+            for chunk_size in [1, 2, 3, 5, 13] {
+                assert_eq!(
+                    scan(&input, chunk_size),
+                    expected,
+                    "{:?} read {} bytes at a time",
+                    input,
+                    chunk_size
+                );
+            }
+            total_found += expected.len();
+        }
+        assert!(total_found > 300, "only {} instructions found", total_found);
+    }
+
+    #[test]
+    fn instructions_straddling_a_refill_are_found() {
+        let max_len = INSTRUCTION_KINDS
+            .iter()
+            .map(|kind| kind.max_len())
+            .max()
+            .unwrap();
+        for shift in 0..=max_len + 1 {
+            // lands an instruction on every position around the end of the first chunk
+            let input = format!(
+                "{}mul(123,456)do()xmul(7,8)don't()mul(9,10)",
+                "x".repeat(CHUNK_SIZE - shift)
+            );
+            let expected = regex_scan(&input);
+            assert_eq!(expected.len(), 5);
+            for chunk_size in [1, 7, CHUNK_SIZE] {
+                assert_eq!(
+                    scan(&input, chunk_size),
+                    expected,
+                    "shifted {} bytes, read {} bytes at a time",
+                    shift,
+                    chunk_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn long_random_memory_crosses_several_chunks() {
+        let mut rng = XorShift::new(0x94d0_49bb_1331_11eb);
+        let pieces = ["mul(", "12", "3", ",", ")", "do()", "don't()", "xy", "mu"];
+        let input = (0..3 * CHUNK_SIZE / 2)
+            .map(|_| pieces[rng.below(pieces.len())])
+            .collect::<String>();
+        assert!(input.len() > 3 * CHUNK_SIZE);
+        let expected = regex_scan(&input);
+        assert!(expected.len() > 1000);
+        for chunk_size in [3, 4096] {
+            assert_eq!(scan(&input, chunk_size), expected);
+        }
+    }
+}
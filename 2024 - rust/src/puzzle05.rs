@@ -1,12 +1,10 @@
-use std::collections::{HashMap, HashSet};
-use crate::helper::GenResult;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::helper::{AocError, GenError, GenResult, InputContext};
 use colored::Colorize;
 use log::{debug, info};
-use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Write};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::mem::swap;
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -21,8 +19,29 @@ pub fn run(input_path: &PathBuf) -> GenResult<()> {
         info!("{:?}", classified);
         sum_of_valid += classified.value();
         if !classified.is_valid {
-            let fixed = fix_order(&update, &rules);
-            let mut reclassified = classify(&fixed, &rules);
+            for rule in rules.explain_violation(&update) {
+                debug!("  breaks rule: {}", rule);
+            }
+            let fixed = rules.fix(&update)?;
+            if !rules.is_consistent(&fixed.update) {
+                Err(AocError::Logic(format!(
+                    "fixed update {:?} is still out of order",
+                    fixed.update
+                )))?
+            }
+            if !fixed.ambiguities.is_empty() {
+                info!(
+                    " {} the rules don't decide the order of {}",
+                    "warning:".yellow(),
+                    fixed
+                        .ambiguities
+                        .iter()
+                        .map(|(a, b)| format!("{} and {}", a, b))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            let mut reclassified = classify(&fixed.update, &rules);
             reclassified.was_fixed = true;
             info!(" fixed: {:?}", reclassified);
             sum_of_fixed += reclassified.value();
@@ -43,20 +62,23 @@ fn parse_input(input_path: &PathBuf) -> GenResult<(Vec<OrderingRule>, Vec<Update
     let mut is_parsing_rules = true;
     let mut rules = Vec::new();
     let mut pages = Vec::new();
-    for line in reader.lines() {
+    for (line_index, line) in reader.lines().enumerate() {
         let line = line?;
         if is_parsing_rules && !line.is_empty() {
-            let rule = line.parse::<OrderingRule>()?;
+            let rule = line
+                .parse::<OrderingRule>()
+                .at_input_line(line_index, &line)?;
             debug!("{}", rule);
             rules.push(rule);
         } else if is_parsing_rules && line.is_empty() {
             debug!("switch to parsing pages...");
             is_parsing_rules = false;
         } else {
-            let mut p = Vec::new();
-            for s in line.split_terminator(',') {
-                p.push(s.parse::<u32>()?);
-            }
+            let p = line
+                .split_terminator(',')
+                .map(|s| Ok(s.parse::<u32>()?))
+                .collect::<GenResult<Vec<_>>>()
+                .at_input_line(line_index, &line)?;
             debug!("pages: {:?}", p);
             pages.push(Update(p));
         }
@@ -66,7 +88,7 @@ fn parse_input(input_path: &PathBuf) -> GenResult<(Vec<OrderingRule>, Vec<Update
 
 /// An ordering rule from the puzzle input.
 /// E.g. `12|75` would be `OrderingRule { prefix: 12, suffix: 75 }`
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 struct OrderingRule {
     prefix: u32,
     suffix: u32,
@@ -77,7 +99,7 @@ impl Display for OrderingRule {
     }
 }
 impl FromStr for OrderingRule {
-    type Err = Box<dyn Error>;
+    type Err = GenError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (l, r) = s.split_once('|').ok_or("missing '|'")?;
@@ -117,11 +139,137 @@ impl OrderingRules {
         }
     }
 
-    fn is_legal(&self, prefix: u32, suffix: u32) -> bool {
-        self.has_rule(prefix, suffix) || !self.has_rule(suffix, prefix)
+    /// The rules that mention two pages from the update, as pairs of indexes into the update
+    fn rules_within(&self, update: &Update) -> Vec<(usize, usize)> {
+        let pages = &update.0;
+        let mut out = Vec::new();
+        for (i, prefix) in pages.iter().enumerate() {
+            for (j, suffix) in pages.iter().enumerate() {
+                if i != j && self.has_rule(*prefix, *suffix) {
+                    out.push((i, j));
+                }
+            }
+        }
+        out
+    }
+
+    /// Whether the update's pages are already in an order that satisfies every rule
+    fn is_consistent(&self, update: &Update) -> bool {
+        self.explain_violation(update).is_empty()
+    }
+
+    /// The rules broken by the update, i.e. the ones whose suffix page comes before its prefix page
+    fn explain_violation(&self, update: &Update) -> Vec<OrderingRule> {
+        self.rules_within(update)
+            .into_iter()
+            .filter(|(i, j)| j < i)
+            .map(|(i, j)| OrderingRule {
+                prefix: update.0[i],
+                suffix: update.0[j],
+            })
+            .collect()
+    }
+
+    /// Put the update's pages in an order that satisfies every rule between them, via a
+    /// topological sort (Kahn's algorithm) of the rules restricted to those pages.
+    /// When several pages could go next, the one that came first in the update is picked,
+    /// so pages are only moved when some rule requires it.
+    ///
+    /// Fails if the rules between the pages form a cycle, since no order can satisfy them.
+    fn fix(&self, update: &Update) -> GenResult<SortedUpdate> {
+        let pages = &update.0;
+        let edges = self.rules_within(update);
+        let mut successors = vec![Vec::new(); pages.len()];
+        let mut in_degree = vec![0usize; pages.len()];
+        for (i, j) in &edges {
+            successors[*i].push(*j);
+            in_degree[*j] += 1;
+        }
+
+        let mut ready = (0..pages.len())
+            .filter(|i| in_degree[*i] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(pages.len());
+        while let Some(i) = ready.pop_first() {
+            order.push(i);
+            for j in &successors[i] {
+                in_degree[*j] -= 1;
+                if in_degree[*j] == 0 {
+                    ready.insert(*j);
+                }
+            }
+        }
+
+        if order.len() < pages.len() {
+            let cycle = find_cycle(pages, &edges, &in_degree);
+            Err(format!(
+                "can't fix {:?}, the rules form a cycle: {}",
+                update,
+                cycle
+                    .iter()
+                    .map(|rule| rule.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))?;
+        }
+
+        let sorted = order.into_iter().map(|i| pages[i]).collect::<Vec<_>>();
+        // a topological order is the only one possible iff every neighboring pair is ordered by a rule
+        let ambiguities = sorted
+            .windows(2)
+            .filter(|pair| !self.has_rule(pair[0], pair[1]))
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        Ok(SortedUpdate {
+            update: Update(sorted),
+            ambiguities,
+        })
     }
 }
 
+/// Find a cycle among the pages that a topological sort couldn't place,
+/// i.e. those still left with a nonzero `in_degree`.
+/// Each of them has a predecessor that's also stuck, so walking backwards
+/// from any of them must eventually revisit a page.
+fn find_cycle(pages: &[u32], edges: &[(usize, usize)], in_degree: &[usize]) -> Vec<OrderingRule> {
+    let stuck_predecessor = |j: usize| {
+        edges
+            .iter()
+            .find(|(i, to)| *to == j && in_degree[*i] > 0)
+            .map(|(i, _)| *i)
+            .expect("stuck pages always have a stuck predecessor")
+    };
+
+    let start = (0..pages.len())
+        .find(|i| in_degree[*i] > 0)
+        .expect("no stuck pages");
+    let mut walk = vec![start];
+    let mut current = stuck_predecessor(start);
+    while !walk.contains(&current) {
+        walk.push(current);
+        current = stuck_predecessor(current);
+    }
+
+    // the walk went against the direction of the rules, so reverse it
+    let mut cycle = walk[walk.iter().position(|i| *i == current).unwrap()..].to_vec();
+    cycle.reverse();
+    cycle
+        .iter()
+        .zip(cycle.iter().cycle().skip(1))
+        .map(|(i, j)| OrderingRule {
+            prefix: pages[*i],
+            suffix: pages[*j],
+        })
+        .collect()
+}
+
+/// An update put in order by `OrderingRules::fix`
+struct SortedUpdate {
+    update: Update,
+    /// Neighboring pages that no rule puts in order, so they could have gone either way
+    ambiguities: Vec<(u32, u32)>,
+}
+
 /// A list of "page numbers" from the puzzle input
 #[derive(Debug)]
 struct Update(Vec<u32>);
@@ -189,7 +337,7 @@ impl Debug for ClassifiedUpdate {
 }
 
 /// Part 1 helper:
-/// Inspects pairs of "pages" (numbers) in the update. If any pair is "illegal",
+/// Checks the update against the rules. If any rule is broken,
 /// the overall result will have `is_valid: false`. The individual violations will
 /// also be marked, so we can have pretty debug output.
 fn classify(update: &Update, rules: &OrderingRules) -> ClassifiedUpdate {
@@ -197,53 +345,129 @@ fn classify(update: &Update, rules: &OrderingRules) -> ClassifiedUpdate {
         .map(|num| ClassifiedPage { num: *num, is_violation: false })
         .collect::<Vec<_>>();
 
-    // run the compliance check, marking `is_correct = false` on pages violating the order
-    for i in 0..update.0.len() {
-        let prefix = update.0[i];
-        for j in (i+1)..update.0.len() {
-            let suffix = update.0[j];
-            if !rules.is_legal(prefix, suffix) {
-                classified_pages[i].is_violation = true;
-                classified_pages[j].is_violation = true;
+    // mark the pages involved in any broken rule
+    let violations = rules.explain_violation(update);
+    for rule in &violations {
+        for page in classified_pages.iter_mut() {
+            if page.num == rule.prefix || page.num == rule.suffix {
+                page.is_violation = true;
             }
         }
     }
 
-    let is_valid = classified_pages.iter().all(|page| !page.is_violation);
-
     ClassifiedUpdate {
         pages: classified_pages,
-        is_valid,
+        is_valid: violations.is_empty(),
         was_fixed: false,
     }
 }
 
-/// Part 2 helper:
-/// Works as a kind of bubble sort. Visit each pair of values in the update,
-/// swapping them if they are not legal according to the `rules`. Whenever a
-/// swap occurs, re-inspect pairs after the swapped index, since a swap may
-/// put a number in front of another number that it was supposed to be behind.
-fn fix_order(update: &Update, rules: &OrderingRules) -> Update {
-    debug!("Attempting to fix {:?}", update);
-    let mut fixed_pages = update.0.clone();
-
-    for i in 0..fixed_pages.len() {
-        debug!("at index {}", i);
-        let mut did_swap = true;
-        let (fixed_so_far, to_be_fixed) = fixed_pages.split_at_mut(i + 1);
-        while did_swap {
-            did_swap = false;
-            let prefix = fixed_so_far.last_mut().unwrap();
-            for suffix in to_be_fixed.iter_mut() {
-                if !rules.is_legal(*prefix, *suffix) {
-                    debug!("swap {} and {}", prefix, suffix);
-                    swap(prefix, suffix);
-                    did_swap = true;
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(pairs: &[(u32, u32)]) -> OrderingRules {
+        OrderingRules::new(
+            pairs
+                .iter()
+                .map(|&(prefix, suffix)| OrderingRule { prefix, suffix })
+                .collect(),
+        )
+    }
+
+    fn rule_pairs(rules: &[OrderingRule]) -> Vec<(u32, u32)> {
+        rules
+            .iter()
+            .map(|rule| (rule.prefix, rule.suffix))
+            .collect()
+    }
+
+    #[test]
+    fn example_sums() {
+        let (rules, updates) = parse_input(&PathBuf::from("example_inputs/5.txt")).unwrap();
+        let rules = OrderingRules::new(rules);
+        let mut sum_of_valid = 0;
+        let mut sum_of_fixed = 0;
+        for update in &updates {
+            let classified = classify(update, &rules);
+            sum_of_valid += classified.value();
+            if !classified.is_valid {
+                let fixed = rules.fix(update).unwrap();
+                assert!(rules.is_consistent(&fixed.update), "{:?}", fixed.update);
+                // the example's rules compare every pair of pages, so there's only one right order
+                assert!(fixed.ambiguities.is_empty(), "{:?}", fixed.ambiguities);
+                sum_of_fixed += classify(&fixed.update, &rules).value();
             }
         }
-        debug!("settled on {}", fixed_so_far.last().unwrap());
+        assert_eq!(sum_of_valid, 143);
+        assert_eq!(sum_of_fixed, 123);
     }
 
-    Update(fixed_pages)
-}
\ No newline at end of file
+    #[test]
+    fn explain_violation_names_the_broken_rules() {
+        let (rules, _) = parse_input(&PathBuf::from("example_inputs/5.txt")).unwrap();
+        let rules = OrderingRules::new(rules);
+        let broken = |pages: &[u32]| rule_pairs(&rules.explain_violation(&Update(pages.to_vec())));
+
+        assert_eq!(broken(&[75, 47, 61, 53, 29]), []);
+        assert_eq!(broken(&[75, 97, 47, 61, 53]), [(97, 75)]);
+        assert_eq!(broken(&[61, 13, 29]), [(29, 13)]);
+        assert_eq!(
+            broken(&[97, 13, 75, 29, 47]),
+            [(75, 13), (29, 13), (47, 13), (47, 29)]
+        );
+    }
+
+    #[test]
+    fn fix_reports_the_cycle() {
+        let rules = rules(&[(1, 2), (2, 3), (3, 1), (3, 4)]);
+        let err = rules.fix(&Update(vec![4, 1, 2, 3])).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "can't fix Update([4, 1, 2, 3]), the rules form a cycle: \
+             1 must be before 2, 2 must be before 3, 3 must be before 1"
+        );
+        // with one of the cycle's pages missing, the rest can be put in order
+        let fixed = rules.fix(&Update(vec![4, 2, 3])).unwrap();
+        assert_eq!(fixed.update.0, [2, 3, 4]);
+    }
+
+    #[test]
+    fn find_cycle_lists_the_rules_in_cycle_order() {
+        // page 4 is stuck behind the cycle, but isn't part of it
+        let pages = [4, 2, 3, 5];
+        let edges = [(1, 2), (2, 3), (3, 1), (2, 0)];
+        let in_degree = [1, 1, 1, 1];
+        let cycle = find_cycle(&pages, &edges, &in_degree);
+        assert_eq!(rule_pairs(&cycle), [(5, 2), (2, 3), (3, 5)]);
+
+        // two pages that must each come before the other
+        let cycle = find_cycle(&[7, 8], &[(0, 1), (1, 0)], &[1, 1]);
+        assert_eq!(rule_pairs(&cycle), [(8, 7), (7, 8)]);
+    }
+
+    #[test]
+    fn ambiguities_are_neighbors_without_a_rule() {
+        let rules = rules(&[(1, 2), (2, 3)]);
+
+        // the rules chain every page together, so the order is forced
+        let fixed = rules.fix(&Update(vec![3, 2, 1])).unwrap();
+        assert_eq!(fixed.update.0, [1, 2, 3]);
+        assert_eq!(fixed.ambiguities, []);
+
+        // nothing says where 9 goes, so it stays first
+        let fixed = rules.fix(&Update(vec![9, 2, 1])).unwrap();
+        assert_eq!(fixed.update.0, [9, 1, 2]);
+        assert_eq!(fixed.ambiguities, [(9, 1)]);
+
+        // rules only count between pages in the update, so without 2, 1 and 3 can go either way
+        let fixed = rules.fix(&Update(vec![3, 1])).unwrap();
+        assert_eq!(fixed.update.0, [3, 1]);
+        assert_eq!(fixed.ambiguities, [(3, 1)]);
+
+        // no rules at all leaves the update alone, with every neighboring pair ambiguous
+        let fixed = rules.fix(&Update(vec![5, 4, 6])).unwrap();
+        assert_eq!(fixed.update.0, [5, 4, 6]);
+        assert_eq!(fixed.ambiguities, [(5, 4), (4, 6)]);
+    }
+}
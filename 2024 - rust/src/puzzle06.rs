@@ -1,14 +1,26 @@
-use crate::geometry::{Cardinal, CardinalSet, CharGrid, Grid, RenderTileChar};
-use crate::helper::GenResult;
+use crate::geometry::{Cardinal, CardinalSet, CharGrid, DiffCharGrid, Grid, RenderTileChar};
+use crate::helper::{AocError, GenResult, ParseError};
+use crate::viewer::{run_viewer, Simulation};
 use colored::{ColoredString, Colorize};
-use log::{debug, info};
+use log::{debug, info, log_enabled, Level};
+use rayon::prelude::*;
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
+use std::time::Instant;
+
+pub fn run(input_path: &PathBuf, use_viewer: bool, args: &[String]) -> GenResult<()> {
+    let bench = match args {
+        [] => false,
+        [arg] if arg == "bench" => true,
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 6: {:?}",
+            args
+        )))?,
+    };
 
-pub fn run(input_path: &PathBuf) -> GenResult<()> {
     let (initial_grid, initial_guard) = {
         let file = File::open(input_path)?;
         let mut reader = BufReader::new(file);
@@ -21,6 +33,10 @@ pub fn run(input_path: &PathBuf) -> GenResult<()> {
         CharGrid(&initial_grid, WithGuard(Some(initial_guard)))
     );
 
+    if use_viewer {
+        run_viewer(PatrolState::new(initial_grid.clone(), initial_guard))?;
+    }
+
     // Run the patrol with the state as given
     let (part1_state, part1_result) = run_patrol(initial_grid.clone(), initial_guard.clone());
     {
@@ -36,40 +52,210 @@ pub fn run(input_path: &PathBuf) -> GenResult<()> {
         );
     }
 
-    let mut num_loops = 0;
-    let mut num_tested = 0;
-    // for any tile along the path traversed in part 1, see if inserting an obstacle on that type would induce a cycle
-    for x in 0..initial_grid.width() {
-        for y in 0..initial_grid.height() {
-            if (x, y) != initial_guard.pos {
-                if part1_state.grid.get(x, y).unwrap().is_traversed() {
-                    num_tested += 1;
-                    let altered_grid = {
-                        let mut g = initial_grid.clone();
-                        let tile = g.get_mut(x, y).unwrap();
-                        *tile = TileState::ArtificialObstacle;
-                        g
-                    };
-                    let (patrol_state, result) = run_patrol(altered_grid, initial_guard.clone());
-                    match result {
-                        PatrolResult::LoopDetected => {
-                            // I want log output, but not log spam
-                            if num_loops == 0 {
-                                info!("First loop detected in \n{}", CharGrid(&patrol_state.grid, WithGuard(patrol_state.guard)));
-                            } else {
-                                debug!("Loop detected in:\n{}", CharGrid(&patrol_state.grid, WithGuard(patrol_state.guard)));
-                            }
-                            num_loops += 1;
-                        },
-                        _ => (),
+    // candidates for the new obstacle are the tiles the guard walks through in part 1,
+    // since placing it anywhere else wouldn't change their route
+    let candidates = traversed_positions(&part1_state.grid, initial_guard);
+
+    if bench {
+        benchmark(&initial_grid, initial_guard, &candidates);
+        return Ok(());
+    }
+
+    let table = JumpTable::new(&initial_grid);
+    let loop_positions = candidates
+        .par_iter()
+        .filter(|obstacle| table.creates_loop(initial_guard, **obstacle))
+        .copied()
+        .collect::<Vec<_>>();
+
+    // replay the loops in full, just for the log output (tiles that differ from the part 1 patrol are highlighted)
+    for (i, (x, y)) in loop_positions.iter().enumerate() {
+        if i > 0 && !log_enabled!(Level::Debug) {
+            break;
+        }
+        let (patrol_state, _) = run_patrol(with_obstacle(&initial_grid, *x, *y), initial_guard);
+        let diff = DiffCharGrid(
+            &part1_state.grid,
+            &patrol_state.grid,
+            WithGuard(patrol_state.guard),
+        );
+        if i == 0 {
+            info!("First loop detected in \n{}", diff);
+        } else {
+            debug!("Loop detected in:\n{}", diff);
+        }
+    }
+    info!(
+        "Detected {} loop-inducing positions (out of {} attempts)",
+        format!("{}", loop_positions.len()).cyan(),
+        candidates.len()
+    );
+
+    Ok(())
+}
+
+/// Tiles traversed in the given grid, except for the guard's starting position, in column-major order
+fn traversed_positions(grid: &Grid<TileState>, initial_guard: Guard) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    for x in 0..grid.width() {
+        for y in 0..grid.height() {
+            if (x, y) != initial_guard.pos && grid.get(x, y).unwrap().is_traversed() {
+                out.push((x, y));
+            }
+        }
+    }
+    out
+}
+
+fn with_obstacle(grid: &Grid<TileState>, x: usize, y: usize) -> Grid<TileState> {
+    let mut g = grid.clone();
+    *g.get_mut(x, y).unwrap() = TileState::ArtificialObstacle;
+    g
+}
+
+/// The original part 2 approach: replay the whole patrol on a modified copy of the grid for each candidate
+fn count_loops_by_replay(
+    initial_grid: &Grid<TileState>,
+    initial_guard: Guard,
+    candidates: &[(usize, usize)],
+) -> usize {
+    candidates
+        .iter()
+        .filter(|(x, y)| {
+            let (_, result) = run_patrol(with_obstacle(initial_grid, *x, *y), initial_guard);
+            matches!(result, PatrolResult::LoopDetected)
+        })
+        .count()
+}
+
+/// Compare the replay approach with the jump table approach (both with and without parallelism)
+fn benchmark(initial_grid: &Grid<TileState>, initial_guard: Guard, candidates: &[(usize, usize)]) {
+    let start = Instant::now();
+    let replay_count = count_loops_by_replay(initial_grid, initial_guard, candidates);
+    let replay_time = start.elapsed();
+    info!("Replay: {} loops in {:?}", replay_count, replay_time);
+
+    let start = Instant::now();
+    let table = JumpTable::new(initial_grid);
+    let table_time = start.elapsed();
+    let serial_count = candidates
+        .iter()
+        .filter(|obstacle| table.creates_loop(initial_guard, **obstacle))
+        .count();
+    let serial_time = start.elapsed();
+    info!(
+        "Jump table: {} loops in {:?} (of which {:?} building the table), {:.1}x faster",
+        serial_count,
+        serial_time,
+        table_time,
+        replay_time.as_secs_f64() / serial_time.as_secs_f64()
+    );
+
+    let start = Instant::now();
+    let table = JumpTable::new(initial_grid);
+    let parallel_count = candidates
+        .par_iter()
+        .filter(|obstacle| table.creates_loop(initial_guard, **obstacle))
+        .count();
+    let parallel_time = start.elapsed();
+    info!(
+        "Parallel jump table: {} loops in {:?}, {:.1}x faster",
+        parallel_count,
+        parallel_time,
+        replay_time.as_secs_f64() / parallel_time.as_secs_f64()
+    );
+}
+
+/// For each tile and heading, where a guard walking that way from that tile will stop:
+/// the last tile before the next obstacle, or `None` if they walk off the map first.
+///
+/// This lets a patrol be simulated one turn at a time rather than one step at a time.
+/// An extra obstacle isn't in the table, so `creates_loop` checks for it separately.
+struct JumpTable {
+    width: usize,
+    stops: Vec<[Option<(usize, usize)>; 4]>,
+}
+
+impl JumpTable {
+    fn new(grid: &Grid<TileState>) -> Self {
+        let (width, height) = (grid.width(), grid.height());
+        let mut stops = vec![[None; 4]; width * height];
+        for heading in Cardinal::ALL {
+            let (dx, dy): (isize, isize) = heading.into();
+            // visit tiles such that the tile ahead of each one is visited before it
+            let xs = if dx > 0 {
+                (0..width).rev().collect::<Vec<_>>()
+            } else {
+                (0..width).collect()
+            };
+            let ys = if dy > 0 {
+                (0..height).rev().collect::<Vec<_>>()
+            } else {
+                (0..height).collect()
+            };
+            for &y in &ys {
+                for &x in &xs {
+                    stops[y * width + x][heading as usize] =
+                        match next_guard_pos((x, y), heading, grid) {
+                            None => None,
+                            Some((false, _, _)) => Some((x, y)),
+                            Some((true, x2, y2)) => stops[y2 * width + x2][heading as usize],
+                        };
+                }
+            }
+        }
+        JumpTable { width, stops }
+    }
+
+    /// Whether adding an obstacle at the given position would trap the guard in a loop
+    fn creates_loop(&self, initial_guard: Guard, obstacle: (usize, usize)) -> bool {
+        let mut turns = HashSet::new();
+        let Guard {
+            mut pos,
+            mut heading,
+        } = initial_guard;
+        loop {
+            let mut stop = self.stops[pos.1 * self.width + pos.0][heading as usize];
+            // the extra obstacle may be in the way, before the usual stop
+            if let Some(obstacle_dist) = steps_ahead(pos, obstacle, heading) {
+                let blocked = match stop {
+                    None => true,
+                    Some(stop) => steps_ahead(pos, stop, heading)
+                        .is_some_and(|stop_dist| obstacle_dist <= stop_dist),
+                };
+                if blocked {
+                    let (dx, dy): (isize, isize) = heading.into();
+                    let back = obstacle_dist as isize - 1;
+                    stop = Some((
+                        pos.0.wrapping_add_signed(dx * back),
+                        pos.1.wrapping_add_signed(dy * back),
+                    ));
+                }
+            }
+            match stop {
+                None => return false,
+                Some(stop) => {
+                    if !turns.insert((stop, heading)) {
+                        return true;
                     }
+                    pos = stop;
+                    heading = heading.turn_right();
                 }
             }
         }
     }
-    info!("Detected {} loop-inducing positions (out of {} attempts)", format!("{}", num_loops).cyan(), num_tested);
+}
 
-    Ok(())
+/// How many steps in the `heading` direction it takes to get from `from` to `to`, if any
+fn steps_ahead(from: (usize, usize), to: (usize, usize), heading: Cardinal) -> Option<usize> {
+    let n = match heading {
+        Cardinal::North if to.0 == from.0 => from.1.checked_sub(to.1)?,
+        Cardinal::South if to.0 == from.0 => to.1.checked_sub(from.1)?,
+        Cardinal::West if to.1 == from.1 => from.0.checked_sub(to.0)?,
+        Cardinal::East if to.1 == from.1 => to.0.checked_sub(from.0)?,
+        _ => return None,
+    };
+    (n > 0).then_some(n)
 }
 
 fn run_patrol(initial_grid: Grid<TileState>, initial_guard: Guard) -> (PatrolState, PatrolResult) {
@@ -99,7 +285,7 @@ enum PatrolResult {
     Aborted,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 enum TileState {
     Obstacle,
     ArtificialObstacle,
@@ -169,6 +355,7 @@ struct Guard {
     pos: (usize, usize),
 }
 
+#[derive(Clone)]
 struct PatrolState {
     grid: Grid<TileState>,
     guard: Option<Guard>,
@@ -223,7 +410,10 @@ fn parse_input<T: BufRead>(reader: &mut T) -> GenResult<(Grid<TileState>, Guard)
                         });
                     }
                     Err(_) => {
-                        Err(format!("Unrecognized character: '{}'", other))?;
+                        Err(
+                            ParseError::new(format!("Unrecognized character: '{}'", other))
+                                .at_span(current_row, &line, col, 1),
+                        )?
                     }
                 },
             }
@@ -232,7 +422,9 @@ fn parse_input<T: BufRead>(reader: &mut T) -> GenResult<(Grid<TileState>, Guard)
         current_row += 1;
     }
 
-    Ok((Grid { rows }, guard.ok_or("guard not found")?))
+    let guard =
+        guard.ok_or_else(|| ParseError::new("guard not found (expected one of ^, >, v or <)"))?;
+    Ok((Grid { rows }, guard))
 }
 
 impl PatrolState {
@@ -306,6 +498,31 @@ impl PatrolState {
     }
 }
 
+impl Simulation for PatrolState {
+    type Tile = TileState;
+    type Style = WithGuard;
+
+    fn step(&mut self) -> bool {
+        if self.guard.is_none() || self.detected_loop {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    fn grid(&self) -> &Grid<TileState> {
+        &self.grid
+    }
+
+    fn style(&self) -> WithGuard {
+        WithGuard(self.guard)
+    }
+
+    fn caption(&self) -> String {
+        format!("{} tiles visited", self.num_traversed())
+    }
+}
+
 fn next_guard_pos(
     (x, y): (usize, usize),
     heading: Cardinal,
@@ -317,3 +534,79 @@ fn next_guard_pos(
     let tile = grid.get(x2, y2)?;
     Some((tile.is_traversable(), x2, y2))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::rng::XorShift;
+    use std::io::Cursor;
+
+    /// Check that the jump table agrees with a full replay about every candidate obstacle
+    fn assert_table_matches_replay(input: &str) -> usize {
+        let (grid, guard) = parse_input(&mut Cursor::new(input)).unwrap();
+        let (part1_state, _) = run_patrol(grid.clone(), guard);
+        let table = JumpTable::new(&grid);
+        let mut loops = 0;
+        for (x, y) in traversed_positions(&part1_state.grid, guard) {
+            let (_, result) = run_patrol(with_obstacle(&grid, x, y), guard);
+            let replay_loops = matches!(result, PatrolResult::LoopDetected);
+            assert_eq!(
+                table.creates_loop(guard, (x, y)),
+                replay_loops,
+                "obstacle at {:?} in\n{}",
+                (x, y),
+                input
+            );
+            loops += usize::from(replay_loops);
+        }
+        loops
+    }
+
+    #[test]
+    fn jump_table_matches_replay_on_example() {
+        let input = std::fs::read_to_string("example_inputs/6.txt").unwrap();
+        assert_eq!(assert_table_matches_replay(&input), 6);
+    }
+
+    #[test]
+    fn jump_table_matches_replay_on_random_grids() {
+        let mut rng = XorShift::new(0x5851_f42d_4c95_7f2d);
+        let mut total_loops = 0;
+        for _ in 0..100 {
+            let (width, height) = (5 + rng.below(12), 5 + rng.below(12));
+            let guard = (rng.below(width), rng.below(height));
+            let heading = ['^', '>', 'v', '<'][rng.below(4)];
+            let input = (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| match ((x, y) == guard, rng.below(100)) {
+                            (true, _) => heading,
+                            (false, n) if n < 15 => '#',
+                            _ => '.',
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            total_loops += assert_table_matches_replay(&input);
+        }
+        // make sure the grids are interesting enough to have loops in them
+        assert!(total_loops > 20, "only {} loops", total_loops);
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_bad_character() {
+        let err = parse_input(&mut Cursor::new("..#\n.^.\n.x.\n"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "parse error: Unrecognized character: 'x'\n --> <input>:3:2\n  |\n3 | .x.\n  |  ^"
+        );
+        let err = parse_input(&mut Cursor::new("..#\n...\n")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "parse error: guard not found (expected one of ^, >, v or <)"
+        );
+    }
+}
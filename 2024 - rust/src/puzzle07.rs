@@ -1,28 +1,46 @@
-use crate::helper::GenResult;
-use colored::Colorize;
+use crate::helper::{AocError, GenError, GenResult, InputContext};
+use colored::{ColoredString, Colorize};
 use log::info;
-use std::error::Error;
+use rayon::prelude::*;
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::str::FromStr;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
+pub fn run(input_path: &Path, args: &[String]) -> GenResult<()> {
+    // an extra run with some other set of operators can be requested, e.g. `ops=+,*,-,^`
+    let custom_operators = match args {
+        [] => None,
+        [arg] if arg.starts_with("ops=") => Some(parse_operators(&arg["ops=".len()..])?),
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 7: {:?} (expected ops=<operators>)",
+            args
+        )))?,
+    };
+
     let puzzle_lines: Vec<PuzzleLine> = {
         let file = File::open(input_path)?;
         let reader = BufReader::new(file);
         reader
             .lines()
-            .map(|l| l?.parse::<PuzzleLine>())
+            .enumerate()
+            .map(|(i, l)| {
+                let l = l?;
+                l.parse::<PuzzleLine>().at_input_line(i, &l)
+            })
             .collect::<GenResult<Vec<_>>>()?
     };
 
     let mut part1_sum = 0u64;
     let mut part2_sum = 0u64;
 
-    for line in puzzle_lines {
-        let solutions = line.solve();
+    // lines are independent, so they're solved in parallel, then logged in order
+    let all_solutions = puzzle_lines
+        .par_iter()
+        .map(PuzzleLine::solve)
+        .collect::<Vec<_>>();
+    for solutions in all_solutions {
         if solutions.is_solved() {
             part2_sum += solutions.puzzle.output;
             if !solutions.required_concat {
@@ -35,18 +53,35 @@ pub fn run(input_path: &Path) -> GenResult<()> {
     info!("Part 1 sum: {}", part1_sum.to_string().green());
     info!("Part 2 sum: {}", part2_sum.to_string().yellow());
 
+    if let Some(operators) = custom_operators {
+        let sum = puzzle_lines
+            .par_iter()
+            .filter(|line| !line.inner_solve(&operators).is_empty())
+            .map(|line| line.output)
+            .sum::<u64>();
+        info!(
+            "Sum using operators {}: {}",
+            operators
+                .iter()
+                .map(|op| op.render().to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            sum.to_string().cyan()
+        );
+    }
+
     Ok(())
 }
 
 /// Represents a line from the day 7 puzzle input
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct PuzzleLine {
     output: u64,
     inputs: Vec<u64>,
 }
 
 impl FromStr for PuzzleLine {
-    type Err = Box<dyn Error>;
+    type Err = GenError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (raw_output, raw_inputs) = s.split_once(": ").ok_or("bad split")?;
@@ -60,116 +95,320 @@ impl FromStr for PuzzleLine {
 }
 
 impl PuzzleLine {
-    fn solve(self) -> Solutions {
-        if self.inputs.is_empty() {
-            Solutions {
-                puzzle: self,
-                solutions: Vec::new(),
-                required_concat: false,
-            }
-        } else {
-            let mut required_concat = false;
-            let mut solutions = (&self).inner_solve(&[Operator::Plus, Operator::Times]);
-            if solutions.is_empty() {
-                required_concat = true;
-                solutions =
-                    (&self).inner_solve(&[Operator::Plus, Operator::Times, Operator::Concat]);
-            }
-            Solutions {
-                puzzle: self,
-                solutions,
-                required_concat,
-            }
+    fn solve(&self) -> Solutions {
+        let mut required_concat = false;
+        let mut solutions = self.inner_solve(PART1_OPERATORS);
+        if solutions.is_empty() {
+            required_concat = true;
+            solutions = self.inner_solve(PART2_OPERATORS);
+        }
+        Solutions {
+            puzzle: self.clone(),
+            solutions,
+            required_concat,
         }
     }
 
-    fn inner_solve(&self, operators: &[Operator]) -> Vec<Solution> {
+    fn inner_solve(&self, operators: &[&'static dyn Operator]) -> Vec<Solution> {
+        let Some((&last, init)) = self.inputs.split_last() else {
+            return Vec::new();
+        };
         let mut out = Vec::new();
-        Self::recursive_solve(
+        search_backward(
             self.output,
-            (self.inputs[0], Vec::new()),
-            &self.inputs[1..],
+            init,
+            last,
             operators,
+            &mut Vec::new(),
             &mut out,
         );
+
         out.into_iter()
-            .map(|operators| Solution {
+            .map(|path| Solution {
                 inputs: self.inputs.clone(),
-                operators,
+                operators: path.into_iter().map(|i| operators[i]).collect(),
                 output: self.output,
             })
             .collect()
     }
+}
+
+/// Operators for part 1
+const PART1_OPERATORS: &[&dyn Operator] = &[&Plus, &Times];
+
+/// Operators for part 2
+const PART2_OPERATORS: &[&dyn Operator] = &[&Plus, &Times, &Concat];
+
+/// Every operator that can be picked with the `ops=` argument
+const ALL_OPERATORS: &[&dyn Operator] = &[&Plus, &Times, &Concat, &Minus, &Xor, &Power];
+
+fn parse_operators(list: &str) -> GenResult<Vec<&'static dyn Operator>> {
+    list.split(',')
+        .map(|symbol| {
+            ALL_OPERATORS
+                .iter()
+                .find(|op| op.symbol() == symbol)
+                .copied()
+                .ok_or_else(|| AocError::Args(format!("unknown operator {:?}", symbol)))
+        })
+        .collect()
+}
+
+/// Operator sequences are represented as indexes into the list of operators being used
+type OperatorPath = Vec<usize>;
+
+/// Works backwards from the goal, undoing the last operation for each possible operator
+/// (e.g. un-multiplying by the last input, which is only possible if it's a divisor).
+/// Most operators can only be undone in a few cases, so most branches die immediately,
+/// unlike the forward search, which can't rule anything out until the total overshoots.
+///
+/// `path` holds the operators picked so far, last operator first.
+fn search_backward(
+    goal: u64,
+    init: &[u64],
+    last: u64,
+    operators: &[&'static dyn Operator],
+    path: &mut OperatorPath,
+    out: &mut Vec<OperatorPath>,
+) {
+    let Some((&prev, rest)) = init.split_last() else {
+        // recursion end case; with a single input left, it has to be the goal itself
+        if last == goal {
+            out.push(path.iter().rev().copied().collect());
+        }
+        return;
+    };
+    for (i, op) in operators.iter().enumerate() {
+        path.push(i);
+        match op.invert(goal, last) {
+            Inverse::None => (),
+            Inverse::One(new_goal) => search_backward(new_goal, rest, prev, operators, path, out),
+            Inverse::Any => {
+                // the result doesn't depend on what came before, as long as it can be computed at all
+                let mut prefixes = Vec::new();
+                search_forward(init, operators, &|_| true, &mut prefixes);
+                for mut prefix in prefixes {
+                    prefix.extend(path.iter().rev());
+                    out.push(prefix);
+                }
+            }
+        }
+        path.pop();
+    }
+}
 
-    // Basically a DFS through a binary/trinary tree;
-    // Each step inspects the next number from the `tail`, once per operator, resulting in
-    // O(num_numbers ^ num_operators) worst case performance.
-    // Includes an early-exit to avoid continuing down a search path whose `accum`
-    // total already went past the `goal` number.
-    fn recursive_solve(
-        goal: u64,
-        accum: (u64, Vec<Operator>),
+// Basically a DFS through a binary/trinary tree;
+// Each step inspects the next number from the `tail`, once per operator, resulting in
+// O(num_operators ^ num_numbers) worst case performance.
+// Branches whose arithmetic overflows are dropped.
+fn search_forward(
+    inputs: &[u64],
+    operators: &[&'static dyn Operator],
+    accept: &dyn Fn(u64) -> bool,
+    out: &mut Vec<OperatorPath>,
+) {
+    fn recurse(
+        total: u64,
         tail: &[u64],
-        operators: &[Operator],
-        out: &mut Vec<Vec<Operator>>,
+        operators: &[&'static dyn Operator],
+        accept: &dyn Fn(u64) -> bool,
+        path: &mut OperatorPath,
+        out: &mut Vec<OperatorPath>,
     ) {
-        let (total, path) = accum;
-        if total > goal {
-            return; // since there are no decreasing operators, use this to exit early
-        }
-        if tail.is_empty() {
+        let Some((&head, remaining)) = tail.split_first() else {
             // recursion end case
-            if total == goal {
-                out.push(path)
+            if accept(total) {
+                out.push(path.clone());
             }
-        } else {
-            let head = tail[0];
-            let remaining = &tail[1..];
-
-            for op in operators {
-                let next_total = op.apply(total, head);
-                let mut next_path = path.clone();
-                next_path.push(*op);
-                Self::recursive_solve(goal, (next_total, next_path), remaining, operators, out);
+            return;
+        };
+        for (i, op) in operators.iter().enumerate() {
+            if let Some(next_total) = op.apply(total, head) {
+                path.push(i);
+                recurse(next_total, remaining, operators, accept, path, out);
+                path.pop();
             }
         }
     }
+
+    if let Some((&first, tail)) = inputs.split_first() {
+        recurse(first, tail, operators, accept, &mut Vec::new(), out);
+    }
+}
+
+/// Possible values for the left operand of an operator, given the right operand and the result
+#[derive(Debug, Eq, PartialEq)]
+enum Inverse {
+    None,
+    One(u64),
+    /// The result is the same whatever the left operand is (e.g. multiplying by 0)
+    Any,
 }
 
-/// Enum of the operators defined for day 7
-#[derive(Copy, Clone)]
-enum Operator {
-    Plus,
-    Times,
-    Concat,
+/// A binary operator that can be placed between the numbers of a puzzle line.
+/// Arithmetic is checked, with `None` for results that don't fit in a `u64`.
+trait Operator: Sync {
+    /// How the operator is written, e.g. `+`
+    fn symbol(&self) -> &'static str;
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64>;
+
+    /// Find the `a` for which `apply(a, b) == result`
+    fn invert(&self, result: u64, b: u64) -> Inverse;
+
+    /// The symbol, in color for the log output
+    fn render(&self) -> ColoredString {
+        self.symbol().normal()
+    }
 }
 
-impl Operator {
-    fn apply(&self, a: u64, b: u64) -> u64 {
-        match self {
-            Operator::Plus => a + b,
-            Operator::Times => a * b,
-            Operator::Concat => format!("{}{}", a, b).parse().unwrap(),
+struct Plus;
+
+impl Operator for Plus {
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_add(b)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        result.checked_sub(b).map_or(Inverse::None, Inverse::One)
+    }
+
+    fn render(&self) -> ColoredString {
+        self.symbol().cyan()
+    }
+}
+
+struct Times;
+
+impl Operator for Times {
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_mul(b)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        match (result, b) {
+            (0, 0) => Inverse::Any,
+            (_, 0) => Inverse::None,
+            _ if result.is_multiple_of(b) => Inverse::One(result / b),
+            _ => Inverse::None,
         }
     }
+
+    fn render(&self) -> ColoredString {
+        self.symbol().bright_magenta()
+    }
 }
 
-// Fancy colored output for operators
-impl Display for Operator {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            Operator::Plus => "+".to_string().cyan(),
-            Operator::Times => "*".to_string().bright_magenta(),
-            Operator::Concat => "||".to_string().yellow(),
-        };
-        write!(f, "{}", str)
+/// Appends the digits of `b` to `a`
+struct Concat;
+
+impl Concat {
+    /// The power of 10 that `a` is multiplied by to make room for the digits of `b`
+    fn shift(b: u64) -> Option<u64> {
+        10u64.checked_pow(b.checked_ilog10().unwrap_or(0) + 1)
+    }
+}
+
+impl Operator for Concat {
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_mul(Self::shift(b)?)?.checked_add(b)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        match Self::shift(b) {
+            Some(shift) if result % shift == b => Inverse::One(result / shift),
+            _ => Inverse::None,
+        }
+    }
+
+    fn render(&self) -> ColoredString {
+        self.symbol().yellow()
+    }
+}
+
+struct Minus;
+
+impl Operator for Minus {
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_sub(b)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        result.checked_add(b).map_or(Inverse::None, Inverse::One)
+    }
+}
+
+struct Xor;
+
+impl Operator for Xor {
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        Some(a ^ b)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        Inverse::One(result ^ b)
+    }
+}
+
+struct Power;
+
+impl Operator for Power {
+    fn symbol(&self) -> &'static str {
+        "**"
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_pow(u32::try_from(b).ok()?)
+    }
+
+    fn invert(&self, result: u64, b: u64) -> Inverse {
+        if b == 0 {
+            return if result == 1 {
+                Inverse::Any
+            } else {
+                Inverse::None
+            };
+        }
+        // binary search for the integer `b`th root
+        let (mut lo, mut hi) = (0, result);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            match self.apply(mid, b) {
+                Some(n) if n <= result => lo = mid,
+                _ => hi = mid - 1,
+            }
+        }
+        match self.apply(lo, b) {
+            Some(n) if n == result => Inverse::One(lo),
+            _ => Inverse::None,
+        }
     }
 }
 
 // One possible solution to a puzzle
 struct Solution {
     inputs: Vec<u64>,
-    operators: Vec<Operator>,
+    operators: Vec<&'static dyn Operator>,
     output: u64,
 }
 
@@ -179,7 +418,7 @@ impl Display for Solution {
         write!(f, "{} = ", self.output.to_string().green())?;
         for (i, n) in self.inputs.iter().enumerate() {
             if i > 0 {
-                write!(f, " {} ", self.operators[i - 1].to_string().yellow())?;
+                write!(f, " {} ", self.operators[i - 1].render())?;
             }
             write!(f, "{}", n.to_string())?;
         }
@@ -235,3 +474,151 @@ impl<'a> Display for Unsolved<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::rng::XorShift;
+
+    /// Every operator path that turns `line`'s inputs into its output, found by `search_backward`
+    fn backward_paths(line: &PuzzleLine, operators: &[&'static dyn Operator]) -> Vec<OperatorPath> {
+        let mut out = Vec::new();
+        if let Some((&last, init)) = line.inputs.split_last() {
+            search_backward(
+                line.output,
+                init,
+                last,
+                operators,
+                &mut Vec::new(),
+                &mut out,
+            );
+        }
+        out.sort();
+        out
+    }
+
+    /// The same as `backward_paths`, but by trying every path from the left
+    fn forward_paths(line: &PuzzleLine, operators: &[&'static dyn Operator]) -> Vec<OperatorPath> {
+        let mut out = Vec::new();
+        let goal = line.output;
+        search_forward(&line.inputs, operators, &|total| total == goal, &mut out);
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn example_sums() {
+        let input = std::fs::read_to_string("example_inputs/7.txt").unwrap();
+        let (mut part1_sum, mut part2_sum) = (0, 0);
+        for line in input.lines() {
+            let solutions = line.parse::<PuzzleLine>().unwrap().solve();
+            if solutions.is_solved() {
+                part2_sum += solutions.puzzle.output;
+                if !solutions.required_concat {
+                    part1_sum += solutions.puzzle.output;
+                }
+            }
+        }
+        assert_eq!(part1_sum, 3749);
+        assert_eq!(part2_sum, 11387);
+    }
+
+    #[test]
+    fn times_invert_edge_cases() {
+        assert_eq!(Times.invert(0, 0), Inverse::Any);
+        assert_eq!(Times.invert(5, 0), Inverse::None);
+        assert_eq!(Times.invert(0, 7), Inverse::One(0));
+        assert_eq!(Times.invert(12, 4), Inverse::One(3));
+        assert_eq!(Times.invert(12, 5), Inverse::None);
+    }
+
+    #[test]
+    fn power_invert_edge_cases() {
+        // anything to the power of 0 is 1
+        assert_eq!(Power.invert(1, 0), Inverse::Any);
+        assert_eq!(Power.invert(7, 0), Inverse::None);
+        // integer roots, and numbers that don't have one
+        assert_eq!(Power.invert(27, 3), Inverse::One(3));
+        assert_eq!(Power.invert(26, 3), Inverse::None);
+        assert_eq!(Power.invert(28, 3), Inverse::None);
+        assert_eq!(Power.invert(0, 5), Inverse::One(0));
+        assert_eq!(Power.invert(1, 40), Inverse::One(1));
+        assert_eq!(Power.invert(9, 1), Inverse::One(9));
+        assert_eq!(Power.invert(1 << 62, 2), Inverse::One(1 << 31));
+        assert_eq!(Power.invert(u64::MAX, 2), Inverse::None);
+        assert_eq!(Power.invert(1 << 63, 63), Inverse::One(2));
+        // exponents too big for `u32` can't be applied, so they can't be undone either
+        assert_eq!(Power.invert(1, 1 << 40), Inverse::None);
+    }
+
+    #[test]
+    fn concat_invert_edge_cases() {
+        assert_eq!(Concat.apply(12, 0), Some(120));
+        assert_eq!(Concat.invert(120, 0), Inverse::One(12));
+        assert_eq!(Concat.invert(0, 0), Inverse::One(0));
+        assert_eq!(Concat.invert(5, 0), Inverse::None);
+        assert_eq!(Concat.invert(12345, 345), Inverse::One(12));
+        assert_eq!(Concat.invert(345, 345), Inverse::One(0));
+        assert_eq!(Concat.invert(12345, 45), Inverse::One(123));
+        assert_eq!(Concat.invert(12345, 46), Inverse::None);
+        assert_eq!(Concat.apply(u64::MAX / 10, 99), None);
+        // a 20-digit `b` leaves no room for any `a`, so it can't be undone either
+        assert_eq!(Concat.apply(0, u64::MAX), None);
+        assert_eq!(Concat.invert(u64::MAX, u64::MAX), Inverse::None);
+    }
+
+    #[test]
+    fn backward_search_matches_forward_search() {
+        let operator_sets = [
+            PART1_OPERATORS.to_vec(),
+            PART2_OPERATORS.to_vec(),
+            ALL_OPERATORS.to_vec(),
+            parse_operators("-,^").unwrap(),
+            parse_operators("+,-,*").unwrap(),
+            parse_operators("**,||,^").unwrap(),
+        ];
+        let mut rng = XorShift::new(0xbf58_476d_1ce4_e5b9);
+        let mut solved = 0;
+        for _ in 0..300 {
+            let inputs = (0..1 + rng.below(5))
+                .map(|_| rng.below(12) as u64)
+                .collect::<Vec<_>>();
+            let operators = rng.pick(&operator_sets);
+            // usually aim for a total that some path reaches, so there's something to find
+            let mut total = Some(inputs[0]);
+            for b in &inputs[1..] {
+                total = total.and_then(|a| rng.pick(operators).apply(a, *b));
+            }
+            let output = match total {
+                Some(total) if rng.below(4) > 0 => total,
+                _ => rng.below(200) as u64,
+            };
+
+            let line = PuzzleLine { output, inputs };
+            let backward = backward_paths(&line, operators);
+            assert_eq!(
+                backward,
+                forward_paths(&line, operators),
+                "{:?} with operators {:?}",
+                line,
+                operators.iter().map(|op| op.symbol()).collect::<Vec<_>>()
+            );
+            solved += usize::from(!backward.is_empty());
+        }
+        assert!(solved > 150, "only {} lines were solvable", solved);
+    }
+
+    #[test]
+    fn operators_are_parsed_by_symbol() {
+        let symbols = |list: &str| {
+            parse_operators(list)
+                .unwrap()
+                .iter()
+                .map(|op| op.symbol())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(symbols("+,*,||"), ["+", "*", "||"]);
+        assert_eq!(symbols("-,^,**"), ["-", "^", "**"]);
+        assert!(matches!(parse_operators("+,/"), Err(AocError::Args(_))));
+    }
+}
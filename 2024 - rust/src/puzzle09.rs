@@ -1,7 +1,8 @@
-use crate::helper::GenResult;
+use crate::helper::{GenResult, ParseError};
 use colored::Colorize;
 use log::{debug, info};
-use std::collections::LinkedList;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{Debug, Write};
 use std::fs::File;
 use std::io::BufReader;
@@ -9,21 +10,22 @@ use std::path::Path;
 use utf8_chars::BufReadCharsExt;
 
 pub fn run(input_path: &Path) -> GenResult<()> {
-    let mut fs = parse_input(input_path)?;
+    let disk_map = parse_input(input_path)?;
+    info!("Start: {:?}", disk_map.segments);
+    disk_map.log_layout("Start", &disk_map.layout());
 
-    let segments_v2 = fs.unformatted.iter().cloned().collect::<Vec<_>>();
+    let (part1_sum, part2_sum) = disk_map.checksums();
 
-    info!("Start: {:?}", fs);
-    defrag(&mut fs);
-    info!("End: {:?}", fs);
-    let part1_sum = checksum(&fs.formatted);
-    info!("Part 1 Checksum: {}", part1_sum);
-
-    fill_v2(&segments_v2);
+    info!("Part 1 Checksum: {}", part1_sum.to_string().green());
+    info!("Part 2 Checksum: {}", part2_sum.to_string().bright_blue());
 
     Ok(())
 }
 
+/// Inputs with more blocks than this are too big to be worth rendering
+const MAX_RENDER_BLOCKS: usize = 200;
+
+/// One file from the disk map, followed by some free space
 #[derive(Clone)]
 struct Segment {
     id: usize,
@@ -44,178 +46,264 @@ impl Debug for Segment {
     }
 }
 
-#[derive(Debug)]
-struct Filesystem {
-    formatted: Vec<Segment>,
-    unformatted: LinkedList<Segment>,
+/// A contiguous run of blocks belonging to one file, at a specific position on the disk
+#[derive(Debug, Copy, Clone)]
+struct Extent {
+    id: usize,
+    offset: usize,
+    width: usize,
 }
 
-fn parse_input(input_path: &Path) -> GenResult<Filesystem> {
-    let file = File::open(input_path)?;
-    let mut reader = BufReader::new(file);
-    let mut itr = reader.chars().map(Result::unwrap).filter(|c| *c != '\n');
-    let mut out = LinkedList::new();
-    let mut id = 0;
+/// The dense format from the puzzle input, alternating between file lengths and gap lengths
+#[derive(Debug, Clone)]
+struct DiskMap {
+    segments: Vec<Segment>,
+}
 
-    while let Some(c) = itr.next() {
-        let length = { c.to_digit(10).ok_or(format!("not a digit: {}", c))? as usize };
-        let gap = if let Some(c2) = itr.next() {
-            c2.to_digit(10).ok_or(format!("not a digit: {}", c2))? as usize
-        } else {
-            0
-        };
-        out.push_back(Segment { id, length, gap });
-        id += 1;
+impl DiskMap {
+    /// Where each file is, before any compaction
+    fn layout(&self) -> Vec<Extent> {
+        let mut offset = 0;
+        let mut out = Vec::with_capacity(self.segments.len());
+        for seg in &self.segments {
+            out.push(Extent {
+                id: seg.id,
+                offset,
+                width: seg.length,
+            });
+            offset += seg.length + seg.gap;
+        }
+        out
     }
 
-    Ok(Filesystem {
-        formatted: Vec::new(),
-        unformatted: out,
-    })
-}
-
-fn defrag(fs: &mut Filesystem) {
-    while let Some(mut segment) = fs.unformatted.pop_front() {
-        let gap = segment.gap;
+    /// Total number of blocks on the disk, including free space
+    fn size(&self) -> usize {
+        self.segments.iter().map(|seg| seg.length + seg.gap).sum()
+    }
 
-        // move the segment to the formatted section
-        segment.gap = 0;
-        fs.formatted.push(segment);
+    fn log_layout(&self, label: &str, layout: &[Extent]) {
+        if let Some(rendered) = render(layout, self.size()) {
+            info!("{}: {}", label, rendered);
+        }
+    }
 
-        // handle the remaining gap by pulling items from the back of the filesystem
-        fill_gap(gap, fs);
+    /// Checksums after compacting by blocks (part 1) and by whole files (part 2)
+    fn checksums(&self) -> (usize, usize) {
+        let blocks = self.compact_blocks();
+        self.log_layout("Compacted by block", &blocks);
+        let files = self.compact_files();
+        self.log_layout("Compacted by file", &files);
+        (checksum(&blocks), checksum(&files))
     }
-}
 
-fn fill_gap(gap: usize, fs: &mut Filesystem) {
-    if gap > 0 {
-        if let Some(mut segment) = fs.unformatted.pop_back() {
-            let length = segment.length;
-            if length <= gap {
-                // whole segment fits in the gap
-                segment.gap = 0;
-                fs.formatted.push(segment);
-                // recurse the fill what remains of the gap
-                fill_gap(gap - length, fs);
-            } else {
-                // segment is too big for the gap;
-                // add a slice of this segment to the formatted region,
-                // and return the rest to the back of the unformatted region
-                fs.formatted.push(Segment {
-                    id: segment.id,
-                    length: gap,
-                    gap: 0,
-                });
-                segment.length -= gap;
-                fs.unformatted.push_back(segment);
+    /// Part 1: move blocks one at a time from the end of the disk to the leftmost free space,
+    /// splitting files up as needed.
+    fn compact_blocks(&self) -> Vec<Extent> {
+        // segments that haven't been placed yet; files are pulled from both ends
+        let mut unformatted = self.segments.iter().cloned().collect::<VecDeque<_>>();
+        let mut formatted = Vec::<Extent>::new();
+        let mut offset = 0;
+        let mut place = |id: usize, width: usize, out: &mut Vec<Extent>| {
+            out.push(Extent { id, offset, width });
+            offset += width;
+        };
+
+        while let Some(segment) = unformatted.pop_front() {
+            place(segment.id, segment.length, &mut formatted);
+
+            // fill the segment's gap by pulling files from the back of the disk
+            let mut gap = segment.gap;
+            while gap > 0 {
+                let Some(mut last) = unformatted.pop_back() else {
+                    break;
+                };
+                if last.length <= gap {
+                    // whole file fits in the gap
+                    place(last.id, last.length, &mut formatted);
+                    gap -= last.length;
+                } else {
+                    // file is too big for the gap; move as much of it as fits,
+                    // and return the rest to the back of the disk
+                    place(last.id, gap, &mut formatted);
+                    last.length -= gap;
+                    gap = 0;
+                    unformatted.push_back(last);
+                }
             }
         }
+        formatted
     }
-}
 
-fn checksum(segments: &Vec<Segment>) -> usize {
-    let mut i = 0;
-    let mut accum = 0;
-    for seg in segments.iter() {
-        for d in 0..seg.length {
-            accum += (i + d) * seg.id;
+    /// Part 2: move whole files, from the highest id down, into the leftmost gap that fits them.
+    ///
+    /// Gaps are kept in one min-heap of offsets per width. Since file widths are single digits,
+    /// finding the leftmost gap that fits a file means checking the top of at most 10 heaps.
+    fn compact_files(&self) -> Vec<Extent> {
+        let mut files = self.layout();
+        let mut gaps_by_width: [BinaryHeap<Reverse<usize>>; 10] = Default::default();
+        for (file, seg) in files.iter().zip(&self.segments) {
+            if seg.gap > 0 {
+                gaps_by_width[seg.gap].push(Reverse(file.offset + file.width));
+            }
+        }
+
+        for file in files.iter_mut().rev() {
+            let leftmost_fit = (file.width..gaps_by_width.len())
+                .filter_map(|width| {
+                    let Reverse(offset) = gaps_by_width[width].peek()?;
+                    (*offset < file.offset).then_some((*offset, width))
+                })
+                .min();
+            if let Some((gap_offset, gap_width)) = leftmost_fit {
+                debug!(
+                    "{:?} can fit in the {}-wide gap at {}",
+                    file, gap_width, gap_offset
+                );
+                gaps_by_width[gap_width].pop();
+                file.offset = gap_offset;
+                // the space the file left behind is never filled, since only files further
+                // right (which are processed earlier) could have moved into it
+                let remaining = gap_width - file.width;
+                if remaining > 0 {
+                    gaps_by_width[remaining].push(Reverse(gap_offset + file.width));
+                }
+            }
         }
-        i += seg.length;
+
+        files.sort_by_key(|file| file.offset);
+        files
     }
-    accum
 }
 
-#[derive(Debug)]
-struct Gap {
-    offset: usize,
-    width: usize,
+fn checksum(layout: &[Extent]) -> usize {
+    layout
+        .iter()
+        .flat_map(|ext| (ext.offset..ext.offset + ext.width).map(|i| i * ext.id))
+        .sum()
 }
 
-#[derive(Debug)]
-struct SegmentV2 {
-    id: usize,
-    offset: usize,
-    width: usize,
+/// Render the layout in the puzzle's notation, e.g. `00...111...2...333.44.5555.6666.777.888899`,
+/// with one char per block and `.` for free space. Ids past 9 continue with letters.
+/// Returns `None` for layouts too large to be readable, or with more ids than there are chars for.
+fn render(layout: &[Extent], disk_size: usize) -> Option<String> {
+    if disk_size > MAX_RENDER_BLOCKS {
+        return None;
+    }
+    let mut blocks = vec!['.'; disk_size];
+    for ext in layout {
+        let c = char::from_digit(ext.id.try_into().ok()?, 36)?;
+        blocks[ext.offset..ext.offset + ext.width].fill(c);
+    }
+    Some(blocks.into_iter().collect())
 }
 
-fn fill_v2(segments: &Vec<Segment>) {
-    // represent the filesystem as an arbitrarily-ordered collection of SegmentV2,
-    // where each item tracks its own `offset` from the start of the filesystem,
-    let mut segments_v2 = {
-        let mut out = Vec::new();
+fn parse_input(input_path: &Path) -> GenResult<DiskMap> {
+    let file = File::open(input_path)?;
+    let mut reader = BufReader::new(file);
+    let line = reader.chars().collect::<Result<String, _>>()?;
+    parse_disk_map(line.trim_end())
+}
 
-        let mut accum_offset = 0;
-        for seg in segments {
-            out.push(SegmentV2 {
-                id: seg.id,
-                offset: accum_offset,
-                width: seg.length,
-            });
-            accum_offset += seg.length + seg.gap;
-        }
+fn parse_disk_map(line: &str) -> GenResult<DiskMap> {
+    let mut itr = line.chars().enumerate();
+    let mut segments = Vec::new();
+    let mut id = 0;
 
-        out
+    let parse_digit = |(column, c): (usize, char)| {
+        c.to_digit(10).map(|d| d as usize).ok_or_else(|| {
+            ParseError::new(format!("not a digit: {:?}", c)).at_span(0, line, column, 1)
+        })
     };
 
-    info!("segments v2: {:?}", segments_v2);
+    while let Some(c) = itr.next() {
+        let length = parse_digit(c)?;
+        let gap = if let Some(c2) = itr.next() {
+            parse_digit(c2)?
+        } else {
+            0
+        };
+        segments.push(Segment { id, length, gap });
+        id += 1;
+    }
 
-    // Also represent the gaps between items in the filesystem. For the part 2
-    // algorithm, each segment will need to search the `gaps` for one wide enough.
-    let mut gaps = {
-        let mut accum_offset = 0;
-        let mut out = Vec::new();
-        for seg in segments {
-            accum_offset += seg.length;
-            if seg.gap > 0 {
-                out.push(Gap {
-                    offset: accum_offset,
-                    width: seg.gap,
-                });
-            }
-            accum_offset += seg.gap;
-        }
-        out
-    };
+    Ok(DiskMap { segments })
+}
 
-    info!("gaps: {:?}", gaps);
-
-    for seg in segments_v2.iter_mut().rev() {
-        if let Some(gap_index) = gaps
-            .iter_mut()
-            .take_while(|g| g.offset < seg.offset)
-            .position(|g| g.width >= seg.width)
-        {
-            let gap = &mut gaps[gap_index];
-            // the segment can be moved into the gap
-            debug!("{:?} can fit in {:?}", seg, gap);
-            if gap.width > seg.width {
-                debug!("gap shrinks by {}", seg.width);
-                seg.offset = gap.offset;
-                gap.offset += seg.width;
-                gap.width -= seg.width;
-            } else {
-                debug!("gap closed");
-                seg.offset = gap.offset;
-                gaps.remove(gap_index);
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::rng::XorShift;
+
+    #[test]
+    fn example_checksums() {
+        let input = std::fs::read_to_string("example_inputs/9.txt").unwrap();
+        let disk_map = parse_disk_map(input.trim_end()).unwrap();
+        assert_eq!(disk_map.checksums(), (1928, 2858));
+    }
+
+    #[test]
+    fn trailing_crlf_is_ignored() {
+        let disk_map = parse_disk_map("12345\r\n".trim_end()).unwrap();
+        assert_eq!(disk_map.segments.len(), 3);
     }
 
-    segments_v2.sort_by_key(|seg| seg.offset);
-    info!("final segments: {:?}", segments_v2);
+    #[test]
+    fn render_uses_the_puzzle_notation() {
+        let disk_map = parse_disk_map("12345").unwrap();
+        assert_eq!(
+            render(&disk_map.layout(), disk_map.size()).as_deref(),
+            Some("0..111....22222")
+        );
+        assert_eq!(
+            render(&disk_map.compact_blocks(), disk_map.size()).as_deref(),
+            Some("022111222......")
+        );
+    }
 
-    let checksum = {
-        let mut accum = 0;
-        for seg in segments_v2 {
-            for j in 0..seg.width {
-                let i = seg.offset + j;
-                accum += i * seg.id;
+    /// The disk as one entry per block, holding the id of the file stored there
+    fn blocks(layout: &[Extent], disk_size: usize) -> Vec<Option<usize>> {
+        let mut blocks = vec![None; disk_size];
+        for ext in layout {
+            blocks[ext.offset..ext.offset + ext.width].fill(Some(ext.id));
+        }
+        blocks
+    }
+
+    /// Part 2 done the slow way: scan the whole disk from the left for each file
+    fn compact_files_naive(disk_map: &DiskMap) -> Vec<Option<usize>> {
+        let layout = disk_map.layout();
+        let mut disk = blocks(&layout, disk_map.size());
+        for file in layout.iter().rev() {
+            let gap_start = (0..file.offset).find(|&start| {
+                start + file.width <= file.offset
+                    && disk[start..start + file.width].iter().all(Option::is_none)
+            });
+            if let Some(start) = gap_start {
+                disk[file.offset..file.offset + file.width].fill(None);
+                disk[start..start + file.width].fill(Some(file.id));
             }
         }
-        accum
-    };
-    info!("part 2 checksum: {}", checksum);
+        disk
+    }
 
+    #[test]
+    fn compact_files_matches_a_naive_scan() {
+        let mut rng = XorShift::new(0x2f6b_1c3e_85d4_a907);
+        for _ in 0..200 {
+            let digits = (0..rng.below(40) + 1)
+                .map(|i| {
+                    // files are never empty, but gaps can be
+                    let min = if i % 2 == 0 { 1 } else { 0 };
+                    char::from_digit((min + rng.below(10 - min)) as u32, 10).unwrap()
+                })
+                .collect::<String>();
+            let disk_map = parse_disk_map(&digits).unwrap();
+            assert_eq!(
+                blocks(&disk_map.compact_files(), disk_map.size()),
+                compact_files_naive(&disk_map),
+                "disk map {}",
+                digits
+            );
+        }
+    }
 }
-
-// fn find_gap(segments: &Vec<Segment>, width: usize, max_offset: usize) -> usize {}
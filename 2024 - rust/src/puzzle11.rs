@@ -1,7 +1,9 @@
 use crate::helper::GenResult;
+use aoc_core::multiset::CountedMultiset;
+use aoc_core::parse::InputSpan;
 use colored::Colorize;
 use log::{debug, info};
-use std::collections::HashMap;
+use smallvec::{smallvec, SmallVec};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -12,47 +14,48 @@ pub fn run(input_path: &Path) -> GenResult<()> {
         let mut reader = BufReader::new(file);
         let mut s = String::new();
         reader.read_line(&mut s)?;
-        s.split_whitespace()
-            .map(|n| Stone(n.parse::<u64>().unwrap()))
+        InputSpan::new(0, s.trim_end())
+            .list::<u64>(' ')?
+            .into_iter()
+            .map(Stone)
             .collect::<Vec<_>>()
     };
 
     info!("Initial stones: {:?}", initial_stones);
 
-    let initial_counts = {
-        let mut counts = HashMap::new();
-        for stone in &initial_stones {
-            *counts.entry(*stone).or_insert(0) += 1;
-        }
-        counts
-    };
+    let initial_counts = initial_stones
+        .iter()
+        .copied()
+        .collect::<CountedMultiset<_>>();
 
     info!("Initial counts: {:?}", initial_counts);
 
-    let mut counts = initial_counts.clone();
-
-    for n in 0..75 {
-        counts = evolve_by_counts(counts);
-
-        if counts.len() < 100 {
+    initial_counts.evolve_n(NUM_BLINKS, Stone::evolve, |n, counts| {
+        if counts.distinct() < 100 {
             debug!("Stone Counts: {:?}", counts);
         }
 
-        report_counts(n + 1, score(&counts));
-    }
+        report_counts(n, counts.total(), counts.distinct());
+    });
 
     Ok(())
 }
 
-fn report_counts(n: u32, count: u64) {
-    if n == 25 || n == 75 {
+const NUM_BLINKS: usize = 75;
+
+fn report_counts(n: usize, count: u128, distinct: usize) {
+    if n == 25 || n == NUM_BLINKS {
         info!(
-            "After {} blinks: {} stones",
+            "After {} blinks: {} stones ({} distinct)",
             n.to_string().green(),
-            count.to_string().green()
+            count.to_string().green(),
+            distinct
         );
     } else {
-        info!("After {} blinks, {} stones", n, count);
+        info!(
+            "After {} blinks, {} stones ({} distinct)",
+            n, count, distinct
+        );
     }
 }
 
@@ -60,42 +63,59 @@ fn report_counts(n: u32, count: u64) {
 struct Stone(u64);
 
 impl Stone {
-    fn evolve(self) -> Vec<Stone> {
+    fn evolve(&self) -> SmallVec<[Stone; 2]> {
         let n = self.0;
         // Rule 1: 0 becomes 1
         if n == 0 {
-            return vec![Stone(1)];
+            return smallvec![Stone(1)];
         }
 
         // Rule 2: even-digit stones get split
         let s = n.to_string();
         if s.len() % 2 == 0 {
             let (prefix, suffix) = s.split_at(s.len() / 2);
-            return vec![
+            return smallvec![
                 Stone(prefix.parse().unwrap()),
                 Stone(suffix.parse().unwrap()),
             ];
         }
 
         // Rule 3: multiply by 2024
-        vec![Stone(n * 2024)]
+        smallvec![Stone(n * 2024)]
     }
 }
 
-/// Since we are only *counting* the number of stones and order doesn't matter,
-/// we'll only keep track of how many of each unique stone (by its u64 id) there are.
-/// When a stone would "split", the result is multiplied by however many of that
-/// stone there currently are, rather than individual stones being added to a Vec.
-fn evolve_by_counts(stones: HashMap<Stone, u64>) -> HashMap<Stone, u64> {
-    let mut next_counts = HashMap::new();
-    for (stone, count) in stones {
-        for s2 in stone.evolve() {
-            *next_counts.entry(s2).or_insert(0) += count;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::multiset::ExpansionCounter;
+
+    #[test]
+    fn example_stones() {
+        let stones = [Stone(125), Stone(17)]
+            .into_iter()
+            .collect::<CountedMultiset<_>>();
+        let after_6 = stones.evolve_n(6, Stone::evolve, |_, _| {});
+        assert_eq!(after_6.total(), 22);
+        assert_eq!(after_6.count_of(&Stone(2)), 4);
+        let after_25 = stones.evolve_n(25, Stone::evolve, |_, _| {});
+        assert_eq!(after_25.total(), 55312);
     }
-    next_counts
-}
 
-fn score(stone_counts: &HashMap<Stone, u64>) -> u64 {
-    stone_counts.values().sum()
+    #[test]
+    fn depth_first_expansion_agrees_with_step_by_step_counts() {
+        let stones = [0, 1, 10, 99, 999, 2024, 125, 17]
+            .map(Stone)
+            .into_iter()
+            .collect::<CountedMultiset<_>>();
+        let mut counter = ExpansionCounter::new(Stone::evolve);
+        stones.evolve_n(NUM_BLINKS, Stone::evolve, |n, counts| {
+            assert_eq!(
+                counter.count_all(&stones, n),
+                counts.total(),
+                "after {} blinks",
+                n
+            );
+        });
+    }
 }
@@ -1,40 +1,26 @@
-use crate::helper::GenResult;
+use crate::helper::{GenResult, ParseError};
+use aoc_core::parse::{self, InputSpan};
 use colored::Colorize;
-use log::info;
-use regex::Regex;
+use log::{debug, info};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
+mod linear;
+
 pub fn run(input_path: &Path) -> GenResult<()> {
     // parse input file
     let machines = {
         let file = File::open(input_path)?;
         let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-        let mut out = Vec::new();
-
-        // each "machine" is represented as three lines, followed by either a blank line or EOF
-        while let Some(button_a_line) = lines.next() {
-            let button_a_line = button_a_line?;
-            let button_b_line = lines.next().transpose()?.ok_or("missing Button B line")?;
-            let prize_line = lines.next().transpose()?.ok_or("missing Prize line")?;
-            if let Some(blank_line) = lines.next() {
-                let blank_line = blank_line?;
-                if !blank_line.is_empty() {
-                    None.ok_or(format!("unexpected non-blank line: {}", blank_line))?;
-                }
-            }
-
-            out.push(Machine::from_input_lines(
-                button_a_line,
-                button_b_line,
-                prize_line,
-            )?);
-        }
+        let lines = reader.lines().collect::<Result<Vec<_>, _>>()?;
 
-        out
+        // each "machine" is represented as three lines, separated by blank lines
+        parse::sections(&lines)
+            .iter()
+            .map(|section| Machine::from_input_section(section))
+            .collect::<Result<Vec<_>, _>>()?
     };
 
     let mut p1_cost = 0;
@@ -45,7 +31,7 @@ pub fn run(input_path: &Path) -> GenResult<()> {
         info!("Machine {}:", i + 1);
 
         info!("  p1: {}", machine);
-        if let Some(solution) = solve(machine) {
+        if let Some(solution) = solve(machine)? {
             info!(
                 "    solved with cost {} via {:?} ",
                 solution.cost().to_string().bright_blue(),
@@ -65,7 +51,7 @@ pub fn run(input_path: &Path) -> GenResult<()> {
 
         info!("  p2: {}", p2_machine);
 
-        if let Some(solution) = solve(&p2_machine) {
+        if let Some(solution) = solve(&p2_machine)? {
             info!(
                 "    solved with cost {} via {:?} ",
                 solution.cost().to_string().bright_green(),
@@ -111,46 +97,12 @@ impl Display for Machine {
 }
 
 impl Machine {
-    fn from_input_lines(
-        button_a_line: String,
-        button_b_line: String,
-        prize_line: String,
-    ) -> GenResult<Machine> {
-        let button_pattern = Regex::new("^Button [AB]: X\\+(\\d+), Y\\+(\\d+)$")?;
-        let prize_pattern = Regex::new("^Prize: X=(\\d+), Y=(\\d+)$")?;
-
-        // capture X and Y values from the "Button A" line
-        let (_, [adx, ady]) = button_pattern
-            .captures(&button_a_line)
-            .ok_or(format!(
-                "Couldn't capture info from A line: {}",
-                button_a_line
-            ))?
-            .extract();
-        let adx: usize = adx.parse()?;
-        let ady: usize = ady.parse()?;
-
-        // capture X and Y values from the "Button B" line
-        let (_, [bdx, bdy]) = button_pattern
-            .captures(&button_b_line)
-            .ok_or(format!(
-                "Couldn't capture info from B line: {}",
-                button_b_line
-            ))?
-            .extract();
-        let bdx: usize = bdx.parse()?;
-        let bdy: usize = bdy.parse()?;
-
-        // capture X and Y values from the "Prize" line
-        let (_, [prize_x, prize_y]) = prize_pattern
-            .captures(&prize_line)
-            .ok_or(format!(
-                "Couldn't capture info from Prize line: {}",
-                prize_line
-            ))?
-            .extract();
-        let prize_x: usize = prize_x.parse()?;
-        let prize_y: usize = prize_y.parse()?;
+    /// Parse the `Button A`, `Button B` and `Prize` lines describing a machine
+    fn from_input_section(section: &[InputSpan]) -> Result<Machine, ParseError> {
+        let [button_a_line, button_b_line, prize_line] = parse::expect_lines(section)?;
+        let [adx, ady] = button_a_line.key_value("Button A")?.integers_n()?;
+        let [bdx, bdy] = button_b_line.key_value("Button B")?.integers_n()?;
+        let [prize_x, prize_y] = prize_line.key_value("Prize")?.integers_n()?;
 
         Ok(Machine {
             a_deltas: (adx, ady),
@@ -168,11 +120,17 @@ struct ButtonsPressed {
 
 impl ButtonsPressed {
     fn cost(&self) -> usize {
-        (self.num_a_presses * 3) + (self.num_b_presses)
+        (self.num_a_presses * A_COST) + (self.num_b_presses * B_COST)
     }
 }
 
-fn solve(machine: &Machine) -> Option<ButtonsPressed> {
+/// Costs of pressing the A and B buttons
+const A_COST: usize = 3;
+const B_COST: usize = 1;
+
+/// The cheapest way to win the prize, if there is one.
+/// Fails if the solver comes up with presses that don't actually reach the prize.
+fn solve(machine: &Machine) -> Result<Option<ButtonsPressed>, String> {
     // Solving the machine can be done with arithmetic instead of a search.
     // Let `x` be the number of times Button A is pressed.
     // Let `y` be the number of times Button B is pressed.
@@ -184,52 +142,171 @@ fn solve(machine: &Machine) -> Option<ButtonsPressed> {
     // Then represent it as a matrix multiplication problem:
     //   [ machine.a_deltas.0   machine.b_deltas.0 ] * [ x ] = [ machine.prize_pos.0 ]
     //   [ machine.a_deltas.1   machine.b_deltas.1 ]   [ y ]   [ machine.prize_pos.1 ]
-    // Then apply Cramer's rule:
-    //   For any Ax = b equation (where `A` is an NxN matrix, `x` is a column vector of the unknowns,
-    //   and B is a column vector of size N), if det(A) is nonzero, then each `i`th unknown can be solved
-    //   by taking `det(Ai) / det(A)`, where `Ai` is the result of replacing the `i`th column in `A` with `b`.
-    // Note: det([ a  b ]) = (a*d) - (c*b)
-    //           [ c  d ]
-
-    let a = machine.a_deltas.0 as i128;
-    let b = machine.b_deltas.0 as i128;
-    let c = machine.prize_pos.0 as i128;
-
-    let d = machine.a_deltas.1 as i128;
-    let e = machine.b_deltas.1 as i128;
-    let f = machine.prize_pos.1 as i128;
-
-    // [ a, b ] * [ x ] = [ c ]
-    // [ d, e ]   [ y ]   [ f ]
-
-    let denom = (a * e) - (b * d);
-    let x_num = (c * e) - (f * b);
-    let y_num = (a * f) - (d * c);
-
-    if denom == 0 {
-        None
-    } else {
-        let x = x_num / denom;
-        let y = y_num / denom;
+    // If the matrix isn't singular, that has exactly one solution, which is only usable if
+    // both of its values are non-negative integers.
+    let matrix = vec![
+        vec![machine.a_deltas.0 as i128, machine.b_deltas.0 as i128],
+        vec![machine.a_deltas.1 as i128, machine.b_deltas.1 as i128],
+    ];
+    let prize = [machine.prize_pos.0 as i128, machine.prize_pos.1 as i128];
 
-        // can't press a button negative times
-        if x < 0 || y < 0 {
-            return None;
+    let presses = match linear::solve_exact(&matrix, &prize) {
+        Some(solution) => {
+            debug!("    exact solution: {:?}", solution);
+            solution[0].to_integer().zip(solution[1].to_integer())
         }
-        let x = x as usize;
-        let y = y as usize;
+        None => solve_collinear(machine),
+    };
+
+    // can't press a button negative times
+    let Some((x, y)) = presses.filter(|(x, y)| *x >= 0 && *y >= 0) else {
+        return Ok(None);
+    };
+    let solution = ButtonsPressed {
+        num_a_presses: x as usize,
+        num_b_presses: y as usize,
+    };
+
+    // double-check that the presses really do land on the prize
+    let pos_x = (solution.num_a_presses * machine.a_deltas.0)
+        + (solution.num_b_presses * machine.b_deltas.0);
+    let pos_y = (solution.num_a_presses * machine.a_deltas.1)
+        + (solution.num_b_presses * machine.b_deltas.1);
+    if (pos_x, pos_y) != machine.prize_pos {
+        return Err(format!(
+            "bad solution {:?} lands on {:?} instead of the prize at {:?}",
+            solution,
+            (pos_x, pos_y),
+            machine.prize_pos
+        ));
+    }
+
+    Ok(Some(solution))
+}
+
+/// When both buttons move the claw along the same line (so the determinant is zero), there may be
+/// many ways to reach the prize, or none. If the prize is on that line, the two equations are
+/// multiples of each other, so the cheapest solution to either one of them is the answer.
+fn solve_collinear(machine: &Machine) -> Option<(i128, i128)> {
+    let (ax, ay) = (machine.a_deltas.0 as i128, machine.a_deltas.1 as i128);
+    let (bx, by) = (machine.b_deltas.0 as i128, machine.b_deltas.1 as i128);
+    let (px, py) = (machine.prize_pos.0 as i128, machine.prize_pos.1 as i128);
+
+    // the prize is on the buttons' line iff it's collinear with whichever button actually moves
+    let on_line = if (ax, ay) != (0, 0) {
+        ax * py == ay * px
+    } else {
+        bx * py == by * px
+    };
+    if !on_line {
+        debug!("    buttons are collinear, and the prize isn't on their line");
+        return None;
+    }
+
+    // use whichever axis the buttons actually move along
+    let (a, b, target) = if (ax, bx) != (0, 0) {
+        (ax, bx, px)
+    } else {
+        (ay, by, py)
+    };
+    if (a, b) == (0, 0) && (px, py) != (0, 0) {
+        // neither button moves the claw at all
+        return None;
+    }
+    let solution = linear::min_cost_combination(a, b, target, A_COST as i128, B_COST as i128);
+    debug!(
+        "    buttons are collinear; cheapest combination is {:?}",
+        solution
+    );
+    solution
+}
 
-        // rule out non-integer solutions
-        let pos_x = (x * machine.a_deltas.0) + (y * machine.b_deltas.0);
-        let pos_y = (x * machine.a_deltas.1) + (y * machine.b_deltas.1);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if (pos_x, pos_y) == machine.prize_pos {
-            Some(ButtonsPressed {
-                num_a_presses: x,
-                num_b_presses: y,
+    /// The cheapest presses found by trying every count of each button
+    fn brute_force(machine: &Machine) -> Option<ButtonsPressed> {
+        let limit = machine.prize_pos.0.max(machine.prize_pos.1);
+        (0..=limit)
+            .flat_map(|a| (0..=limit).map(move |b| (a, b)))
+            .map(|(num_a_presses, num_b_presses)| ButtonsPressed {
+                num_a_presses,
+                num_b_presses,
             })
-        } else {
-            None
+            .filter(|presses| {
+                let x = presses.num_a_presses * machine.a_deltas.0
+                    + presses.num_b_presses * machine.b_deltas.0;
+                let y = presses.num_a_presses * machine.a_deltas.1
+                    + presses.num_b_presses * machine.b_deltas.1;
+                (x, y) == machine.prize_pos
+            })
+            .min_by_key(ButtonsPressed::cost)
+    }
+
+    #[test]
+    fn example_machines() {
+        let input = std::fs::read_to_string("example_inputs/13.txt").unwrap();
+        let lines = input.lines().map(str::to_string).collect::<Vec<_>>();
+        let machines = parse::sections(&lines)
+            .iter()
+            .map(|section| Machine::from_input_section(section).unwrap())
+            .collect::<Vec<_>>();
+        let costs = machines
+            .iter()
+            .map(|machine| solve(machine).unwrap().map(|presses| presses.cost()))
+            .collect::<Vec<_>>();
+        assert_eq!(costs, [Some(280), None, Some(200), None]);
+    }
+
+    #[test]
+    fn collinear_buttons_match_brute_force() {
+        // both buttons are multiples of the same direction, so `solve_exact` always gives up
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1), (2, 3)] {
+            for (a_scale, b_scale) in [(1, 1), (1, 2), (3, 1), (2, 4), (3, 5), (0, 2), (4, 0)] {
+                for (px, py) in [
+                    (0, 0),
+                    (6, 9),
+                    (12, 18),
+                    (14, 21),
+                    (30, 45),
+                    (7, 10),
+                    (5, 0),
+                ] {
+                    let machine = Machine {
+                        a_deltas: (a_scale * dx, a_scale * dy),
+                        b_deltas: (b_scale * dx, b_scale * dy),
+                        prize_pos: (px, py),
+                    };
+                    assert_eq!(
+                        solve(&machine).unwrap().map(|presses| presses.cost()),
+                        brute_force(&machine).map(|presses| presses.cost()),
+                        "{}",
+                        machine
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn general_machines_match_brute_force() {
+        for a_deltas in [(1, 2), (3, 1), (2, 2), (0, 3), (4, 0)] {
+            for b_deltas in [(2, 1), (1, 3), (1, 1), (5, 0), (0, 2)] {
+                for prize_pos in [(0, 0), (7, 8), (10, 10), (12, 6), (9, 12), (20, 14)] {
+                    let machine = Machine {
+                        a_deltas,
+                        b_deltas,
+                        prize_pos,
+                    };
+                    assert_eq!(
+                        solve(&machine).unwrap().map(|presses| presses.cost()),
+                        brute_force(&machine).map(|presses| presses.cost()),
+                        "{}",
+                        machine
+                    );
+                }
+            }
         }
     }
 }
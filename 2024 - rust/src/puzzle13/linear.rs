@@ -0,0 +1,296 @@
+//! Exact solutions to integer linear systems, with no floating point rounding to worry about.
+//!
+//! `solve_exact` handles square systems with a unique solution, via Gaussian elimination over
+//! rationals. When there's no unique solution (the determinant is zero), `min_cost_combination`
+//! covers the one-equation, two-unknown case that's left over, using the extended gcd.
+
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Div, Mul, Sub};
+
+/// A fraction kept in lowest terms, with a positive denominator
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    pub fn new(num: i128, den: i128) -> Self {
+        assert_ne!(den, 0, "zero denominator");
+        let g = gcd(num, den).max(1) * den.signum();
+        Rational {
+            num: num / g,
+            den: den / g,
+        }
+    }
+
+    pub fn integer(n: i128) -> Self {
+        Rational { num: n, den: 1 }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    /// The value as an integer, if it is one
+    pub fn to_integer(self) -> Option<i128> {
+        (self.den == 1).then_some(self.num)
+    }
+}
+
+impl Debug for Rational {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+
+    fn sub(self, rhs: Rational) -> Rational {
+        Rational::new(self.num * rhs.den - rhs.num * self.den, self.den * rhs.den)
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, rhs: Rational) -> Rational {
+        Rational::new(self.num * rhs.num, self.den * rhs.den)
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+
+    fn div(self, rhs: Rational) -> Rational {
+        Rational::new(self.num * rhs.den, self.den * rhs.num)
+    }
+}
+
+/// Solve `matrix * x = rhs` for `x`, where `matrix` is NxN.
+/// Returns `None` if the matrix is singular, in which case there are either no solutions or
+/// infinitely many. Entries are `i128` so that intermediate products have room to grow.
+pub fn solve_exact(matrix: &[Vec<i128>], rhs: &[i128]) -> Option<Vec<Rational>> {
+    let n = rhs.len();
+    assert!(
+        matrix.len() == n && matrix.iter().all(|row| row.len() == n),
+        "expected a square system"
+    );
+
+    // augmented matrix [ matrix | rhs ]
+    let mut rows = matrix
+        .iter()
+        .zip(rhs)
+        .map(|(row, b)| {
+            row.iter()
+                .chain(std::iter::once(b))
+                .map(|v| Rational::integer(*v))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // forward elimination, to upper triangular form
+    for col in 0..n {
+        let pivot = (col..n).find(|r| !rows[*r][col].is_zero())?;
+        rows.swap(col, pivot);
+        for r in col + 1..n {
+            let factor = rows[r][col] / rows[col][col];
+            if factor.is_zero() {
+                continue;
+            }
+            let pivot_row = rows[col].clone();
+            for (value, pivot_value) in rows[r].iter_mut().zip(pivot_row).skip(col) {
+                *value = *value - factor * pivot_value;
+            }
+        }
+    }
+
+    // back substitution
+    let mut x = vec![Rational::integer(0); n];
+    for r in (0..n).rev() {
+        let mut value = rows[r][n];
+        for c in r + 1..n {
+            value = value - rows[r][c] * x[c];
+        }
+        x[r] = value / rows[r][r];
+    }
+    Some(x)
+}
+
+/// Find non-negative integers `x` and `y` with `a * x + b * y = target` that minimize
+/// `x_cost * x + y_cost * y`, if any exist. Costs and coefficients must be non-negative.
+///
+/// All integer solutions have the form `x = x0 + k * (b / g)`, `y = y0 - k * (a / g)`, where
+/// `g = gcd(a, b)` and `(x0, y0)` comes from the extended gcd. The cost is linear in `k`,
+/// so the cheapest solution is at one end of the range of `k` that keeps both non-negative.
+pub fn min_cost_combination(
+    a: i128,
+    b: i128,
+    target: i128,
+    x_cost: i128,
+    y_cost: i128,
+) -> Option<(i128, i128)> {
+    assert!(
+        a >= 0 && b >= 0 && x_cost >= 0 && y_cost >= 0,
+        "expected non-negative coefficients"
+    );
+    if a == 0 && b == 0 {
+        return (target == 0).then_some((0, 0));
+    }
+    let (g, s, t) = extended_gcd(a, b);
+    if target % g != 0 {
+        return None;
+    }
+    let (x0, y0) = (s * (target / g), t * (target / g));
+    let (step_x, step_y) = (b / g, a / g);
+
+    // x >= 0 needs k >= -x0 / step_x, and y >= 0 needs k <= y0 / step_y;
+    // a zero step means that variable never changes, so it must already be non-negative
+    let k_min = match step_x {
+        0 if x0 < 0 => return None,
+        0 => None,
+        _ => Some(div_ceil(-x0, step_x)),
+    };
+    let k_max = match step_y {
+        0 if y0 < 0 => return None,
+        0 => None,
+        _ => Some(div_floor(y0, step_y)),
+    };
+    if let (Some(lo), Some(hi)) = (k_min, k_max) {
+        if lo > hi {
+            return None;
+        }
+    }
+
+    let slope = x_cost * step_x - y_cost * step_y;
+    // a missing bound is always on the side the cost rises towards, since coefficients and costs are non-negative
+    let k = match slope.cmp(&0) {
+        Ordering::Less => k_max.or(k_min)?,
+        Ordering::Greater | Ordering::Equal => k_min.or(k_max)?,
+    };
+    Some((x0 + k * step_x, y0 - k * step_y))
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Returns `(g, s, t)` where `g = gcd(a, b) = a * s + b * t`
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, s, t) = extended_gcd(b, a % b);
+        (g, t, s - (a / b) * t)
+    }
+}
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    a.div_euclid(b)
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -((-a).div_euclid(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_cost_combination_matches_brute_force() {
+        for a in 0..6 {
+            for b in 0..6 {
+                for target in 0..25 {
+                    for (x_cost, y_cost) in [(3, 1), (1, 3), (2, 2), (0, 1), (1, 0), (0, 0)] {
+                        let cost = |(x, y): (i128, i128)| x_cost * x + y_cost * y;
+                        let expected = (0..=target)
+                            .flat_map(|x| (0..=target).map(move |y| (x, y)))
+                            .filter(|(x, y)| a * x + b * y == target)
+                            .map(cost)
+                            .min();
+                        let found = min_cost_combination(a, b, target, x_cost, y_cost);
+                        if let Some((x, y)) = found {
+                            assert!(x >= 0 && y >= 0, "negative presses {:?}", (x, y));
+                            assert_eq!(a * x + b * y, target, "{:?} misses", (x, y));
+                        }
+                        assert_eq!(
+                            found.map(cost),
+                            expected,
+                            "a={} b={} target={} costs=({}, {})",
+                            a,
+                            b,
+                            target,
+                            x_cost,
+                            y_cost
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn min_cost_combination_handles_large_targets() {
+        let target = 10_000_000_000_000 + 18;
+        let (x, y) = min_cost_combination(4, 6, target, 3, 1).unwrap();
+        assert_eq!(4 * x + 6 * y, target);
+        // B is cheaper per unit of distance, so A is only pressed to make up the remainder
+        assert!(x < 3, "pressed A {} times", x);
+    }
+
+    #[test]
+    fn solve_exact_gives_up_on_singular_systems() {
+        // no solutions
+        assert_eq!(solve_exact(&[vec![1, 2], vec![2, 4]], &[3, 7]), None);
+        // infinitely many solutions
+        assert_eq!(solve_exact(&[vec![1, 2], vec![2, 4]], &[3, 6]), None);
+        // the third row is the sum of the first two
+        let matrix = [vec![1, 0, 2], vec![0, 1, 1], vec![1, 1, 3]];
+        assert_eq!(solve_exact(&matrix, &[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn solve_exact_solves_a_3x3_system() {
+        // the first pivot is zero, so rows have to be swapped
+        let matrix = [vec![0, 2, 1], vec![1, -1, 0], vec![3, 0, -2]];
+        let x = solve_exact(&matrix, &[5, -1, -4]).unwrap();
+        let expected = [
+            Rational::new(2, 7),
+            Rational::new(9, 7),
+            Rational::new(17, 7),
+        ];
+        assert_eq!(x, expected);
+        for (row, b) in matrix.iter().zip([5, -1, -4]) {
+            let lhs = row
+                .iter()
+                .zip(&x)
+                .fold(Rational::integer(0), |sum, (m, x)| {
+                    sum - Rational::integer(-m) * *x
+                });
+            assert_eq!(lhs, Rational::integer(b));
+        }
+    }
+
+    #[test]
+    fn rationals_stay_in_lowest_terms() {
+        assert_eq!(Rational::new(4, -6), Rational::new(-2, 3));
+        assert_eq!(Rational::new(0, -5), Rational::integer(0));
+        assert_eq!(Rational::new(6, 3).to_integer(), Some(2));
+        assert_eq!(Rational::new(7, 3).to_integer(), None);
+        assert_eq!(
+            Rational::new(1, 2) / Rational::new(-1, 4),
+            Rational::integer(-2)
+        );
+    }
+}
@@ -1,44 +1,52 @@
 use crate::geometry::{CharGrid, Grid, GridAddress, GridDelta, RenderTileChar};
-use crate::helper::{GenError, GenResult};
+use crate::helper::{parse_arg, AocError, GenResult, ParseError};
+use crate::viewer::{run_viewer, Simulation};
+use aoc_core::parse::InputSpan;
 use colored::{ColoredString, Colorize};
-use log::{debug, info};
-use regex::Regex;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::Range;
 use std::path::Path;
-use std::str::FromStr;
 
-pub fn run(input_path: &Path, is_example: bool) -> GenResult<()> {
+pub fn run(
+    input_path: &Path,
+    is_example: bool,
+    use_viewer: bool,
+    args: &[String],
+) -> GenResult<()> {
     // Parse input
     let init_robots = {
         let mut out = Vec::new();
         let file = File::open(input_path)?;
         let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line?.parse::<Robot>()?;
-            out.push(line);
+        for (i, line) in reader.lines().enumerate() {
+            let robot = Robot::parse(InputSpan::new(i, &line?))?;
+            out.push(robot);
         }
         out
     };
 
-    // Map size is bigger for the real puzzle, but is not part of the input itself
-    let map_size = if is_example {
-        Quadrants {
-            width: 11,
-            height: 7,
-        }
-    } else {
-        Quadrants {
-            width: 101,
-            height: 103,
-        }
-    };
+    let options = parse_options(args, is_example)?;
+    let map_size = options.map_size;
 
     // Report initial state
     let initial_grid = robot_count_grid(&init_robots, map_size.width, map_size.height);
     info!("{}", CharGrid(&initial_grid, map_size));
 
+    if use_viewer {
+        run_viewer(RobotSimulation {
+            robots: init_robots.clone(),
+            grid: initial_grid.clone(),
+            map_size,
+            seconds_elapsed: 0,
+        })?;
+    }
+
     // Compute state after 100 seconds
     {
         let mut robots = init_robots.clone();
@@ -55,32 +63,133 @@ pub fn run(input_path: &Path, is_example: bool) -> GenResult<()> {
         info!("Safety factor after 100 seconds: {}", safety_100);
     }
 
-    // Step 1 second at a time, looking for a Christmas tree picture
+    // Look for the Christmas tree picture
     {
-        let mut robots = init_robots.clone();
-        let mut seconds_elapsed = 0;
-        while seconds_elapsed < 10000 {
-            let grid = robot_count_grid(&robots, map_size.width, map_size.height);
-            let score = maybe_xmas(&robots, &grid);
-            if score > 1000 {
+        let period = find_period(&init_robots, map_size);
+        info!(
+            "Robot positions repeat every {} seconds ({} horizontally, {} vertically)",
+            period.total, period.x, period.y
+        );
+        let crt_seconds = crt_variance_minimum(&init_robots, map_size, &period);
+        if let Some(seconds) = crt_seconds {
+            info!(
+                "Positions are most tightly clustered after {} seconds (by CRT on the x and y variance minima)",
+                seconds.to_string().bright_blue()
+            );
+        }
+
+        // there's nothing new to see after one full period
+        let steps = options.steps.unwrap_or(0..period.total);
+        let ranked = rank_frames(&init_robots, map_size, steps.clone());
+        info!(
+            "Top {} of {} frames between {} and {} seconds:",
+            options.top_k.min(ranked.len()),
+            ranked.len(),
+            steps.start,
+            steps.end
+        );
+        for (rank, stats) in ranked.iter().take(options.top_k).enumerate() {
+            let grid = robot_count_grid(
+                &robots_at(&init_robots, stats.seconds),
+                map_size.width,
+                map_size.height,
+            );
+            info!("#{}: {}\n{}", rank + 1, stats, CharGrid(&grid, map_size));
+        }
+        match tree_candidate(crt_seconds, &ranked) {
+            Some(TreeCandidate {
+                seconds,
+                rank_sum_best,
+            }) => {
                 info!(
-                    "Possible Xmas tree at {} seconds with score {}:\n{}",
-                    seconds_elapsed,
-                    score,
-                    CharGrid(&grid, map_size)
+                    "Most likely Xmas tree at {} seconds",
+                    seconds.to_string().green()
                 );
-                break;
-            }
-            for robot in &mut robots {
-                robot.step(1);
+                if let Some(best) = rank_sum_best.filter(|best| *best != seconds) {
+                    let rank = ranked.iter().position(|stats| stats.seconds == seconds);
+                    warn!(
+                        "The frame statistics disagree: they rank {} seconds best, and {} seconds #{}",
+                        best,
+                        seconds,
+                        rank.map_or("?".to_string(), |r| (r + 1).to_string())
+                    );
+                }
             }
-            seconds_elapsed += 1;
+            None => info!("No frames to search for the Xmas tree"),
         }
     }
 
     Ok(())
 }
 
+/// Settings that can be overridden with `key=value` arguments
+struct Options {
+    /// `size=<width>x<height>`, the map size
+    map_size: Quadrants,
+    /// `steps=<start>..<end>`, which seconds to search for the picture (defaults to one full period)
+    steps: Option<Range<usize>>,
+    /// `top=<k>`, how many of the best-ranked frames to print
+    top_k: usize,
+}
+
+/// How many frames to print by default
+const DEFAULT_TOP_K: usize = 3;
+
+fn parse_options(args: &[String], is_example: bool) -> GenResult<Options> {
+    // Map size is bigger for the real puzzle, but is not part of the input itself
+    let mut options = Options {
+        map_size: if is_example {
+            Quadrants {
+                width: 11,
+                height: 7,
+            }
+        } else {
+            Quadrants {
+                width: 101,
+                height: 103,
+            }
+        },
+        steps: None,
+        top_k: DEFAULT_TOP_K,
+    };
+    for arg in args {
+        let parse_pair = |value: &str, separator: &str| -> GenResult<(usize, usize)> {
+            let (a, b) = value.split_once(separator).ok_or_else(|| {
+                AocError::Args(format!(
+                    "expected two numbers separated by {:?} in {:?}",
+                    separator, arg
+                ))
+            })?;
+            Ok((parse_arg("number", a)?, parse_arg("number", b)?))
+        };
+        match arg.split_once('=') {
+            Some(("size", value)) => {
+                let (width, height) = parse_pair(value, "x")?;
+                if width == 0 || height == 0 {
+                    Err(AocError::Args(format!("map size can't be empty: {:?}", arg)))?
+                }
+                options.map_size = Quadrants { width, height };
+            }
+            Some(("steps", value)) => {
+                let (start, end) = parse_pair(value, "..")?;
+                if start >= end {
+                    Err(AocError::Args(format!(
+                        "steps range must be non-empty, with the start before the end: {:?}",
+                        arg
+                    )))?
+                }
+                options.steps = Some(start..end);
+            }
+            Some(("top", value)) => options.top_k = parse_arg("top", value)?,
+            _ => Err(AocError::Args(format!(
+                "unexpected argument for puzzle 14: {:?} (expected size=WxH, steps=START..END or top=K)",
+                arg
+            )))?,
+        }
+    }
+    Ok(options)
+}
+
 #[derive(Copy, Clone)]
 struct Robot {
     pos: (i64, i64),
@@ -93,6 +202,17 @@ impl Robot {
         self.pos.1 += self.velocity.1 * num_seconds as i64;
     }
 
+    /// Parse a `p=x,y v=dx,dy` line from the puzzle input
+    fn parse(line: InputSpan) -> Result<Robot, ParseError> {
+        let (pos, velocity) = line.split_once(" ")?;
+        let [px, py] = pos.strip_prefix("p=")?.integers_n()?;
+        let [vx, vy] = velocity.strip_prefix("v=")?.integers_n()?;
+        Ok(Robot {
+            pos: (px, py),
+            velocity: (vx, vy),
+        })
+    }
+
     fn wrapped_pos(&self, width: usize, height: usize) -> (usize, usize) {
         let (x, y) = self.pos;
         let mut wx = x % (width as i64);
@@ -111,22 +231,6 @@ impl Robot {
     }
 }
 
-impl FromStr for Robot {
-    type Err = GenError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pattern = Regex::new(r#"^p=(\d+),(\d+) v=(-?\d+),(-?\d+)$"#)?;
-        let (_, [px, py, vx, vy]) = pattern
-            .captures(s)
-            .ok_or(format!("unmatched input: {}", s))?
-            .extract();
-        Ok(Robot {
-            pos: (px.parse()?, py.parse()?),
-            velocity: (vx.parse()?, vy.parse()?),
-        })
-    }
-}
-
 impl Display for Robot {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -195,24 +299,370 @@ impl RenderTileChar<usize> for Quadrants {
     }
 }
 
-fn maybe_xmas(robots: &Vec<Robot>, count_grid: &Grid<usize>) -> usize {
-    let mut adjacency_score = 0;
+/// Robot positions over time, for use with the interactive viewer.
+/// The simulation never ends on its own, since the robots just keep wrapping around the map.
+#[derive(Clone)]
+struct RobotSimulation {
+    robots: Vec<Robot>,
+    grid: Grid<usize>,
+    map_size: Quadrants,
+    seconds_elapsed: u32,
+}
 
-    for robot in robots {
-        let (x, y) = robot.wrapped_pos(count_grid.width(), count_grid.height());
-        let addr = GridAddress(x, y);
-
-        // check if every tile adjacent to this robot is occupied by at least one other robot
-        for delta in GridDelta::CARDINALS_AND_DIAGONALS {
-            if let Some(adjacent_addr) = addr.checked_add(delta) {
-                if let Some(num_adjacent) = count_grid.get_at(adjacent_addr) {
-                    if *num_adjacent > 0 {
-                        adjacency_score += 1;
+impl Simulation for RobotSimulation {
+    type Tile = usize;
+    type Style = Quadrants;
+
+    fn step(&mut self) -> bool {
+        for robot in &mut self.robots {
+            robot.step(1);
+        }
+        self.grid = robot_count_grid(&self.robots, self.map_size.width, self.map_size.height);
+        self.seconds_elapsed += 1;
+        true
+    }
+
+    fn grid(&self) -> &Grid<usize> {
+        &self.grid
+    }
+
+    fn style(&self) -> Quadrants {
+        self.map_size
+    }
+
+    fn caption(&self) -> String {
+        format!("{} seconds elapsed", self.seconds_elapsed)
+    }
+}
+
+fn robots_at(robots: &[Robot], seconds: usize) -> Vec<Robot> {
+    let mut robots = robots.to_vec();
+    for robot in &mut robots {
+        robot.step(seconds as u32);
+    }
+    robots
+}
+
+/// How often the robots' positions repeat, along each axis and overall
+struct Period {
+    x: usize,
+    y: usize,
+    total: usize,
+}
+
+/// Each robot's x coordinate repeats every `width / gcd(vx, width)` seconds (and similarly
+/// for y), so the whole picture repeats at the lcm of those cycles.
+fn find_period(robots: &[Robot], map_size: Quadrants) -> Period {
+    let axis_period = |size: usize, velocity: fn(&Robot) -> i64| {
+        robots.iter().fold(1, |period, robot| {
+            let v = velocity(robot).rem_euclid(size as i64) as usize;
+            lcm(period, size / gcd(v, size))
+        })
+    };
+    let x = axis_period(map_size.width, |robot| robot.velocity.0);
+    let y = axis_period(map_size.height, |robot| robot.velocity.1);
+    Period {
+        x,
+        y,
+        total: lcm(x, y),
+    }
+}
+
+/// The robots' x and y coordinates move independently, so the second where x is least spread
+/// out can be found within the (short) x period, and likewise for y. The second where both are
+/// true at once follows from the Chinese remainder theorem.
+fn crt_variance_minimum(robots: &[Robot], map_size: Quadrants, period: &Period) -> Option<usize> {
+    let least_variance = |period: usize, size: usize, axis: fn(&Robot) -> (i64, i64)| {
+        (0..period).min_by(|t1, t2| {
+            let spread = |t: usize| {
+                variance(robots.iter().map(|robot| {
+                    let (pos, velocity) = axis(robot);
+                    (pos + velocity * t as i64).rem_euclid(size as i64) as f64
+                }))
+            };
+            spread(*t1).total_cmp(&spread(*t2))
+        })
+    };
+    let tx = least_variance(period.x, map_size.width, |robot| {
+        (robot.pos.0, robot.velocity.0)
+    })?;
+    let ty = least_variance(period.y, map_size.height, |robot| {
+        (robot.pos.1, robot.velocity.1)
+    })?;
+    debug!(
+        "Least x variance at {} (mod {}), least y variance at {} (mod {})",
+        tx, period.x, ty, period.y
+    );
+    crt(tx, period.x, ty, period.y)
+}
+
+fn variance(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let (count, sum) = values
+        .clone()
+        .fold((0.0, 0.0), |(n, s), v| (n + 1.0, s + v));
+    if count == 0.0 {
+        return 0.0;
+    }
+    let mean = sum / count;
+    values.map(|v| (v - mean) * (v - mean)).sum::<f64>() / count
+}
+
+/// Size (in tiles) of the square blocks that robots are binned into when measuring entropy
+const ENTROPY_BLOCK_SIZE: usize = 4;
+
+/// Measures of how "organized" the robots look at a given second.
+/// A picture should have low variance and entropy, and a large connected component.
+struct FrameStats {
+    seconds: usize,
+    /// Sum of the x and y variances of the robots' positions
+    variance: f64,
+    /// Shannon entropy (in bits) of the robot counts per block
+    entropy: f64,
+    /// Number of tiles in the largest group of orthogonally-adjacent occupied tiles
+    largest_component: usize,
+}
+
+impl FrameStats {
+    fn measure(robots: &[Robot], map_size: Quadrants, seconds: usize) -> Self {
+        let robots = robots_at(robots, seconds);
+        let positions = robots
+            .iter()
+            .map(|robot| robot.wrapped_pos(map_size.width, map_size.height))
+            .collect::<Vec<_>>();
+        let grid = robot_count_grid(&robots, map_size.width, map_size.height);
+
+        let variance = variance(positions.iter().map(|(x, _)| *x as f64))
+            + variance(positions.iter().map(|(_, y)| *y as f64));
+
+        let mut blocks = HashMap::<(usize, usize), usize>::new();
+        for (x, y) in &positions {
+            *blocks
+                .entry((x / ENTROPY_BLOCK_SIZE, y / ENTROPY_BLOCK_SIZE))
+                .or_default() += 1;
+        }
+        let total = positions.len() as f64;
+        let entropy = blocks
+            .values()
+            .map(|count| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum();
+
+        FrameStats {
+            seconds,
+            variance,
+            entropy,
+            largest_component: largest_component(&grid),
+        }
+    }
+}
+
+impl Display for FrameStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "after {} seconds: variance {:.1}, entropy {:.3} bits, largest cluster {} tiles",
+            self.seconds, self.variance, self.entropy, self.largest_component
+        )
+    }
+}
+
+/// The frame chosen as the most likely picture of the tree
+#[derive(Debug, PartialEq)]
+struct TreeCandidate {
+    seconds: usize,
+    /// The best-ranked frame, if `seconds` came from the CRT estimate instead
+    rank_sum_best: Option<usize>,
+}
+
+/// Pick the CRT estimate, when it's among the `ranked` frames, since it accounts for the whole
+/// period at once; otherwise fall back to the best-ranked frame
+fn tree_candidate(crt_seconds: Option<usize>, ranked: &[FrameStats]) -> Option<TreeCandidate> {
+    let rank_sum_best = ranked.first().map(|stats| stats.seconds);
+    match crt_seconds {
+        Some(seconds) if ranked.iter().any(|stats| stats.seconds == seconds) => {
+            Some(TreeCandidate {
+                seconds,
+                rank_sum_best,
+            })
+        }
+        _ => rank_sum_best.map(|seconds| TreeCandidate {
+            seconds,
+            rank_sum_best: None,
+        }),
+    }
+}
+
+/// Measure every frame in the range, ranked best-first by the sum of their ranks under each measure
+fn rank_frames(robots: &[Robot], map_size: Quadrants, steps: Range<usize>) -> Vec<FrameStats> {
+    let frames = steps
+        .into_par_iter()
+        .map(|seconds| FrameStats::measure(robots, map_size, seconds))
+        .collect::<Vec<_>>();
+
+    let mut rank_sums = vec![0; frames.len()];
+    let mut add_ranks = |cmp: &dyn Fn(&FrameStats, &FrameStats) -> Ordering| {
+        let mut order = (0..frames.len()).collect::<Vec<_>>();
+        order.sort_by(|i, j| cmp(&frames[*i], &frames[*j]));
+        for (rank, i) in order.into_iter().enumerate() {
+            rank_sums[i] += rank;
+        }
+    };
+    add_ranks(&|a, b| a.variance.total_cmp(&b.variance));
+    add_ranks(&|a, b| a.entropy.total_cmp(&b.entropy));
+    add_ranks(&|a, b| b.largest_component.cmp(&a.largest_component));
+
+    let mut ranked = frames.into_iter().zip(rank_sums).collect::<Vec<_>>();
+    ranked.sort_by_key(|(stats, rank_sum)| (*rank_sum, stats.seconds));
+    ranked.into_iter().map(|(stats, _)| stats).collect()
+}
+
+fn largest_component(grid: &Grid<usize>) -> usize {
+    let mut visited = Grid::<bool>::new_default(grid.width(), grid.height());
+    let mut largest = 0;
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            if grid.rows[y][x] == 0 || visited.rows[y][x] {
+                continue;
+            }
+            visited.rows[y][x] = true;
+            let mut size = 0;
+            let mut stack = vec![GridAddress(x, y)];
+            while let Some(addr) = stack.pop() {
+                size += 1;
+                for delta in GridDelta::CARDINALS {
+                    let Some(next) = addr.checked_add(delta) else {
+                        continue;
+                    };
+                    if grid.get_at(next).is_some_and(|count| *count > 0) && !visited[next] {
+                        visited[next] = true;
+                        stack.push(next);
                     }
                 }
             }
+            largest = largest.max(size);
+        }
+    }
+    largest
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+/// Find the smallest `t` where `t = a1 (mod m1)` and `t = a2 (mod m2)`, if there is one.
+/// The moduli don't need to be coprime.
+fn crt(a1: usize, m1: usize, a2: usize, m2: usize) -> Option<usize> {
+    let g = gcd(m1, m2);
+    let diff = a2 as i128 - a1 as i128;
+    if diff % g as i128 != 0 {
+        return None;
+    }
+    // find k such that a1 + k * m1 = a2 (mod m2), i.e. k * (m1 / g) = diff / g (mod m2 / g)
+    let m = (m2 / g) as i128;
+    let k = (diff / g as i128).rem_euclid(m) * mod_inverse((m1 / g) as i128, m) % m;
+    Some((a1 as i128 + k * m1 as i128).rem_euclid(lcm(m1, m2) as i128) as usize)
+}
+
+/// Inverse of `a` modulo `m`, assuming they're coprime
+fn mod_inverse(a: i128, m: i128) -> i128 {
+    // extended Euclid, only tracking the coefficient of `a`
+    let (mut r0, mut r1) = (a.rem_euclid(m), m);
+    let (mut s0, mut s1) = (1, 0);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+    s0.rem_euclid(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn stats(seconds: usize) -> FrameStats {
+        FrameStats {
+            seconds,
+            variance: 0.0,
+            entropy: 0.0,
+            largest_component: 0,
         }
     }
 
-    adjacency_score
+    #[test]
+    fn steps_range_must_go_forwards() {
+        let options = parse_options(&args(&["steps=10..20"]), true).unwrap();
+        assert_eq!(options.steps, Some(10..20));
+        for bad in ["steps=20..10", "steps=5..5"] {
+            assert!(
+                matches!(parse_options(&args(&[bad]), true), Err(AocError::Args(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn crt_estimate_is_preferred_when_it_was_ranked() {
+        let ranked = [stats(13), stats(24), stats(5)];
+        assert_eq!(
+            tree_candidate(Some(24), &ranked),
+            Some(TreeCandidate {
+                seconds: 24,
+                rank_sum_best: Some(13),
+            })
+        );
+        // outside the searched steps
+        assert_eq!(
+            tree_candidate(Some(50), &ranked),
+            Some(TreeCandidate {
+                seconds: 13,
+                rank_sum_best: None,
+            })
+        );
+        assert_eq!(tree_candidate(Some(50), &[]), None);
+    }
+
+    #[test]
+    fn crt_finds_a_planted_cluster() {
+        let map_size = Quadrants {
+            width: 11,
+            height: 7,
+        };
+        let planted = 40;
+        // robots that all end up within a tile of the middle after `planted` seconds
+        let robots = (0..30)
+            .map(|i: i64| {
+                let velocity = (i % 5 + 1, -(i % 3) - 1);
+                let target = (5 + i % 3 - 1, 3 + i % 2);
+                Robot {
+                    pos: (
+                        (target.0 - velocity.0 * planted).rem_euclid(11),
+                        (target.1 - velocity.1 * planted).rem_euclid(7),
+                    ),
+                    velocity,
+                }
+            })
+            .collect::<Vec<_>>();
+        let period = find_period(&robots, map_size);
+        assert_eq!((period.x, period.y, period.total), (11, 7, 77));
+        assert_eq!(
+            crt_variance_minimum(&robots, map_size, &period),
+            Some(planted as usize)
+        );
+    }
 }
@@ -1,67 +1,52 @@
-use crate::geometry::{Cardinal, CharGrid, Grid, GridAddress, RenderTileChar};
-use crate::helper::GenResult;
+use crate::geometry::{Cardinal, CharGrid, DiffCharGrid, Grid, GridAddress, RenderTileChar};
+use crate::helper::{parse_arg, AocError, GenResult, ParseError};
+use crate::viewer::{run_viewer, Simulation};
 use colored::{ColoredString, Colorize};
-use log::{debug, info};
+use log::{debug, info, log_enabled, Level};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
+pub fn run(input_path: &Path, use_viewer: bool, args: &[String]) -> GenResult<()> {
+    // extra arguments ask for another variant, with the map scaled by some other amount
+    let extra_scale = match args {
+        [] => None,
+        [width] => Some((parse_arg("width scale", width)?, 1)),
+        [width, height] => Some((
+            parse_arg("width scale", width)?,
+            parse_arg("height scale", height)?,
+        )),
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 15: {:?}",
+            args
+        )))?,
+    };
+    if extra_scale.is_some_and(|(width, height): (usize, usize)| width == 0 || height == 0) {
+        Err(AocError::Args(
+            "scale factors must be at least 1".to_string(),
+        ))?
+    }
+
     // Parse Input
-    let (grid, directions, initial_robot_pos) = {
+    let (layout, directions) = {
         let file = File::open(input_path)?;
-        let reader = BufReader::new(file);
-        let mut rows = Vec::new();
-        let mut directions = Vec::new();
-        let mut robot_pos = None;
-        for line in reader.lines() {
-            let line = line?;
-            if line.starts_with('#') {
-                let row = line
-                    .chars()
-                    .map(Tile::from_char)
-                    .collect::<GenResult<Vec<_>>>()?;
-                if let Some(x) = row.iter().position(|t| *t == Tile::Robot) {
-                    let y = rows.len();
-                    robot_pos = Some(GridAddress(x, y));
-                }
-                rows.push(row);
-            } else {
-                for c in line.chars() {
-                    directions.push(cardinal_from_char(c)?);
-                }
-            }
-        }
-
-        (
-            Grid { rows },
-            directions,
-            robot_pos.ok_or("Couldn't find robot")?,
-        )
+        parse_input(BufReader::new(file))?
     };
 
-    // Part 1
-    {
-        info!("Begin Part 1:");
-        let part1_end_state = run_simulation(grid.clone(), initial_robot_pos, &directions);
-        info!("Part 1 Score: {}", score(&part1_end_state));
+    let mut variants = vec![("Part 1", (1, 1)), ("Part 2", (2, 1))];
+    if let Some(scale) = extra_scale {
+        variants.push(("Scaled", scale));
     }
-
-    // Part 2
-    {
-        info!("Begin Part 2:");
-        let part2_end_state = run_simulation(
-            Grid {
-                rows: grid
-                    .rows
-                    .iter()
-                    .map(|row| row.iter().flat_map(|t| t.fatten()).collect())
-                    .collect(),
-            },
-            GridAddress(initial_robot_pos.0 * 2, initial_robot_pos.1),
-            &directions,
-        );
-        info!("Part 2 Score: {}", score(&part2_end_state));
+    for (label, scale) in variants {
+        info!("Begin {} ({}x{} scale):", label, scale.0, scale.1);
+        let warehouse = layout.warehouse(scale, &directions)?;
+        if use_viewer {
+            run_viewer(warehouse.clone())?;
+        }
+        let end_state = run_simulation(warehouse);
+        info!("{} Score: {}", label, end_state.score());
     }
 
     Ok(())
@@ -71,204 +56,543 @@ pub fn run(input_path: &Path) -> GenResult<()> {
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 enum Tile {
     Wall,
-    Box,
-    BoxLeft,
-    BoxRight,
+    /// Part of the crate with the given id; the edge flags say which part, for rendering
+    Crate {
+        id: usize,
+        left_edge: bool,
+        right_edge: bool,
+    },
     Robot,
     Empty,
 }
 
-impl Tile {
-    fn from_char(c: char) -> GenResult<Tile> {
-        match c {
-            '#' => Ok(Tile::Wall),
-            'O' => Ok(Tile::Box),
-            '@' => Ok(Tile::Robot),
-            '.' => Ok(Tile::Empty),
-            _ => Err(format!("Unrecognised tile '{}'", c).into()),
-        }
-    }
-
-    fn fatten(&self) -> [Tile; 2] {
-        match self {
-            Tile::Wall => [Tile::Wall, Tile::Wall],
-            Tile::Box => [Tile::BoxLeft, Tile::BoxRight],
-            Tile::Robot => [Tile::Robot, Tile::Empty],
-            Tile::Empty => [Tile::Empty, Tile::Empty],
-            _ => panic!("Didn't expect to fatten a {:?}", self),
-        }
-    }
-}
-
 // For pretty colors
 impl RenderTileChar<Tile> for () {
     fn render_tile_char(&self, tile: &Tile, _: usize, _: usize) -> ColoredString {
         match tile {
             Tile::Wall => "#".normal(),
-            Tile::Box => "O".bright_blue(),
+            Tile::Crate {
+                left_edge,
+                right_edge,
+                ..
+            } => match (left_edge, right_edge) {
+                (true, true) => "O".bright_blue(),
+                (true, false) => "[".bright_blue(),
+                (false, true) => "]".bright_blue(),
+                (false, false) => "=".bright_blue(),
+            },
             Tile::Robot => "@".yellow(),
             Tile::Empty => ".".bright_black(),
-            Tile::BoxLeft => "[".bright_blue(),
-            Tile::BoxRight => "]".bright_blue(),
         }
     }
 }
 
+/// Parse the warehouse map (the lines starting with `#`) and the robot's instructions
+fn parse_input<R: BufRead>(reader: R) -> GenResult<(Layout, Vec<Cardinal>)> {
+    let mut layout = Layout {
+        width: 0,
+        height: 0,
+        walls: Vec::new(),
+        crates: Vec::new(),
+        robot: None,
+    };
+    let mut directions = Vec::new();
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') {
+            let y = layout.height;
+            for (x, c) in line.chars().enumerate() {
+                let addr = GridAddress(x, y);
+                match c {
+                    '#' => layout.walls.push(addr),
+                    'O' => layout.crates.push(addr),
+                    '@' => layout.robot = Some(addr),
+                    '.' => (),
+                    _ => Err(ParseError::new(format!("Unrecognised tile '{}'", c))
+                        .at_span(line_index, &line, x, 1))?,
+                }
+            }
+            layout.width = layout.width.max(line.len());
+            layout.height += 1;
+        } else {
+            for (x, c) in line.chars().enumerate() {
+                let direction = cardinal_from_char(c).ok_or_else(|| {
+                    ParseError::new(format!("Unrecognised direction '{}'", c))
+                        .at_span(line_index, &line, x, 1)
+                })?;
+                directions.push(direction);
+            }
+        }
+    }
+    Ok((layout, directions))
+}
+
 // For input parsing
-fn cardinal_from_char(c: char) -> GenResult<Cardinal> {
+fn cardinal_from_char(c: char) -> Option<Cardinal> {
     match c {
-        '^' => Ok(Cardinal::North),
-        '>' => Ok(Cardinal::East),
-        'v' => Ok(Cardinal::South),
-        '<' => Ok(Cardinal::West),
-        _ => Err(format!("Unrecognised direction '{}'", c).into()),
+        '^' => Some(Cardinal::North),
+        '>' => Some(Cardinal::East),
+        'v' => Some(Cardinal::South),
+        '<' => Some(Cardinal::West),
+        _ => None,
     }
 }
 
-/// Run the robot through all the directional instructions, pushing boxes around as it goes
-fn run_simulation(
-    mut grid: Grid<Tile>,
-    mut robot_pos: GridAddress,
-    instructions: &Vec<Cardinal>,
-) -> Grid<Tile> {
-    info!("Initial State:\n{}", CharGrid(&grid, ()));
+/// The warehouse map as given in the puzzle input, where everything is one tile in size
+struct Layout {
+    width: usize,
+    height: usize,
+    walls: Vec<GridAddress>,
+    crates: Vec<GridAddress>,
+    robot: Option<GridAddress>,
+}
 
-    for &direction in instructions.iter() {
-        debug!("Move {:?}", direction);
+impl Layout {
+    /// Set up a warehouse where each wall and crate is scaled to `scale` (width, height) tiles.
+    /// The robot stays one tile in size, in the top-left corner of its scaled-up position.
+    fn warehouse<'a>(
+        &self,
+        (scale_x, scale_y): (usize, usize),
+        instructions: &'a [Cardinal],
+    ) -> GenResult<Warehouse<'a>> {
+        let scaled = |GridAddress(x, y): GridAddress| GridAddress(x * scale_x, y * scale_y);
+        let robot_pos = scaled(self.robot.ok_or("Couldn't find robot")?);
 
-        if can_displace_tile(robot_pos, direction, &grid) {
-            displace_tile(robot_pos, direction, &mut grid);
-            robot_pos = robot_pos.checked_add(direction.into()).unwrap()
-        } else {
-            debug!("clunk");
+        let mut grid = Grid {
+            rows: vec![vec![Tile::Empty; self.width * scale_x]; self.height * scale_y],
+        };
+        for wall in &self.walls {
+            let GridAddress(x0, y0) = scaled(*wall);
+            for y in y0..y0 + scale_y {
+                grid.rows[y][x0..x0 + scale_x].fill(Tile::Wall);
+            }
+        }
+        grid[robot_pos] = Tile::Robot;
+
+        let crates = self
+            .crates
+            .iter()
+            .map(|pos| Crate {
+                pos: scaled(*pos),
+                width: scale_x,
+                height: scale_y,
+            })
+            .collect::<Vec<_>>();
+        for (id, c) in crates.iter().enumerate() {
+            c.place(id, &mut grid);
+        }
+
+        Ok(Warehouse {
+            grid,
+            crates,
+            robot_pos,
+            instructions,
+            history: Vec::new(),
+        })
+    }
+}
+
+/// A box in the warehouse, covering a `width` by `height` rectangle of tiles
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Crate {
+    /// The top-left corner
+    pos: GridAddress,
+    width: usize,
+    height: usize,
+}
+
+impl Crate {
+    fn tiles(&self) -> impl Iterator<Item = GridAddress> + '_ {
+        let GridAddress(x0, y0) = self.pos;
+        (y0..y0 + self.height)
+            .flat_map(move |y| (x0..x0 + self.width).map(move |x| GridAddress(x, y)))
+    }
+
+    /// Mark the crate's tiles in the grid as belonging to it
+    fn place(&self, id: usize, grid: &mut Grid<Tile>) {
+        for addr in self.tiles() {
+            grid[addr] = Tile::Crate {
+                id,
+                left_edge: addr.0 == self.pos.0,
+                right_edge: addr.0 == self.pos.0 + self.width - 1,
+            };
         }
+    }
 
-        debug!("State:\n{}", CharGrid(&grid, ()));
+    /// The puzzle's "GPS coordinate", measured from the crate's top-left corner
+    fn gps(&self) -> usize {
+        self.pos.1 * 100 + self.pos.0
     }
-    info!("Final State:\n{}", CharGrid(&grid, ()));
-    grid
 }
 
-/// If the tile at `addr` would be pushed in the given `direction`, find the tile(s)
-/// adjacent to it that would also need to be pushed. This accounts for "wide" tiles
-/// for the Part 2 twist. Non-recursive; only the immediately-adjacent tile(s) are returned.
-fn find_displaced_when_pushed(
-    addr: GridAddress,
+/// What happened on one of the robot's moves
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct MoveReport {
+    /// Index into the robot's instructions
+    index: usize,
     direction: Cardinal,
-    grid: &Grid<Tile>,
-) -> Vec<GridAddress> {
-    let this_tile = grid[addr];
-
-    // depending on the tile being pushed, we may need to displace up to 2 other tiles
-    // (i.e. when pushing a "big" box, both of its sides will move and each may displace
-    // their own respective tiles)
-    match this_tile {
-        Tile::Empty => {
-            panic!("Tried to push an empty tile?")
+    /// Whether the robot actually moved, i.e. nothing was blocked by a wall
+    moved: bool,
+    /// Ids of the crates that were pushed along with the robot
+    pushed: Vec<usize>,
+}
+
+impl Display for MoveReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Move {} ({:?}): ", self.index, self.direction)?;
+        if !self.moved {
+            write!(f, "clunk")
+        } else if self.pushed.is_empty() {
+            write!(f, "moved")
+        } else {
+            write!(f, "pushed crates {:?}", self.pushed)
         }
-        Tile::Wall => {
-            panic!("Tried to push a wall?")
+    }
+}
+
+/// The warehouse state partway through the robot's list of instructions
+#[derive(Clone)]
+struct Warehouse<'a> {
+    /// Rendered view of the state, which also serves as a lookup of what's on each tile
+    grid: Grid<Tile>,
+    /// Every crate, indexed by id
+    crates: Vec<Crate>,
+    robot_pos: GridAddress,
+    instructions: &'a [Cardinal],
+    /// One report per move made so far, for `undo`
+    history: Vec<MoveReport>,
+}
+
+impl Warehouse<'_> {
+    fn next_instruction(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Perform the robot's next move, pushing crates out of the way if possible.
+    /// Returns `None` if there are no moves left.
+    fn apply_next(&mut self) -> Option<MoveReport> {
+        let index = self.next_instruction();
+        let direction = *self.instructions.get(index)?;
+        let report = match self.find_pushed(direction) {
+            Some(pushed) => {
+                self.shift(&pushed, direction);
+                MoveReport {
+                    index,
+                    direction,
+                    moved: true,
+                    pushed,
+                }
+            }
+            None => MoveReport {
+                index,
+                direction,
+                moved: false,
+                pushed: Vec::new(),
+            },
+        };
+        self.history.push(report.clone());
+        Some(report)
+    }
+
+    /// Take back the most recent move, returning its report, or `None` if no moves have been made
+    fn undo(&mut self) -> Option<MoveReport> {
+        let report = self.history.pop()?;
+        if report.moved {
+            // the tiles everything moved out of are still empty, so moving back always works
+            self.shift(&report.pushed, report.direction.opposite());
         }
-        Tile::Box | Tile::Robot => {
-            // a single-tile box only displaces the next tile over
-            addr.checked_add(direction.into()).into_iter().collect()
+        Some(report)
+    }
+
+    /// Undo moves until the instruction at `index` is next
+    fn rewind_to(&mut self, index: usize) {
+        while self.next_instruction() > index {
+            self.undo();
         }
-        Tile::BoxLeft | Tile::BoxRight
-            if direction == Cardinal::East || direction == Cardinal::West =>
-        {
-            // a double-tile box being moved left or right will only really displace
-            // a single tile to that left or right direction, but rather than computing
-            // that displaced tile, we'll just pretend it's a normal box displacing a
-            // tile next to it, and allow the recursion to reach the far side of the box
-            addr.checked_add(direction.into()).into_iter().collect()
+    }
+
+    /// Rewind to the instruction at `index`, then run the remaining moves again,
+    /// returning the reports for the replayed moves
+    fn replay_from(&mut self, index: usize) -> Vec<MoveReport> {
+        self.rewind_to(index);
+        let mut reports = Vec::new();
+        while let Some(report) = self.apply_next() {
+            reports.push(report);
         }
-        Tile::BoxLeft => {
-            // when a double-tile box is pushed up or down, its other side is also pushed
-            // with it, causing its own displacement(s)
-            let left_displaced = addr.checked_add(direction.into());
-            let right_displaced = addr
-                .checked_add(Cardinal::East.into())
-                .and_then(|xy| xy.checked_add(direction.into()));
-            left_displaced
-                .into_iter()
-                .chain(right_displaced.into_iter())
-                .collect()
+        reports
+    }
+
+    /// Find which crates would be pushed if the robot moved in the given direction,
+    /// or `None` if a wall is in the way of the robot or any of those crates
+    fn find_pushed(&self, direction: Cardinal) -> Option<Vec<usize>> {
+        let mut pushed = Vec::new();
+        let mut seen = HashSet::new();
+        // tiles that will move, whose destinations still need checking
+        let mut to_check = vec![self.robot_pos];
+        while let Some(addr) = to_check.pop() {
+            let dest = addr.checked_add(direction.into())?;
+            match self.grid.get_at(dest)? {
+                Tile::Wall => return None,
+                Tile::Crate { id, .. } => {
+                    // a crate may bump into itself, e.g. the right half of a wide crate moving left
+                    if seen.insert(*id) {
+                        pushed.push(*id);
+                        to_check.extend(self.crates[*id].tiles());
+                    }
+                }
+                Tile::Robot | Tile::Empty => (),
+            }
         }
-        Tile::BoxRight => {
-            // same as with BoxLeft case, but the other side is to the West
-            let left_displaced = addr
-                .checked_add(Cardinal::West.into())
-                .and_then(|xy| xy.checked_add(direction.into()));
-            let right_displaced = addr.checked_add(direction.into());
-            left_displaced
-                .into_iter()
-                .chain(right_displaced.into_iter())
-                .collect()
+        Some(pushed)
+    }
+
+    /// Move the robot and the given crates one tile in the given direction, without checking for collisions
+    fn shift(&mut self, crate_ids: &[usize], direction: Cardinal) {
+        // clear everything first, so nothing being moved can overwrite something else being moved
+        for id in crate_ids {
+            for addr in self.crates[*id].tiles() {
+                self.grid[addr] = Tile::Empty;
+            }
+        }
+        self.grid[self.robot_pos] = Tile::Empty;
+
+        for id in crate_ids {
+            let c = &mut self.crates[*id];
+            c.pos = c.pos.checked_add(direction.into()).unwrap();
+            c.place(*id, &mut self.grid);
         }
+        self.robot_pos = self.robot_pos.checked_add(direction.into()).unwrap();
+        self.grid[self.robot_pos] = Tile::Robot;
+    }
+
+    fn score(&self) -> usize {
+        self.crates.iter().map(Crate::gps).sum()
     }
 }
 
-/// Check if a tile in the grid can be "pushed" in a given direction.
-///
-/// `addr` is the address of the tile to be pushed
-/// `grid` is the grid where the tile is stored
-/// `direction` is the direction to push the tile
-fn can_displace_tile(addr: GridAddress, direction: Cardinal, grid: &Grid<Tile>) -> bool {
-    let this_tile = grid[addr];
-
-    match this_tile {
-        Tile::Wall => false,
-        Tile::Empty => true,
-        _ => {
-            // for the various Box tile types, displacing the tile in any direction may
-            // recursively displace other tiles in that same direction; only if all can
-            // be displaced may the tile at `addr` be displaced
-            find_displaced_when_pushed(addr, direction, grid)
-                .into_iter()
-                .all(|to_displace| can_displace_tile(to_displace, direction, grid))
+impl Simulation for Warehouse<'_> {
+    type Tile = Tile;
+    type Style = ();
+
+    fn step(&mut self) -> bool {
+        self.apply_next().is_some()
+    }
+
+    fn grid(&self) -> &Grid<Tile> {
+        &self.grid
+    }
+
+    fn style(&self) {}
+
+    fn caption(&self) -> String {
+        let index = self.next_instruction();
+        match self.instructions.get(index) {
+            Some(next) => format!(
+                "move {}/{}, next {:?}",
+                index,
+                self.instructions.len(),
+                next
+            ),
+            None => "all moves done".to_string(),
         }
     }
 }
 
-/// Move the tile located at `addr` in the given `direction` by one step.
-/// Should be guarded by a call to `can_displace_tile`; may panic or behave unexpectedly otherwise.
-fn displace_tile(addr: GridAddress, direction: Cardinal, grid: &mut Grid<Tile>) {
-    let this_tile = grid[addr];
+/// Run the robot through all the directional instructions, pushing crates around as it goes
+fn run_simulation(mut warehouse: Warehouse) -> Warehouse {
+    info!("Initial State:\n{}", CharGrid(&warehouse.grid, ()));
 
-    match this_tile {
-        Tile::Wall => {
-            panic!("Tried to displace a wall")
+    loop {
+        // remember the previous state so the debug output can highlight what moved
+        let before = log_enabled!(Level::Debug).then(|| warehouse.grid.clone());
+        let Some(report) = warehouse.apply_next() else {
+            break;
+        };
+        if let Some(before) = before {
+            debug!("{}\n{}", report, DiffCharGrid(&before, &warehouse.grid, ()));
         }
-        Tile::Empty => {
-            // nothing to do
+    }
+    info!("Final State:\n{}", CharGrid(&warehouse.grid, ()));
+
+    let (moves, clunks) = warehouse
+        .history
+        .iter()
+        .fold((0, 0), |(moves, clunks), report| {
+            if report.moved {
+                (moves + 1, clunks)
+            } else {
+                (moves, clunks + 1)
+            }
+        });
+    info!(
+        "The robot moved {} times and bumped into walls {} times",
+        moves, clunks
+    );
+    if let Some(biggest) = warehouse
+        .history
+        .iter()
+        .max_by_key(|report| report.pushed.len())
+        .cloned()
+    {
+        // step back to just before the biggest push to show what it moved, then replay the rest
+        warehouse.rewind_to(biggest.index);
+        let before = warehouse.grid.clone();
+        warehouse.apply_next();
+        info!(
+            "Biggest push: {}\n{}",
+            biggest,
+            DiffCharGrid(&before, &warehouse.grid, ())
+        );
+        warehouse.replay_from(biggest.index + 1);
+    }
+    warehouse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::assert_grids_eq;
+    use std::io::Cursor;
+
+    fn parse(input: &str) -> (Layout, Vec<Cardinal>) {
+        parse_input(Cursor::new(input)).unwrap()
+    }
+
+    fn example() -> (Layout, Vec<Cardinal>) {
+        parse(&std::fs::read_to_string("example_inputs/15.txt").unwrap())
+    }
+
+    /// 1-wide, 2-wide, and bigger crates
+    const SCALES: [(usize, usize); 4] = [(1, 1), (2, 1), (3, 1), (3, 2)];
+
+    fn run_all(warehouse: &mut Warehouse) {
+        while warehouse.apply_next().is_some() {}
+    }
+
+    #[test]
+    fn example_scores() {
+        let (layout, directions) = example();
+        for (scale, score) in [((1, 1), 10092), ((2, 1), 9021)] {
+            let mut warehouse = layout.warehouse(scale, &directions).unwrap();
+            run_all(&mut warehouse);
+            assert_eq!(warehouse.score(), score, "{:?}", scale);
         }
-        _ => {
-            for to_displace in find_displaced_when_pushed(addr, direction, grid) {
-                // push any tiles out of the way
-                displace_tile(to_displace, direction, grid);
-
-                // Now that the displaced tiles are out of the way, tiles can move into their place.
-                // To account for double-wide tiles, we'll use `to_displace - direction` instead of `addr`.
-                if let Some(asdf) = to_displace.checked_add(direction.opposite().into()) {
-                    grid[to_displace] = grid[asdf];
-                    grid[asdf] = Tile::Empty;
+    }
+
+    #[test]
+    fn undo_restores_every_earlier_state() {
+        let (layout, directions) = example();
+        for scale in SCALES {
+            let mut warehouse = layout.warehouse(scale, &directions).unwrap();
+            let mut states = Vec::new();
+            loop {
+                let state = (warehouse.grid.clone(), warehouse.crates.clone());
+                match warehouse.apply_next() {
+                    Some(report) => states.push((state, report)),
+                    None => break,
                 }
             }
+            while let Some(((grid, crates), report)) = states.pop() {
+                assert_eq!(warehouse.undo(), Some(report));
+                assert_grids_eq(&grid, &warehouse.grid, ());
+                assert_eq!(crates, warehouse.crates);
+            }
+            assert_eq!(warehouse.undo(), None);
+            assert_eq!(warehouse.next_instruction(), 0);
         }
     }
-}
 
-fn score(grid: &Grid<Tile>) -> usize {
-    let mut accum = 0;
-    for y in 0..grid.height() {
-        for x in 0..grid.width() {
-            match grid[GridAddress(x, y)] {
-                Tile::Box | Tile::BoxLeft => {
-                    accum += (y * 100) + x;
-                }
-                _ => (),
+    #[test]
+    fn replay_reaches_the_same_end_state() {
+        let (layout, directions) = example();
+        for scale in SCALES {
+            let mut end_state = layout.warehouse(scale, &directions).unwrap();
+            run_all(&mut end_state);
+            let len = directions.len();
+            for index in [0, 1, len / 2, len - 1, len] {
+                let mut warehouse = end_state.clone();
+                let reports = warehouse.replay_from(index);
+                assert_eq!(
+                    reports,
+                    end_state.history[index..],
+                    "{:?} from {}",
+                    scale,
+                    index
+                );
+                assert_grids_eq(&end_state.grid, &warehouse.grid, ());
+                assert_eq!(end_state.crates, warehouse.crates);
+                assert_eq!(end_state.robot_pos, warehouse.robot_pos);
             }
         }
     }
-    accum
+
+    #[test]
+    fn one_wide_move_reports() {
+        let (layout, directions) = parse("#######\n#.@OO.#\n#######\n\n>><");
+        let mut warehouse = layout.warehouse((1, 1), &directions).unwrap();
+        let reports = (0..3)
+            .map(|_| warehouse.apply_next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reports,
+            [
+                "Move 0 (East): pushed crates [0, 1]",
+                "Move 1 (East): clunk",
+                "Move 2 (West): moved",
+            ]
+        );
+        assert!(warehouse.apply_next().is_none());
+    }
+
+    #[test]
+    fn wide_crate_pushes_the_two_crates_it_straddles() {
+        let input = "\
+#########
+#.......#
+#..OO...#
+#.......#
+#...O@..#
+#.......#
+#########
+
+<v<<^^";
+        let (layout, directions) = parse(input);
+        let mut warehouse = layout.warehouse((2, 1), &directions).unwrap();
+        for _ in 0..directions.len() - 1 {
+            warehouse.apply_next();
+        }
+        let before = warehouse.grid.clone();
+        let mut report = warehouse.apply_next().unwrap();
+        report.pushed.sort();
+        assert_eq!(report.pushed, [0, 1, 2]);
+        assert_eq!(warehouse.crates[2].pos, GridAddress(7, 2));
+        assert_eq!(warehouse.crates[0].pos, GridAddress(6, 1));
+        assert_eq!(warehouse.crates[1].pos, GridAddress(8, 1));
+
+        warehouse.undo();
+        assert_grids_eq(&before, &warehouse.grid, ());
+    }
+
+    #[test]
+    fn tall_crates_block_as_a_whole() {
+        // at 1x3 scale, the crate's top reaches a wall as soon as it's pushed
+        let (layout, directions) = parse("#####\n#.O.#\n#.@.#\n#####\n\n^");
+        let mut warehouse = layout.warehouse((1, 3), &directions).unwrap();
+        let report = warehouse.apply_next().unwrap();
+        assert!(!report.moved);
+        assert_eq!(warehouse.robot_pos, GridAddress(2, 6));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_bad_character() {
+        let message = |input: &str| parse_input(Cursor::new(input)).err().unwrap().to_string();
+        assert_eq!(
+            message("#####\n#@.x#\n#####\n\n<>\n"),
+            "parse error: Unrecognised tile 'x'\n --> <input>:2:4\n  |\n2 | #@.x#\n  |    ^"
+        );
+        assert_eq!(
+            message("#####\n#@..#\n#####\n\n<>\n^^?v\n"),
+            "parse error: Unrecognised direction '?'\n --> <input>:6:3\n  |\n6 | ^^?v\n  |   ^"
+        );
+    }
 }
@@ -1,19 +1,27 @@
-use crate::geometry::{Cardinal, CardinalSet, CharGrid, Grid, GridAddress, RenderTileChar};
-use crate::helper::GenResult;
+use crate::geometry::{
+    Cardinal, CardinalSet, CharGrid, Grid, GridAddress, GridRect, RenderTileChar,
+};
+use crate::helper::{parse_arg, AocError, GenResult, ParseError};
+use crate::viewer::{run_viewer, Simulation};
 use colored::{ColoredString, Colorize};
-use log::{debug, info};
+use log::{debug, info, log_enabled, Level};
+use pathfinding::prelude::yen;
 use std::cmp::{Ordering, Reverse};
 use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
+/// The cost of each kind of move can be changed with `step=<cost>` and `turn=<cost>`,
+/// e.g. `cargo run -- 16 turn=1`
+pub fn run(input_path: &Path, use_viewer: bool, args: &[String]) -> GenResult<()> {
+    let costs = parse_costs(args)?;
     let (maze, maze_meta) = parse_input(input_path)?;
 
     debug!("Parsed input file:\n{}", CharGrid(&maze, &maze_meta));
+    info!("Move costs: {:?}", costs);
 
-    let path_data = explore(&maze, &maze_meta);
+    let path_data = explore(&maze, &maze_meta, &costs);
     let grid2 = paint_solution(&maze, &path_data, &maze_meta);
     let cost = solution_cost(&path_data, &maze_meta);
     let num_spots = spectator_spots(&grid2);
@@ -21,9 +29,79 @@ pub fn run(input_path: &Path) -> GenResult<()> {
     info!("Solution cost: {}", cost);
     info!("Spectator seats: {}", num_spots);
 
+    // list the optimal routes individually
+    let routes = optimal_routes(&path_data, &maze_meta, MAX_LISTED_ROUTES);
+    if routes.len() < MAX_LISTED_ROUTES {
+        info!("Found {} distinct optimal routes", routes.len());
+    } else {
+        info!("Found at least {} distinct optimal routes", routes.len());
+    }
+
+    // Yen's algorithm runs a search for each position along each route it finds,
+    // which takes several seconds on a full-size maze, so this is only done for debugging
+    if log_enabled!(Level::Debug) {
+        let cheapest = k_cheapest_routes(&maze, &maze_meta, &costs, NUM_CHEAPEST_ROUTES);
+        for (i, (route_cost, route)) in cheapest.iter().enumerate() {
+            debug!(
+                "Route #{} costs {} over {} positions",
+                i + 1,
+                route_cost,
+                route.len()
+            );
+        }
+    }
+
+    if let Some((goal_pos, _)) = cheapest_goal_positions(&path_data, &maze_meta).first() {
+        debug!(
+            "Reached the goal facing {:?}:\n{}",
+            goal_pos.heading,
+            CharGrid(&grid2, &maze_meta)
+                .builder()
+                .viewport(GridRect::around(goal_pos.address, 6))
+                .axis_labels(5)
+                .legend()
+        );
+    }
+
+    if use_viewer {
+        let route = primary_route(&path_data, &maze_meta);
+        run_viewer(RouteWalk {
+            grid: blank_solution_grid(&maze),
+            route: &route,
+            next: 1,
+            maze_meta: &maze_meta,
+        })?;
+    }
+
     Ok(())
 }
 
+/// Stop listing optimal routes after this many, since some mazes have a huge number of them
+const MAX_LISTED_ROUTES: usize = 10_000;
+
+/// How many routes `run` lists in order of cost (when debug logging is on), including non-optimal ones
+const NUM_CHEAPEST_ROUTES: usize = 3;
+
+/// Read `step=<cost>` and `turn=<cost>` arguments, with the puzzle's costs as the defaults
+fn parse_costs(args: &[String]) -> GenResult<MoveCosts> {
+    let mut costs = MoveCosts::default();
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("step", value)) => costs.step = parse_arg("step cost", value)?,
+            Some(("turn", value)) => costs.turn = parse_arg("turn cost", value)?,
+            _ => Err(AocError::Args(format!(
+                "unexpected argument for puzzle 16: {:?} (expected step=N or turn=N)",
+                arg
+            )))?,
+        }
+    }
+    // free moves would let a route wander around forever without costing any more
+    if costs.step == 0 || costs.turn == 0 {
+        Err(AocError::Args("move costs must be at least 1".to_string()))?
+    }
+    Ok(costs)
+}
+
 /// Parse the puzzle input, producing a grid representing the maze,
 /// along with the start and end addresses, respectively.
 fn parse_input(input_path: &Path) -> GenResult<(Grid<MazeTile>, MazeMetadata)> {
@@ -33,30 +111,34 @@ fn parse_input(input_path: &Path) -> GenResult<(Grid<MazeTile>, MazeMetadata)> {
     let mut end_addr = None;
     let mut rows = Vec::new();
     for (y, line) in reader.lines().enumerate() {
-        let row = line?
+        let line = line?;
+        let row = line
             .chars()
             .enumerate()
             .map(|(x, c)| match c {
-                '#' => MazeTile::Wall,
-                '.' => MazeTile::Open,
+                '#' => Ok(MazeTile::Wall),
+                '.' => Ok(MazeTile::Open),
                 'S' => {
                     start_addr = Some(GridAddress(x, y));
-                    MazeTile::Open
+                    Ok(MazeTile::Open)
                 }
                 'E' => {
                     end_addr = Some(GridAddress(x, y));
-                    MazeTile::Open
+                    Ok(MazeTile::Open)
                 }
-                other => panic!("Unexpected maze character: '{}'", other),
+                other => Err(
+                    ParseError::new(format!("Unexpected maze character: '{}'", other))
+                        .at_span(y, &line, x, 1),
+                ),
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         rows.push(row);
     }
     Ok((
         Grid { rows },
         MazeMetadata {
-            start_address: start_addr.ok_or("Didn't find maze start")?,
-            end_address: end_addr.ok_or("Didn't find maze end")?,
+            start_address: start_addr.ok_or(ParseError::new("Didn't find maze start"))?,
+            end_address: end_addr.ok_or(ParseError::new("Didn't find maze end"))?,
         },
     ))
 }
@@ -90,6 +172,15 @@ impl<'a> RenderTileChar<MazeTile> for &'a MazeMetadata {
             }
         }
     }
+
+    fn legend(&self) -> Vec<(ColoredString, &'static str)> {
+        vec![
+            ("S".magenta(), "start"),
+            ("E".cyan(), "end"),
+            ("#".normal(), "wall"),
+            (".".bright_black(), "open"),
+        ]
+    }
 }
 
 /// Each tile of the maze can be considered a cluster of four "nodes",
@@ -106,10 +197,26 @@ struct MazePosition {
     heading: Cardinal,
 }
 
+/// Cost of each kind of move through the maze. The puzzle uses 1 to step forward and 1000 to turn.
+#[derive(Debug, Copy, Clone)]
+struct MoveCosts {
+    step: u32,
+    turn: u32,
+}
+
+impl Default for MoveCosts {
+    fn default() -> Self {
+        MoveCosts {
+            step: 1,
+            turn: 1000,
+        }
+    }
+}
+
 impl MazePosition {
     /// Return positions "adjacent" to the current position, along with the cost to reach
-    /// each position from the current one (+1 for advancing forward, +1000 for rotating)
-    fn get_adjacent(&self) -> Vec<(MazePosition, u32)> {
+    /// each position from the current one (`costs.step` for advancing forward, `costs.turn` for rotating)
+    fn get_adjacent(&self, costs: &MoveCosts) -> Vec<(MazePosition, u32)> {
         let mut out = Vec::new();
 
         // advance forward
@@ -119,7 +226,7 @@ impl MazePosition {
                     address: ahead,
                     heading: self.heading,
                 },
-                1,
+                costs.step,
             ));
         }
 
@@ -129,7 +236,7 @@ impl MazePosition {
                 address: self.address,
                 heading: self.heading.turn_left(),
             },
-            1000,
+            costs.turn,
         ));
 
         // turn right
@@ -138,7 +245,7 @@ impl MazePosition {
                 address: self.address,
                 heading: self.heading.turn_right(),
             },
-            1000,
+            costs.turn,
         ));
 
         out
@@ -183,7 +290,11 @@ struct PathData {
 
 /// Modified Dijkstra's algorithm which computes the cheapest `cost` to reach each position in the maze,
 /// and the `parents` for each position that provided that minimal cost.
-fn explore(maze: &Grid<MazeTile>, maze_metadata: &MazeMetadata) -> HashMap<MazePosition, PathData> {
+fn explore(
+    maze: &Grid<MazeTile>,
+    maze_metadata: &MazeMetadata,
+    costs: &MoveCosts,
+) -> HashMap<MazePosition, PathData> {
     let start_pos = MazePosition {
         address: maze_metadata.start_address,
         heading: Cardinal::East,
@@ -221,7 +332,7 @@ fn explore(maze: &Grid<MazeTile>, maze_metadata: &MazeMetadata) -> HashMap<MazeP
         // for each of the current position's neighbors, adjust the known cost of that neighbor
         // and update its "parents" if a lower cost was found. For the "part 2" twist, the parents
         // will be a Vec of any parent that results in the same minimum cost.
-        for (neighbor, edge_cost) in pos.get_adjacent() {
+        for (neighbor, edge_cost) in pos.get_adjacent(costs) {
             if let Some(neighbor_tile) = maze.get_at(neighbor.address) {
                 if *neighbor_tile == MazeTile::Open {
                     let neighbor_cost = cost + edge_cost;
@@ -286,21 +397,11 @@ fn spectator_spots(solution_grid: &Grid<SolutionTile>) -> usize {
     count
 }
 
-/// Combines the original maze and the resulting path-finding information from `explore`
-/// to create a new `Grid` which renders the solution.
-fn paint_solution(
-    maze: &Grid<MazeTile>,
-    path_data: &HashMap<MazePosition, PathData>,
+/// Find the positions at the maze's `end_address` that were reached with the minimal cost
+fn cheapest_goal_positions<'p>(
+    path_data: &'p HashMap<MazePosition, PathData>,
     maze_meta: &MazeMetadata,
-) -> Grid<SolutionTile> {
-    let mut solution_grid: Grid<SolutionTile> = Grid {
-        rows: maze
-            .rows
-            .iter()
-            .map(|row| row.iter().map(|t| (*t).into()).collect())
-            .collect::<Vec<_>>(),
-    };
-
+) -> Vec<(MazePosition, &'p PathData)> {
     // The goal address may have been reached via any of the four cardinal directions
     let reached_goal_positions: Vec<(MazePosition, &PathData)> = Cardinal::ALL
         .iter()
@@ -312,48 +413,144 @@ fn paint_solution(
         .collect::<Vec<_>>();
 
     // ...but not all incoming headings will have the same cost; filter down to only those with the lowest cost
-    let cheapest_goal_positions: Vec<(MazePosition, &PathData)> = {
-        if let Some((_, cheapest)) = reached_goal_positions.iter().min_by_key(|(_, pd)| pd.cost) {
-            reached_goal_positions
-                .iter()
-                .filter(|(_, pd)| pd.cost == cheapest.cost)
-                .cloned()
-                .collect()
-        } else {
-            vec![]
+    if let Some((_, cheapest)) = reached_goal_positions.iter().min_by_key(|(_, pd)| pd.cost) {
+        reached_goal_positions
+            .iter()
+            .filter(|(_, pd)| pd.cost == cheapest.cost)
+            .cloned()
+            .collect()
+    } else {
+        vec![]
+    }
+}
+
+/// Walk backwards from the end of the path, following `parents` links via only the first listed parent,
+/// returning the "primary" route through the maze in order from start to end.
+fn primary_route(
+    path_data: &HashMap<MazePosition, PathData>,
+    maze_meta: &MazeMetadata,
+) -> Vec<MazePosition> {
+    let mut route = Vec::new();
+
+    // for the "primary" path, arbitrarily pick the first of the "cheapest" goal positions
+    let mut cursor_opt = cheapest_goal_positions(path_data, maze_meta).first().cloned();
+
+    while let Some((cursor, datum)) = cursor_opt {
+        route.push(cursor);
+        // pick the first "parent" listed for the current `cursor` as the "primary" path;
+        // if no parent was found, we have presumably reached the maze's `start_address`
+        cursor_opt = datum
+            .parents
+            .first()
+            .and_then(|parent| path_data.get(parent).map(|pd| (*parent, pd)));
+    }
+
+    route.reverse();
+    route
+}
+
+/// List each distinct optimal route through the maze, in order from start to end,
+/// by following every combination of `parents` links back from the cheapest goal positions.
+/// Stops once `limit` routes have been found.
+fn optimal_routes(
+    path_data: &HashMap<MazePosition, PathData>,
+    maze_meta: &MazeMetadata,
+    limit: usize,
+) -> Vec<Vec<MazePosition>> {
+    fn walk_back(
+        path_data: &HashMap<MazePosition, PathData>,
+        cursor: MazePosition,
+        suffix: &mut Vec<MazePosition>,
+        out: &mut Vec<Vec<MazePosition>>,
+        limit: usize,
+    ) {
+        if out.len() >= limit {
+            return;
         }
+        suffix.push(cursor);
+        match path_data.get(&cursor) {
+            Some(datum) if !datum.parents.is_empty() => {
+                for parent in &datum.parents {
+                    walk_back(path_data, *parent, suffix, out, limit);
+                }
+            }
+            // no parents means we've reached the start
+            _ => out.push(suffix.iter().rev().cloned().collect()),
+        }
+        suffix.pop();
+    }
+
+    let mut out = Vec::new();
+    for (goal_pos, _) in cheapest_goal_positions(path_data, maze_meta) {
+        walk_back(path_data, goal_pos, &mut Vec::new(), &mut out, limit);
+    }
+    out
+}
+
+/// Find the `k` cheapest routes from the start to the end of the maze (which aren't necessarily
+/// optimal), cheapest first, using Yen's algorithm. Routes never visit the same position twice.
+fn k_cheapest_routes(
+    maze: &Grid<MazeTile>,
+    maze_meta: &MazeMetadata,
+    costs: &MoveCosts,
+    k: usize,
+) -> Vec<(u32, Vec<MazePosition>)> {
+    let start_pos = MazePosition {
+        address: maze_meta.start_address,
+        heading: Cardinal::East,
     };
+    yen(
+        &start_pos,
+        |pos| {
+            pos.get_adjacent(costs)
+                .into_iter()
+                .filter(|(neighbor, _)| maze.get_at(neighbor.address) == Some(&MazeTile::Open))
+                .collect::<Vec<_>>()
+        },
+        |pos| pos.address == maze_meta.end_address,
+        k,
+    )
+    .into_iter()
+    .map(|(route, cost)| (cost, route))
+    .collect()
+}
 
-    // Walk backwards from the end of the path, following `parents` links via only the first listed parent.
-    // This will paint the "primary" path.
-    {
-        // for the "primary" path, arbitrarily pick the first of the "cheapest" goal positions
-        let mut cursor_opt = cheapest_goal_positions.get(0).cloned();
-
-        while let Some((cursor, datum)) = cursor_opt {
-            // pick the first "parent" listed for the current `cursor` as the "primary" path
-            if let Some(parent) = datum.parents.get(0) {
-                // When the path crosses between multiple addresses, we want to paint a line.
-                // We do so by painting an outgoing line from the parent in the listed heading,
-                // and simulating an incoming line to the cursor by painting the opposite heading.
-                if parent.address != cursor.address {
-                    solution_grid[parent.address].traversed += cursor.heading;
-                    solution_grid[cursor.address].traversed += cursor.heading.opposite();
-                }
+/// Create a solution grid for the maze, with nothing painted yet
+fn blank_solution_grid(maze: &Grid<MazeTile>) -> Grid<SolutionTile> {
+    Grid {
+        rows: maze
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|t| (*t).into()).collect())
+            .collect::<Vec<_>>(),
+    }
+}
 
-                // Advance the cursor by moving it to the parent
-                cursor_opt = path_data.get(parent).map(|pd| (*parent, pd));
-            } else {
-                // if no parent was found, we have presumably reached the maze's `start_address`
-                cursor_opt = None
-            }
+/// Combines the original maze and the resulting path-finding information from `explore`
+/// to create a new `Grid` which renders the solution.
+fn paint_solution(
+    maze: &Grid<MazeTile>,
+    path_data: &HashMap<MazePosition, PathData>,
+    maze_meta: &MazeMetadata,
+) -> Grid<SolutionTile> {
+    let mut solution_grid = blank_solution_grid(maze);
+
+    // Paint the "primary" path.
+    // When the path crosses between multiple addresses, we want to paint a line.
+    // We do so by painting an outgoing line from the previous position in the listed heading,
+    // and simulating an incoming line to the next position by painting the opposite heading.
+    for pair in primary_route(path_data, maze_meta).windows(2) {
+        let (parent, cursor) = (pair[0], pair[1]);
+        if parent.address != cursor.address {
+            solution_grid[parent.address].traversed += cursor.heading;
+            solution_grid[cursor.address].traversed += cursor.heading.opposite();
         }
     }
 
     // Redo the same walk, but follow *all* of the parent links from *all* of the cheapest goal positions
     {
         let mut visited = HashSet::new();
-        let mut to_visit = VecDeque::from(cheapest_goal_positions);
+        let mut to_visit = VecDeque::from(cheapest_goal_positions(path_data, maze_meta));
 
         while let Some((cursor, datum)) = to_visit.pop_front() {
             // avoid re-visiting positions
@@ -369,7 +566,7 @@ fn paint_solution(
                 }
 
                 // add the parent to the exploration queue
-                if let Some(parent_path_data) = path_data.get(&parent_pos) {
+                if let Some(parent_path_data) = path_data.get(parent_pos) {
                     to_visit.push_back((*parent_pos, parent_path_data));
                 }
             }
@@ -379,10 +576,55 @@ fn paint_solution(
     solution_grid
 }
 
+/// Walks the primary route through the maze one position at a time, for use with the interactive viewer
+#[derive(Clone)]
+struct RouteWalk<'a> {
+    grid: Grid<SolutionTile>,
+    route: &'a [MazePosition],
+    next: usize,
+    maze_meta: &'a MazeMetadata,
+}
+
+impl<'a> Simulation for RouteWalk<'a> {
+    type Tile = SolutionTile;
+    type Style = &'a MazeMetadata;
+
+    fn step(&mut self) -> bool {
+        let (Some(parent), Some(cursor)) = (
+            self.next.checked_sub(1).and_then(|i| self.route.get(i)),
+            self.route.get(self.next),
+        ) else {
+            return false;
+        };
+        if parent.address != cursor.address {
+            self.grid[parent.address].traversed += cursor.heading;
+            self.grid[cursor.address].traversed += cursor.heading.opposite();
+        }
+        self.next += 1;
+        true
+    }
+
+    fn grid(&self) -> &Grid<SolutionTile> {
+        &self.grid
+    }
+
+    fn style(&self) -> &'a MazeMetadata {
+        self.maze_meta
+    }
+
+    fn caption(&self) -> String {
+        match self.route.get(self.next - 1) {
+            Some(pos) => format!("at {:?} facing {:?}", pos.address, pos.heading),
+            None => String::new(),
+        }
+    }
+}
+
 /// A tile in the "painted" solution.
 /// `maze_tile` is the tile from the original maze.
 /// `traversed` is the directions that the tile was visited along the primary path through the maze
 /// `alt_traversed` is the directions that the tile was visited along *any* optimal path through the maze
+#[derive(Clone, Debug)]
 struct SolutionTile {
     maze_tile: MazeTile,
     traversed: CardinalSet,
@@ -428,4 +670,104 @@ impl<'a> RenderTileChar<SolutionTile> for &'a MazeMetadata {
             }
         }
     }
+
+    fn legend(&self) -> Vec<(ColoredString, &'static str)> {
+        vec![
+            ("S".magenta(), "start"),
+            ("E".cyan(), "end"),
+            ("#".normal(), "wall"),
+            ("!".red(), "path through a wall"),
+            ("─".yellow(), "primary path"),
+            ("─".cyan(), "other optimal path"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COST_SETTINGS: [MoveCosts; 4] = [
+        MoveCosts {
+            step: 1,
+            turn: 1000,
+        },
+        MoveCosts { step: 1, turn: 1 },
+        MoveCosts { step: 5, turn: 2 },
+        MoveCosts {
+            step: 1000,
+            turn: 1,
+        },
+    ];
+
+    fn example_maze() -> (Grid<MazeTile>, MazeMetadata) {
+        parse_input(Path::new("example_inputs/16.txt")).unwrap()
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn example_answers() {
+        let (maze, maze_meta) = example_maze();
+        let path_data = explore(&maze, &maze_meta, &MoveCosts::default());
+        assert_eq!(solution_cost(&path_data, &maze_meta), 11048);
+        let solution = paint_solution(&maze, &path_data, &maze_meta);
+        assert_eq!(spectator_spots(&solution), 64);
+    }
+
+    #[test]
+    fn optimal_routes_cover_the_spectator_seats() {
+        let (maze, maze_meta) = example_maze();
+        for costs in COST_SETTINGS {
+            let path_data = explore(&maze, &maze_meta, &costs);
+            let solution = paint_solution(&maze, &path_data, &maze_meta);
+            let routes = optimal_routes(&path_data, &maze_meta, MAX_LISTED_ROUTES);
+            assert!(routes.len() < MAX_LISTED_ROUTES, "{:?}", costs);
+            let seats = routes
+                .iter()
+                .flatten()
+                .map(|pos| pos.address)
+                .collect::<HashSet<_>>();
+            assert_eq!(seats.len(), spectator_spots(&solution), "{:?}", costs);
+        }
+    }
+
+    #[test]
+    fn cheapest_route_matches_explore() {
+        let (maze, maze_meta) = example_maze();
+        for costs in COST_SETTINGS {
+            let path_data = explore(&maze, &maze_meta, &costs);
+            let cheapest = k_cheapest_routes(&maze, &maze_meta, &costs, NUM_CHEAPEST_ROUTES);
+            assert_eq!(
+                cheapest.first().map(|(cost, _)| *cost),
+                Some(solution_cost(&path_data, &maze_meta)),
+                "{:?}",
+                costs
+            );
+            assert!(cheapest.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        }
+    }
+
+    #[test]
+    fn costs_from_args() {
+        let costs = parse_costs(&[]).unwrap();
+        assert_eq!((costs.step, costs.turn), (1, 1000));
+        let costs = parse_costs(&args(&["turn=7"])).unwrap();
+        assert_eq!((costs.step, costs.turn), (1, 7));
+        let costs = parse_costs(&args(&["step=3", "turn=2"])).unwrap();
+        assert_eq!((costs.step, costs.turn), (3, 2));
+    }
+
+    #[test]
+    fn bad_cost_args_are_rejected() {
+        for bad in [&["step=0"][..], &["turn=x"], &["walk=1"], &["5"]] {
+            assert!(
+                matches!(parse_costs(&args(bad)), Err(AocError::Args(_))),
+                "{:?}",
+                bad
+            );
+        }
+    }
 }
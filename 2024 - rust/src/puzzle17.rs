@@ -1,4 +1,5 @@
-use crate::helper::GenResult;
+use crate::helper::{AocError, GenResult, ParseError};
+use aoc_core::parse;
 use colored::Colorize;
 use log::{debug, info};
 use std::fmt::{Debug, Formatter, Write};
@@ -6,7 +7,27 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
+mod asm;
+mod compile;
+mod debugger;
+mod solver;
+
+pub fn run(input_path: &Path, args: &[String]) -> GenResult<()> {
+    // assembling a listing doesn't involve the puzzle input at all
+    if let [tool, listing_path] = args {
+        if tool == "asm" {
+            let file = File::open(listing_path)?;
+            let lines = BufReader::new(file)
+                .lines()
+                .collect::<Result<Vec<_>, _>>()?;
+            let program =
+                asm::assemble(&lines).map_err(|err| err.in_file(Path::new(listing_path)))?;
+            let program = program.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            println!("Program: {}", program.join(","));
+            return Ok(());
+        }
+    }
+
     // parse the puzzle input
     let (machine, raw_program, ops) = {
         let file = File::open(input_path)?;
@@ -19,25 +40,70 @@ pub fn run(input_path: &Path) -> GenResult<()> {
     info!("Program: {:?}", raw_program);
     info!("Init: {:?}, ops: {:?}", machine, ops);
 
+    let listing = asm::disassemble(&raw_program)?;
+    debug!("Program listing:\n{}", listing);
+
+    let check = match args {
+        [] => false,
+        [tool] if tool == "check" => true,
+        [tool] if tool == "debug" => return debugger::run_debugger(machine, ops),
+        [tool] if tool == "disasm" => {
+            print!("{}", listing);
+            return Ok(());
+        }
+        [tool] if tool == "bench" => {
+            compile::benchmark(&machine, &ops, 0..BENCHMARK_RUNS)?;
+            return Ok(());
+        }
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 17: {:?}",
+            args
+        )))?,
+    };
+
     // run the program (part 1)
     info!("Run Program with given initial state...");
     let (_, out) = run_full(machine.clone(), &ops);
 
     // reverse-engineer the puzzle input (part 2)
     info!("Attempt to reverse-engineer the correct value for Register A...");
-    let seed_a =
-        reverse_engineer(&ops, &raw_program, 0).ok_or("Couldn't reverse-engineer Register A")?;
-
-    // verify the reverse-engineering attempt
-    {
-        let mut machine = machine.clone();
-        machine.a = seed_a;
-        let (_, out) = run_full(machine, &ops);
-        assert_eq!(
-            out, raw_program,
-            "output mismatch: {:?} vs {:?}",
-            out, raw_program
-        );
+    let solutions = solver::solve_quine(&machine, &ops, &raw_program)?;
+    let seed_a = *solutions
+        .first()
+        .ok_or("Couldn't reverse-engineer Register A")?;
+    info!("Found {} solution(s): {:?}", solutions.len(), solutions);
+
+    // cross-check against the original approach, when the program fits its assumptions
+    if solver::analyze(&ops) == Ok(solver::LoopShape { shift: 3 }) {
+        let dfs_a = reverse_engineer(&ops, &raw_program, 0);
+        if dfs_a != Some(seed_a) {
+            Err(AocError::Logic(format!(
+                "solver found {} but reverse_engineer found {:?}",
+                seed_a, dfs_a
+            )))?
+        }
+    }
+
+    // with the `check` tool, brute-force the values around the answer, to make sure the
+    // solver didn't miss any there
+    if check {
+        let window =
+            seed_a.saturating_sub(BRUTE_FORCE_RADIUS)..seed_a.saturating_add(BRUTE_FORCE_RADIUS);
+        let program = compile::compile(&ops);
+        let brute_solutions =
+            compile::brute_force(&program, &machine, &raw_program, window.clone());
+        let expected = solutions
+            .iter()
+            .filter(|a| window.contains(a))
+            .copied()
+            .collect::<Vec<_>>();
+        if brute_solutions != expected {
+            Err(AocError::Logic(format!(
+                "brute force found {:?} in {:?}, but the solver found {:?}",
+                brute_solutions, window, expected
+            )))?
+        }
+        info!("Brute force over {:?} agrees with the solver", window);
     }
 
     // report solutions
@@ -54,27 +120,38 @@ pub fn run(input_path: &Path) -> GenResult<()> {
     Ok(())
 }
 
+/// How far either side of the part 2 answer to check by brute force
+const BRUTE_FORCE_RADIUS: u64 = 1 << 16;
+
+/// Number of values of A to run through each implementation for the `bench` tool
+const BENCHMARK_RUNS: u64 = 100_000;
+
 fn parse_input<R: BufRead>(reader: R) -> GenResult<(Machine, Vec<u64>, Vec<Op>)> {
     let input_lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
 
-    let [a_line, b_line, c_line, _, p_line] = input_lines.as_slice() else {
-        Err("input didn't have 5 lines")?
+    let sections = parse::sections(&input_lines);
+    let [registers, program] = sections.as_slice() else {
+        Err(ParseError::new(format!(
+            "expected 2 sections of input (registers and program), but found {}",
+            sections.len()
+        )))?
     };
-    let register_a = a_line[12..].parse::<u64>()?;
-    let register_b = b_line[12..].parse::<u64>()?;
-    let register_c = c_line[12..].parse::<u64>()?;
-    let raw_opcodes = p_line[9..]
-        .split(',')
-        .map(|s| s.parse::<u64>())
-        .collect::<Result<Vec<u64>, _>>()?;
+    let [a_line, b_line, c_line] = parse::expect_lines(registers)?;
+    let [p_line] = parse::expect_lines(program)?;
+
+    let register_a = a_line.key_value("Register A")?.parse::<u64>()?;
+    let register_b = b_line.key_value("Register B")?.parse::<u64>()?;
+    let register_c = c_line.key_value("Register C")?.parse::<u64>()?;
+    let program = p_line.key_value("Program")?;
+    let raw_opcodes = program.list::<u64>(',')?;
 
     let mut ops = Vec::new();
     let mut opcodes_itr = raw_opcodes.iter();
     while let Some(opcode) = opcodes_itr.next() {
         let operand = opcodes_itr
             .next()
-            .ok_or(format!("Missing operand after opcode {}", opcode))?;
-        let op = Op::try_from((*opcode, *operand))?;
+            .ok_or_else(|| program.error(format!("Missing operand after opcode {}", opcode)))?;
+        let op = Op::try_from((*opcode, *operand)).map_err(|err| program.error(err))?;
         ops.push(op);
     }
 
@@ -176,7 +253,7 @@ impl TryFrom<(u64, u64)> for Op {
             5 => Ok(Op::Out(value.1.try_into()?)),
             6 => Ok(Op::Bdv(value.1.try_into()?)),
             7 => Ok(Op::Cdv(value.1.try_into()?)),
-            _ => Err(format!("Invalid opcode {}", value.1)),
+            _ => Err(format!("Invalid opcode {}", value.0)),
         }
     }
 }
@@ -186,17 +263,17 @@ impl Op {
     fn run(&self, machine: &mut Machine) -> Step {
         match self {
             Op::Adv(operand) => {
-                debug!("  A := A >> {:?}", operand);
-                machine.a = machine.a >> operand.get_value(machine);
+                debug!("  {}", self.pseudo_code());
+                machine.a = shr(machine.a, operand.get_value(machine));
                 Step::Continue
             }
             Op::Bxl(value) => {
-                debug!("  B := B ^ {}", value);
+                debug!("  {}", self.pseudo_code());
                 machine.b = machine.b ^ value;
                 Step::Continue
             }
             Op::Bst(operand) => {
-                debug!("  B := {:?} % 8", operand);
+                debug!("  {}", self.pseudo_code());
                 machine.b = operand.get_value(machine) % 8;
                 Step::Continue
             }
@@ -210,28 +287,37 @@ impl Op {
                 }
             }
             Op::Bxc => {
-                debug!("  B := B ^ C");
+                debug!("  {}", self.pseudo_code());
                 machine.b = machine.b ^ machine.c;
                 Step::Continue
             }
             Op::Out(operand) => {
-                debug!("  <output {:?} % 8>", operand);
+                debug!("  {}", self.pseudo_code());
                 Step::Output(operand.get_value(machine) % 8)
             }
             Op::Bdv(operand) => {
-                debug!("  B := A >> {:?}", operand);
-                machine.b = machine.a >> operand.get_value(machine);
+                debug!("  {}", self.pseudo_code());
+                machine.b = shr(machine.a, operand.get_value(machine));
                 Step::Continue
             }
             Op::Cdv(operand) => {
-                debug!("  C := A >> {:?}", operand);
-                machine.c = machine.a >> operand.get_value(machine);
+                debug!("  {}", self.pseudo_code());
+                machine.c = shr(machine.a, operand.get_value(machine));
                 Step::Continue
             }
         }
     }
 }
 
+/// Right shift where shifting by 64 or more bits gives 0, like the division in the puzzle description
+fn shr(value: u64, bits: u64) -> u64 {
+    if bits >= 64 {
+        0
+    } else {
+        value >> bits
+    }
+}
+
 /// Program interpreter that collects outputs to a `Vec`
 fn run_full(mut machine: Machine, ops: &[Op]) -> (Machine, Vec<u64>) {
     let mut out = Vec::new();
@@ -312,6 +398,8 @@ fn test_input_output(init_a: u64, program: &[Op], expected_output: u64) -> bool
 /// Sometimes, there are multiple numbers in the 0..8 range that satisfy the current iteration,
 /// but result in unsatisfied earlier iterations. We use recursion, in what is essentially a DFS,
 /// to account for this.
+///
+/// `solver::solve_quine` checks these assumptions first, and handles programs that break them.
 fn reverse_engineer(program: &[Op], expected_output: &[u64], accum: u64) -> Option<u64> {
     if expected_output.is_empty() {
         debug!("Success with {}", accum);
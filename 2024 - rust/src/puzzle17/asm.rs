@@ -0,0 +1,224 @@
+//! Text listings for the 3-bit machine's programs.
+//!
+//! `cargo run -- 17 disasm` prints the puzzle program as a listing like
+//! ```text
+//!  0: bst A      ; B := A % 8
+//!  2: bxl 5      ; B := B ^ 5
+//!  4: jnz 0      ; if A != 0, jump to 0
+//! ```
+//! and `cargo run -- 17 asm <file>` turns such a listing back into the raw program.
+//! The leading addresses and `;` comments are optional when assembling.
+
+use super::{ComboOperand, Op};
+use crate::helper::ParseError;
+use aoc_core::parse::{self, InputSpan};
+use std::fmt::{Display, Formatter};
+
+/// Mnemonics for each opcode, indexed by opcode
+const MNEMONICS: [&str; 8] = ["adv", "bxl", "bst", "jnz", "bxc", "out", "bdv", "cdv"];
+
+impl Display for ComboOperand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComboOperand::Const0 => f.write_str("0"),
+            ComboOperand::Const1 => f.write_str("1"),
+            ComboOperand::Const2 => f.write_str("2"),
+            ComboOperand::Const3 => f.write_str("3"),
+            ComboOperand::RegA => f.write_str("A"),
+            ComboOperand::RegB => f.write_str("B"),
+            ComboOperand::RegC => f.write_str("C"),
+        }
+    }
+}
+
+impl Op {
+    /// Readable description of what the op does, e.g. `A := A >> 3`
+    pub fn pseudo_code(&self) -> String {
+        match self {
+            Op::Adv(operand) => format!("A := A >> {}", operand),
+            Op::Bxl(value) => format!("B := B ^ {}", value),
+            Op::Bst(operand) => format!("B := {} % 8", operand),
+            Op::Jnz(address) => format!("if A != 0, jump to {}", address),
+            Op::Bxc => "B := B ^ C".to_string(),
+            Op::Out(operand) => format!("output {} % 8", operand),
+            Op::Bdv(operand) => format!("B := A >> {}", operand),
+            Op::Cdv(operand) => format!("C := A >> {}", operand),
+        }
+    }
+}
+
+/// Render the raw program as a listing, one instruction per line
+pub fn disassemble(program: &[u64]) -> Result<String, String> {
+    if !program.len().is_multiple_of(2) {
+        return Err(format!(
+            "Program has an odd number of values ({}), so the last opcode has no operand",
+            program.len()
+        ));
+    }
+    let address_width = program.len().saturating_sub(1).to_string().len();
+    let mut listing = String::new();
+    for (idx, pair) in program.chunks(2).enumerate() {
+        let (opcode, operand) = (pair[0], pair[1]);
+        let op = Op::try_from((opcode, operand))
+            .map_err(|err| format!("At address {}: {}", idx * 2, err))?;
+        let instruction = match op {
+            Op::Adv(combo) | Op::Bst(combo) | Op::Out(combo) | Op::Bdv(combo) | Op::Cdv(combo) => {
+                format!("{} {}", MNEMONICS[opcode as usize], combo)
+            }
+            // `bxc` ignores its operand, but it's kept in the listing so the raw program can be restored exactly
+            Op::Bxl(_) | Op::Jnz(_) | Op::Bxc => {
+                format!("{} {}", MNEMONICS[opcode as usize], operand)
+            }
+        };
+        listing.push_str(&format!(
+            "{:>width$}: {:<10} ; {}\n",
+            idx * 2,
+            instruction,
+            op.pseudo_code(),
+            width = address_width
+        ));
+    }
+    Ok(listing)
+}
+
+/// Parse a listing (as produced by `disassemble`) back into the raw program
+pub fn assemble<S: AsRef<str>>(listing: &[S]) -> Result<Vec<u64>, ParseError> {
+    let mut program = Vec::new();
+    for line in parse::spans(listing) {
+        // drop comments and the optional `address:` prefix
+        let code = match line.split_once(";") {
+            Ok((code, _)) => code,
+            Err(_) => line,
+        };
+        let code = match code.split_once(":") {
+            Ok((address, code)) => {
+                let address = address.trim().parse::<usize>()?;
+                if address != program.len() {
+                    Err(code.error(format!(
+                        "instruction labelled with address {}, but it would be at {}",
+                        address,
+                        program.len()
+                    )))?
+                }
+                code
+            }
+            Err(_) => code,
+        }
+        .trim();
+        if code.is_empty() {
+            continue;
+        }
+
+        let (opcode, operand) = assemble_instruction(code)?;
+        program.push(opcode);
+        program.push(operand);
+    }
+    Ok(program)
+}
+
+fn assemble_instruction(code: InputSpan) -> Result<(u64, u64), ParseError> {
+    let parts = code.split(' ');
+    let (mnemonic, operand) = match parts.as_slice() {
+        [mnemonic] => (*mnemonic, None),
+        [mnemonic, operand] => (*mnemonic, Some(*operand)),
+        _ => Err(code.error("expected a mnemonic followed by at most one operand"))?,
+    };
+    let opcode = MNEMONICS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(mnemonic.text()))
+        .ok_or_else(|| mnemonic.error(format!("unknown mnemonic {:?}", mnemonic.text())))?
+        as u64;
+
+    let operand = match (opcode, operand) {
+        // bxc's operand is ignored, so it may be left out
+        (4, None) => 0,
+        (_, None) => Err(code.error(format!("{} needs an operand", MNEMONICS[opcode as usize])))?,
+        // combo operands may refer to registers by name
+        (0 | 2 | 5 | 6 | 7, Some(operand)) => match operand.text() {
+            "A" | "a" => 4,
+            "B" | "b" => 5,
+            "C" | "c" => 6,
+            _ => operand.parse::<u64>()?,
+        },
+        (_, Some(operand)) => operand.parse::<u64>()?,
+    };
+    if operand > 7 {
+        Err(code.error(format!("operand {} doesn't fit in 3 bits", operand)))?
+    }
+    Op::try_from((opcode, operand)).map_err(|err| code.error(err))?;
+    Ok((opcode, operand))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disassemble the program and assemble the listing again, checking that it comes back unchanged
+    fn assert_round_trip(program: &[u64]) {
+        let listing = disassemble(program).unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+        let reassembled = assemble(&lines).unwrap();
+        assert_eq!(reassembled, program, "listing was:\n{}", listing);
+    }
+
+    #[test]
+    fn example_program_round_trips() {
+        assert_round_trip(&[0, 3, 5, 4, 3, 0]);
+        assert_round_trip(&[2, 4, 1, 5, 7, 5, 1, 6, 0, 3, 4, 0, 5, 5, 3, 0]);
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        for opcode in 0..8 {
+            for operand in 0..8 {
+                if Op::try_from((opcode, operand)).is_ok() {
+                    assert_round_trip(&[opcode, operand]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn long_program_round_trips() {
+        // every valid instruction in one program, so that addresses get two digits and jumps
+        // point all over the place
+        let program = (0..8)
+            .flat_map(|opcode| (0..8).map(move |operand| (opcode, operand)))
+            .filter(|&pair| Op::try_from(pair).is_ok())
+            .flat_map(|(opcode, operand)| [opcode, operand])
+            .collect::<Vec<_>>();
+        assert!(program.len() > 100);
+        assert_round_trip(&program);
+    }
+
+    #[test]
+    fn listing_shows_registers_and_jumps() {
+        let listing = disassemble(&[6, 5, 7, 6, 3, 2]).unwrap();
+        let expected = [
+            "0: bdv B      ; B := A >> B",
+            "2: cdv C      ; C := A >> C",
+            "4: jnz 2      ; if A != 0, jump to 2",
+        ];
+        assert_eq!(listing.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn assemble_accepts_bare_instructions() {
+        let listing = [
+            "adv a",
+            "bxc",
+            "; just a comment",
+            "",
+            "out c ; comment",
+            "jnz 0",
+        ];
+        assert_eq!(assemble(&listing).unwrap(), [0, 4, 4, 0, 5, 6, 3, 0]);
+    }
+
+    #[test]
+    fn assemble_rejects_bad_instructions() {
+        for line in ["adv 8", "adv 7", "foo 1", "bxl", "bst 1 2", "3: bst 1"] {
+            assert!(assemble(&[line]).is_err(), "{:?} should not assemble", line);
+        }
+    }
+}
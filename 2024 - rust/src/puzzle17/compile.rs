@@ -0,0 +1,368 @@
+//! A faster way to run programs many times, e.g. when searching over values of register A.
+//!
+//! Programs with the loop shape that `solver::analyze` accepts (which includes every puzzle
+//! input) are compiled to a `LoopKernel`: since B and C are recomputed from A on every
+//! iteration, each iteration's output only depends on the low bits of A, so the whole loop
+//! body becomes a table lookup followed by a constant shift, with no per-op dispatch at all.
+//!
+//! Anything else falls back to turning each `Op` into a closure with its operands already
+//! resolved (so `adv 3` becomes "shift A right by 3" rather than "look up combo operand 3,
+//! then shift"), which the runner calls in sequence, without the per-step logging done by
+//! `run_full`.
+
+use super::solver::{self, LoopShape};
+use super::{run_full, shr, ComboOperand, Machine, Op, Step};
+use log::info;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::time::Instant;
+
+type CompiledOp = Box<dyn Fn(&mut Machine) -> Step + Send + Sync>;
+
+/// A program that has been compiled for fast repeated runs
+pub enum CompiledProgram {
+    Loop(LoopKernel),
+    Ops(Vec<CompiledOp>),
+}
+
+/// A single loop that outputs one value and shifts A by a constant each iteration,
+/// until A is 0, with the output precomputed for every value of A's low bits
+pub struct LoopKernel {
+    shift: u64,
+    /// Mask for the bits of A that the output depends on
+    mask: u64,
+    /// The output of an iteration, indexed by `A & mask`
+    outputs: Vec<u8>,
+}
+
+/// Give up on runs longer than this, so a non-halting program can't hang a search
+const MAX_STEPS: usize = 1_000_000;
+
+/// Largest lookup table for a `LoopKernel`, in bits of A
+const MAX_KERNEL_BITS: u32 = 16;
+
+/// Compile the ops to a loop kernel if possible, or closures otherwise.
+/// Unlike `Op::run`, the compiled jumps hold the index of the op to jump to, not the raw address.
+pub fn compile(ops: &[Op]) -> CompiledProgram {
+    match solver::analyze(ops) {
+        Ok(shape) => match LoopKernel::build(ops, shape) {
+            Some(kernel) => return CompiledProgram::Loop(kernel),
+            None => info!("Loop output depends on too many bits of A to tabulate"),
+        },
+        Err(_) => info!("Program isn't a simple loop, so compiling it op by op"),
+    }
+    CompiledProgram::Ops(ops.iter().map(compile_op).collect())
+}
+
+impl LoopKernel {
+    /// Tabulate the loop's output over as few low bits of A as it depends on,
+    /// or `None` if that's more than `MAX_KERNEL_BITS`
+    fn build(ops: &[Op], shape: LoopShape) -> Option<Self> {
+        // `analyze` checked that the program ends with the loop's only jump
+        let body = &ops[..ops.len() - 1];
+        (1..=MAX_KERNEL_BITS).find_map(|bits| {
+            let outputs = (0..1 << bits)
+                .map(|low_a| solver::loop_output(body, bits, low_a).map(|n| n as u8))
+                .collect::<Option<Vec<_>>>()?;
+            Some(LoopKernel {
+                shift: shape.shift,
+                mask: (1 << bits) - 1,
+                outputs,
+            })
+        })
+    }
+
+    /// The outputs for an initial value of A, stopping early if `keep_going` returns false
+    fn run(&self, mut a: u64, mut keep_going: impl FnMut(u64) -> bool) {
+        loop {
+            if !keep_going(u64::from(self.outputs[(a & self.mask) as usize])) {
+                return;
+            }
+            a >>= self.shift;
+            if a == 0 {
+                return;
+            }
+        }
+    }
+}
+
+fn compile_op(op: &Op) -> CompiledOp {
+    match *op {
+        Op::Adv(operand) => match register_or_const(operand) {
+            Ok(n) => Box::new(move |m| {
+                m.a = shr(m.a, n);
+                Step::Continue
+            }),
+            Err(get) => Box::new(move |m| {
+                m.a = shr(m.a, get(m));
+                Step::Continue
+            }),
+        },
+        Op::Bxl(value) => Box::new(move |m| {
+            m.b ^= value;
+            Step::Continue
+        }),
+        Op::Bst(operand) => {
+            let get = combo_getter(operand);
+            Box::new(move |m| {
+                m.b = get(m) % 8;
+                Step::Continue
+            })
+        }
+        Op::Jnz(address) => {
+            let target = address / 2;
+            Box::new(move |m| {
+                if m.a == 0 {
+                    Step::Continue
+                } else {
+                    Step::JumpTo(target)
+                }
+            })
+        }
+        Op::Bxc => Box::new(|m| {
+            m.b ^= m.c;
+            Step::Continue
+        }),
+        Op::Out(operand) => {
+            let get = combo_getter(operand);
+            Box::new(move |m| Step::Output(get(m) % 8))
+        }
+        Op::Bdv(operand) => match register_or_const(operand) {
+            Ok(n) => Box::new(move |m| {
+                m.b = shr(m.a, n);
+                Step::Continue
+            }),
+            Err(get) => Box::new(move |m| {
+                m.b = shr(m.a, get(m));
+                Step::Continue
+            }),
+        },
+        Op::Cdv(operand) => match register_or_const(operand) {
+            Ok(n) => Box::new(move |m| {
+                m.c = shr(m.a, n);
+                Step::Continue
+            }),
+            Err(get) => Box::new(move |m| {
+                m.c = shr(m.a, get(m));
+                Step::Continue
+            }),
+        },
+    }
+}
+
+/// A constant operand's value, or a function to read the register it refers to
+fn register_or_const(operand: ComboOperand) -> Result<u64, fn(&Machine) -> u64> {
+    match operand {
+        ComboOperand::Const0 => Ok(0),
+        ComboOperand::Const1 => Ok(1),
+        ComboOperand::Const2 => Ok(2),
+        ComboOperand::Const3 => Ok(3),
+        other => Err(combo_getter(other)),
+    }
+}
+
+fn combo_getter(operand: ComboOperand) -> fn(&Machine) -> u64 {
+    match operand {
+        ComboOperand::Const0 => |_| 0,
+        ComboOperand::Const1 => |_| 1,
+        ComboOperand::Const2 => |_| 2,
+        ComboOperand::Const3 => |_| 3,
+        ComboOperand::RegA => |m| m.a,
+        ComboOperand::RegB => |m| m.b,
+        ComboOperand::RegC => |m| m.c,
+    }
+}
+
+impl CompiledProgram {
+    /// Run to completion, collecting the outputs (like `run_full`)
+    pub fn run(&self, mut machine: Machine) -> Vec<u64> {
+        let mut out = Vec::new();
+        let ops = match self {
+            CompiledProgram::Loop(kernel) => {
+                kernel.run(machine.a, |n| {
+                    out.push(n);
+                    true
+                });
+                return out;
+            }
+            CompiledProgram::Ops(ops) => ops,
+        };
+        let mut pointer = 0;
+        for _ in 0..MAX_STEPS {
+            let Some(op) = ops.get(pointer) else {
+                break;
+            };
+            match op(&mut machine) {
+                Step::Continue => pointer += 1,
+                Step::JumpTo(target) => pointer = target,
+                Step::Output(n) => {
+                    out.push(n);
+                    pointer += 1;
+                }
+            }
+        }
+        out
+    }
+
+    /// Check whether the program outputs exactly `expected`, stopping at the first mismatch
+    pub fn outputs(&self, mut machine: Machine, expected: &[u64]) -> bool {
+        let mut matched = 0;
+        let ops = match self {
+            CompiledProgram::Loop(kernel) => {
+                let mut mismatch = false;
+                kernel.run(machine.a, |n| {
+                    mismatch = expected.get(matched) != Some(&n);
+                    matched += 1;
+                    !mismatch
+                });
+                return !mismatch && matched == expected.len();
+            }
+            CompiledProgram::Ops(ops) => ops,
+        };
+        let mut pointer = 0;
+        for _ in 0..MAX_STEPS {
+            let Some(op) = ops.get(pointer) else {
+                return matched == expected.len();
+            };
+            match op(&mut machine) {
+                Step::Continue => pointer += 1,
+                Step::JumpTo(target) => pointer = target,
+                Step::Output(n) => {
+                    if expected.get(matched) != Some(&n) {
+                        return false;
+                    }
+                    matched += 1;
+                    pointer += 1;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Try every value of A in the range (in parallel), returning the ones that make the program output `expected`
+pub fn brute_force(
+    program: &CompiledProgram,
+    machine: &Machine,
+    expected: &[u64],
+    range: Range<u64>,
+) -> Vec<u64> {
+    let mut found = range
+        .into_par_iter()
+        .filter(|a| program.outputs(Machine { a: *a, ..*machine }, expected))
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
+
+/// Time the interpreter against the compiled program over a range of A values,
+/// checking that they agree on every output.
+pub fn benchmark(machine: &Machine, ops: &[Op], range: Range<u64>) -> Result<(), String> {
+    let count = range.end - range.start;
+
+    let start = Instant::now();
+    let interpreted = range
+        .clone()
+        .map(|a| run_full(Machine { a, ..*machine }, ops).1)
+        .collect::<Vec<_>>();
+    let interpreter_time = start.elapsed();
+
+    let start = Instant::now();
+    let program = compile(ops);
+    let compiled = range
+        .clone()
+        .map(|a| program.run(Machine { a, ..*machine }))
+        .collect::<Vec<_>>();
+    let compiled_time = start.elapsed();
+
+    if let Some(a) = range
+        .zip(interpreted.iter().zip(&compiled))
+        .find_map(|(a, (i, c))| (i != c).then_some(a))
+    {
+        return Err(format!(
+            "Compiled program disagrees with the interpreter for A = {}",
+            a
+        ));
+    }
+
+    info!(
+        "Interpreter: {:?} for {} runs ({:?} per run)",
+        interpreter_time,
+        count,
+        interpreter_time / count as u32
+    );
+    info!(
+        "Compiled: {:?} for {} runs ({:?} per run), {:.1}x faster",
+        compiled_time,
+        count,
+        compiled_time / count as u32,
+        interpreter_time.as_secs_f64() / compiled_time.as_secs_f64()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ops(program: &[u64]) -> Vec<Op> {
+        program
+            .chunks(2)
+            .map(|pair| Op::try_from((pair[0], pair[1])).unwrap())
+            .collect()
+    }
+
+    /// Check that the compiled program agrees with the interpreter over a range of A
+    fn assert_matches_interpreter(program: &CompiledProgram, ops: &[Op], range: Range<u64>) {
+        for a in range {
+            let machine = Machine { a, b: 0, c: 0 };
+            let (_, expected) = run_full(machine, ops);
+            assert_eq!(program.run(machine), expected, "A = {}", a);
+            assert!(program.outputs(machine, &expected), "A = {}", a);
+            assert!(!program.outputs(machine, &expected[1..]), "A = {}", a);
+        }
+    }
+
+    #[test]
+    fn example_compiles_to_a_loop_kernel() {
+        let ops = parse_ops(&[0, 3, 5, 4, 3, 0]);
+        let program = compile(&ops);
+        assert!(matches!(program, CompiledProgram::Loop(_)));
+        assert_matches_interpreter(&program, &ops, 0..5000);
+    }
+
+    #[test]
+    fn typical_input_compiles_to_a_loop_kernel() {
+        // bst A, bxl 5, cdv B, bxl 6, adv 3, bxc, out B, jnz 0
+        let ops = parse_ops(&[2, 4, 1, 5, 7, 5, 1, 6, 0, 3, 4, 0, 5, 5, 3, 0]);
+        let program = compile(&ops);
+        assert!(matches!(&program, CompiledProgram::Loop(kernel) if kernel.mask == (1 << 10) - 1));
+        assert_matches_interpreter(&program, &ops, 0..5000);
+        assert_matches_interpreter(&program, &ops, u64::MAX - 5000..u64::MAX);
+    }
+
+    #[test]
+    fn other_programs_compile_op_by_op() {
+        // shifts A by 1 and then by B, and has a second jump
+        let ops = parse_ops(&[2, 4, 1, 3, 7, 5, 4, 0, 5, 5, 0, 1, 0, 5, 3, 16, 3, 0]);
+        let program = compile(&ops);
+        assert!(matches!(program, CompiledProgram::Ops(_)));
+        assert_matches_interpreter(&program, &ops, 0..5000);
+    }
+
+    #[test]
+    fn loop_reading_stale_registers_compiles_op_by_op() {
+        // out B reads B from the previous iteration
+        let ops = parse_ops(&[5, 5, 2, 4, 0, 3, 3, 0]);
+        assert!(matches!(compile(&ops), CompiledProgram::Ops(_)));
+    }
+
+    #[test]
+    fn brute_force_finds_the_example_quine() {
+        let raw = [0, 3, 5, 4, 3, 0];
+        let ops = parse_ops(&raw);
+        let machine = Machine { a: 0, b: 0, c: 0 };
+        let found = brute_force(&compile(&ops), &machine, &raw, 100_000..200_000);
+        assert_eq!(found, (117440..117448).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,552 @@
+//! Interactive debugger for the 3-bit machine, started with `cargo run -- 17 debug`.
+//!
+//! Reads commands from stdin, one per line; `help` lists them. Instruction indexes
+//! refer to positions in the list of ops (i.e. half of the raw program address that
+//! `jnz` would use), which is also how the program listing is numbered.
+
+use super::{Machine, Op, Step};
+use crate::helper::GenResult;
+use colored::Colorize;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, Write};
+
+/// Safety net for `continue`, so that a program that never halts doesn't hang the REPL
+const MAX_CONTINUE_STEPS: usize = 10_000_000;
+
+/// How many steps `back` can undo; older snapshots are dropped so a long `continue` can't
+/// eat all the memory
+const MAX_HISTORY: usize = 1_000_000;
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute the next n instructions (default 1)
+  c, continue          run until a breakpoint, a watched output, or the program halts
+  r, back [n]          undo the last n steps (default 1)
+  b, break <index>     break before executing the instruction at <index>
+  b, break <cond>      break when a register condition holds, e.g. `a == 0` or `b>7`
+  d, delete <n>        remove the breakpoint numbered <n>
+  w, watch             toggle breaking whenever the program outputs a value
+  set <reg> <value>    change the value of register a, b or c
+  p, print             show the program, pointer, registers and output so far
+  reset                restore the initial state (breakpoints are kept)
+  h, help              show this message
+  q, quit              exit the debugger";
+
+/// Start the debugger REPL for the given program, returning once the user quits
+pub fn run_debugger(machine: Machine, ops: Vec<Op>) -> GenResult<()> {
+    let mut debugger = Debugger::new(machine, ops);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    println!("{}", HELP.bright_black());
+    debugger.print_state();
+
+    loop {
+        print!("{} ", "(dbg)".bold());
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            // stdin was closed
+            println!();
+            return Ok(());
+        };
+        match debugger.execute(line?.trim()) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            Err(msg) => println!("{}", msg.red()),
+        }
+    }
+}
+
+/// Everything needed to put the machine back the way it was before a step
+#[derive(Debug, Copy, Clone)]
+struct Snapshot {
+    machine: Machine,
+    pointer: usize,
+    output_len: usize,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Register {
+    A,
+    B,
+    C,
+}
+
+impl Register {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "a" => Ok(Register::A),
+            "b" => Ok(Register::B),
+            "c" => Ok(Register::C),
+            _ => Err(format!("Unknown register {:?}, expected a, b or c", s)),
+        }
+    }
+
+    fn get(&self, machine: &Machine) -> u64 {
+        match self {
+            Register::A => machine.a,
+            Register::B => machine.b,
+            Register::C => machine.c,
+        }
+    }
+
+    fn get_mut<'m>(&self, machine: &'m mut Machine) -> &'m mut u64 {
+        match self {
+            Register::A => &mut machine.a,
+            Register::B => &mut machine.b,
+            Register::C => &mut machine.c,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Operator symbols, ordered so that two-char symbols are tried before their one-char prefixes
+    const SYMBOLS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    fn test(&self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        Comparison::SYMBOLS
+            .iter()
+            .find(|(_, c)| c == self)
+            .map(|(s, _)| *s)
+            .unwrap()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Breakpoint {
+    /// Stop before executing the instruction at this index
+    At(usize),
+    /// Stop once the register comparison holds
+    When(Register, Comparison, u64),
+}
+
+impl Breakpoint {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(Breakpoint::At(index));
+        }
+        for (symbol, comparison) in Comparison::SYMBOLS {
+            if let Some((reg, value)) = s.split_once(symbol) {
+                let register = Register::parse(reg.trim())?;
+                let value = value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|err| format!("Invalid value {:?}: {}", value.trim(), err))?;
+                return Ok(Breakpoint::When(register, comparison, value));
+            }
+        }
+        Err(format!(
+            "Invalid breakpoint {:?}, expected an instruction index or a condition like `a == 0`",
+            s
+        ))
+    }
+
+    fn is_hit(&self, machine: &Machine, pointer: usize) -> bool {
+        match self {
+            Breakpoint::At(index) => pointer == *index,
+            Breakpoint::When(register, comparison, value) => {
+                comparison.test(register.get(machine), *value)
+            }
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::At(index) => write!(f, "at instruction {}", index),
+            Breakpoint::When(register, comparison, value) => {
+                write!(f, "when {:?} {} {}", register, comparison.symbol(), value)
+            }
+        }
+    }
+}
+
+/// Why `continue` stopped
+enum StopReason {
+    Halted,
+    Breakpoint(usize),
+    Output(u64),
+    StepLimit,
+}
+
+struct Debugger {
+    ops: Vec<Op>,
+    initial: Machine,
+    machine: Machine,
+    pointer: usize,
+    output: Vec<u64>,
+    /// State before each of the most recent steps, most recent last
+    history: VecDeque<Snapshot>,
+    /// Most snapshots to keep in `history`
+    history_limit: usize,
+    /// How many of the oldest snapshots have been dropped from `history` to stay within the limit
+    dropped_history: usize,
+    breakpoints: Vec<Breakpoint>,
+    watch_output: bool,
+}
+
+impl Debugger {
+    fn new(machine: Machine, ops: Vec<Op>) -> Self {
+        Debugger {
+            ops,
+            initial: machine,
+            machine,
+            pointer: 0,
+            output: Vec::new(),
+            history: VecDeque::new(),
+            history_limit: MAX_HISTORY,
+            dropped_history: 0,
+            breakpoints: Vec::new(),
+            watch_output: false,
+        }
+    }
+
+    fn is_halted(&self) -> bool {
+        self.pointer >= self.ops.len()
+    }
+
+    /// Run one command, returning `Ok(false)` if the debugger should exit
+    fn execute(&mut self, command: &str) -> Result<bool, String> {
+        let (cmd, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let rest = rest.trim();
+        match cmd {
+            "" => (),
+            "q" | "quit" | "exit" => return Ok(false),
+            "h" | "help" | "?" => println!("{}", HELP),
+            "p" | "print" => self.print_state(),
+            "s" | "step" => {
+                let count = parse_count(rest)?;
+                for _ in 0..count {
+                    if let Some(n) = self.step() {
+                        println!("output: {}", n.to_string().green());
+                    }
+                }
+                self.print_state();
+            }
+            "c" | "continue" => {
+                match self.run_until_stop() {
+                    StopReason::Halted => println!("program halted"),
+                    StopReason::Breakpoint(idx) => {
+                        println!("hit breakpoint #{}: {}", idx, self.breakpoints[idx])
+                    }
+                    StopReason::Output(n) => println!("output: {}", n.to_string().green()),
+                    StopReason::StepLimit => {
+                        println!("stopped after {} steps without halting", MAX_CONTINUE_STEPS)
+                    }
+                }
+                self.print_state();
+            }
+            "r" | "back" => {
+                let count = parse_count(rest)?;
+                for _ in 0..count {
+                    if !self.step_back() {
+                        if self.dropped_history > 0 {
+                            println!(
+                                "can't go back further; only the last {} steps are kept",
+                                self.history_limit
+                            );
+                        } else {
+                            println!("already at the start of the program");
+                        }
+                        break;
+                    }
+                }
+                self.print_state();
+            }
+            "b" | "break" => {
+                if rest.is_empty() {
+                    self.print_breakpoints();
+                } else {
+                    let breakpoint = Breakpoint::parse(rest)?;
+                    if let Breakpoint::At(index) = breakpoint {
+                        if index >= self.ops.len() {
+                            return Err(format!(
+                                "No instruction at index {}; the program has {}",
+                                index,
+                                self.ops.len()
+                            ));
+                        }
+                    }
+                    self.breakpoints.push(breakpoint);
+                    println!("breakpoint #{}: {}", self.breakpoints.len() - 1, breakpoint);
+                }
+            }
+            "d" | "delete" => {
+                let idx = rest
+                    .parse::<usize>()
+                    .map_err(|_| format!("Expected a breakpoint number, got {:?}", rest))?;
+                if idx >= self.breakpoints.len() {
+                    return Err(format!("No breakpoint #{}", idx));
+                }
+                let removed = self.breakpoints.remove(idx);
+                println!("removed breakpoint {}", removed);
+            }
+            "w" | "watch" => {
+                self.watch_output = !self.watch_output;
+                println!(
+                    "output watch {}",
+                    if self.watch_output { "on" } else { "off" }
+                );
+            }
+            "set" => {
+                let (reg, value) = rest.split_once(' ').ok_or("Usage: set <reg> <value>")?;
+                let register = Register::parse(reg)?;
+                let value = value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|err| format!("Invalid value {:?}: {}", value.trim(), err))?;
+                // register edits go in the history too, so `back` can undo them
+                self.save_snapshot();
+                *register.get_mut(&mut self.machine) = value;
+                self.print_state();
+            }
+            "reset" => {
+                self.machine = self.initial;
+                self.pointer = 0;
+                self.output.clear();
+                self.history.clear();
+                self.dropped_history = 0;
+                self.print_state();
+            }
+            other => return Err(format!("Unknown command {:?}, try `help`", other)),
+        }
+        Ok(true)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            machine: self.machine,
+            pointer: self.pointer,
+            output_len: self.output.len(),
+        }
+    }
+
+    /// Record the current state in the history, dropping the oldest snapshot if it's full
+    fn save_snapshot(&mut self) {
+        if self.history.len() >= self.history_limit {
+            self.history.pop_front();
+            if self.dropped_history == 0 {
+                println!(
+                    "{}",
+                    format!(
+                        "history is full; dropping the oldest steps so that only the last {} can be undone",
+                        self.history_limit
+                    )
+                    .yellow()
+                );
+            }
+            self.dropped_history += 1;
+        }
+        self.history.push_back(self.snapshot());
+    }
+
+    /// Execute the instruction at the pointer, returning the value it output (if any).
+    /// Does nothing if the program has already halted.
+    fn step(&mut self) -> Option<u64> {
+        if self.is_halted() {
+            return None;
+        }
+        self.save_snapshot();
+        match self.ops[self.pointer].run(&mut self.machine) {
+            Step::Continue => {
+                self.pointer += 1;
+                None
+            }
+            Step::Output(n) => {
+                self.output.push(n);
+                self.pointer += 1;
+                Some(n)
+            }
+            Step::JumpTo(address) => {
+                self.pointer = address / 2;
+                None
+            }
+        }
+    }
+
+    fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(snapshot) => {
+                self.machine = snapshot.machine;
+                self.pointer = snapshot.pointer;
+                self.output.truncate(snapshot.output_len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Step until the program halts or something interesting happens.
+    /// Always takes at least one step, so that continuing from a breakpoint doesn't immediately stop again.
+    fn run_until_stop(&mut self) -> StopReason {
+        for _ in 0..MAX_CONTINUE_STEPS {
+            if self.is_halted() {
+                return StopReason::Halted;
+            }
+            let output = self.step();
+            if let (Some(n), true) = (output, self.watch_output) {
+                return StopReason::Output(n);
+            }
+            if let Some(idx) = self
+                .breakpoints
+                .iter()
+                .position(|b| b.is_hit(&self.machine, self.pointer))
+            {
+                return StopReason::Breakpoint(idx);
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    fn print_state(&self) {
+        for (idx, op) in self.ops.iter().enumerate() {
+            let marker = if idx == self.pointer { "=>" } else { "  " };
+            let has_breakpoint = self.breakpoints.contains(&Breakpoint::At(idx));
+            let line = format!(
+                "{} {}{:>3}  {:?}",
+                marker,
+                if has_breakpoint { "*" } else { " " },
+                idx,
+                op
+            );
+            if idx == self.pointer {
+                println!("{}", line.bright_blue());
+            } else {
+                println!("{}", line);
+            }
+        }
+        if self.is_halted() {
+            println!("=>  {:>3}  <halt>", self.pointer);
+        }
+        println!(
+            "step {}  registers: {{ A: {}, B: {}, C: {} }}",
+            self.dropped_history + self.history.len(),
+            self.machine.a.to_string().yellow(),
+            self.machine.b.to_string().yellow(),
+            self.machine.c.to_string().yellow()
+        );
+        println!(
+            "output: [{}]",
+            self.output
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+                .green()
+        );
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("no breakpoints");
+        }
+        for (idx, breakpoint) in self.breakpoints.iter().enumerate() {
+            println!("#{}: {}", idx, breakpoint);
+        }
+        println!(
+            "output watch {}",
+            if self.watch_output { "on" } else { "off" }
+        );
+    }
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    if s.is_empty() {
+        Ok(1)
+    } else {
+        s.parse::<usize>()
+            .map_err(|_| format!("Expected a number of steps, got {:?}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ComboOperand;
+    use super::*;
+
+    /// A debugger for a loop that shifts A right one bit per iteration
+    fn countdown_debugger(history_limit: usize) -> Debugger {
+        let machine = Machine {
+            a: 1 << 20,
+            b: 0,
+            c: 0,
+        };
+        let ops = vec![Op::Adv(ComboOperand::Const1), Op::Jnz(0)];
+        let mut debugger = Debugger::new(machine, ops);
+        debugger.history_limit = history_limit;
+        debugger
+    }
+
+    #[test]
+    fn back_undoes_steps() {
+        let mut debugger = countdown_debugger(MAX_HISTORY);
+        for _ in 0..5 {
+            debugger.step();
+        }
+        assert_eq!(debugger.machine.a, 1 << 17);
+        assert_eq!(debugger.pointer, 1);
+        assert!(debugger.step_back());
+        assert!(debugger.step_back());
+        assert_eq!(debugger.machine.a, 1 << 18);
+        assert_eq!(debugger.pointer, 1);
+    }
+
+    #[test]
+    fn history_drops_the_oldest_steps() {
+        let mut debugger = countdown_debugger(3);
+        for _ in 0..10 {
+            debugger.step();
+        }
+        assert_eq!(debugger.history.len(), 3);
+        assert_eq!(debugger.dropped_history, 7);
+        for _ in 0..3 {
+            assert!(debugger.step_back());
+        }
+        assert!(!debugger.step_back());
+        // back at the state before step 8, after four shifts
+        assert_eq!(debugger.machine.a, 1 << 16);
+        assert_eq!(debugger.pointer, 1);
+    }
+
+    #[test]
+    fn reset_forgets_dropped_history() {
+        let mut debugger = countdown_debugger(2);
+        for _ in 0..4 {
+            debugger.step();
+        }
+        debugger.execute("reset").unwrap();
+        assert_eq!(debugger.dropped_history, 0);
+        assert!(debugger.history.is_empty());
+        assert_eq!(debugger.machine.a, 1 << 20);
+    }
+}
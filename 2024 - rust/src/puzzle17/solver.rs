@@ -0,0 +1,537 @@
+//! Finds every initial value of register A that makes a program output a copy of itself.
+//!
+//! Most puzzle inputs are a single loop that consumes a few bits of A per iteration, and
+//! `analyze` checks for that shape so the quick digit-by-digit search can be used. Anything
+//! else falls back to `symbolic_search`, which tracks which bits of each register are known
+//! while executing, and branches on A's bits whenever execution depends on an unknown one.
+
+use super::{run_full, run_single, shr, ComboOperand, Machine, Op, Step};
+use log::{debug, info, warn};
+
+/// The shape of a program that the quick search can handle
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LoopShape {
+    /// How many bits each iteration shifts out of A
+    pub shift: u64,
+}
+
+/// Check the program against the assumptions made by the quick search,
+/// returning a description of each assumption that doesn't hold.
+pub fn analyze(ops: &[Op]) -> Result<LoopShape, Vec<String>> {
+    let mut problems = Vec::new();
+
+    match ops.last() {
+        Some(Op::Jnz(0)) => (),
+        Some(op) => problems.push(format!(
+            "the program should end with `jnz 0`, but ends with `{}`",
+            op.pseudo_code()
+        )),
+        None => problems.push("the program is empty".to_string()),
+    }
+    let jumps = ops.iter().filter(|op| matches!(op, Op::Jnz(_))).count();
+    if jumps > 1 {
+        problems.push(format!(
+            "the program should contain a single jump, but has {}",
+            jumps
+        ));
+    }
+
+    let outputs = ops.iter().filter(|op| matches!(op, Op::Out(_))).count();
+    if outputs != 1 {
+        problems.push(format!(
+            "each iteration should output exactly one value, but the loop has {} `out` instructions",
+            outputs
+        ));
+    }
+
+    let shifts = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::Adv(operand) => Some(operand),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let shift = match shifts.as_slice() {
+        [ComboOperand::Const1] => Some(1),
+        [ComboOperand::Const2] => Some(2),
+        [ComboOperand::Const3] => Some(3),
+        [ComboOperand::Const0] => {
+            problems.push("the loop's `adv` doesn't shift A at all".to_string());
+            None
+        }
+        [operand] => {
+            problems.push(format!(
+                "the loop's `adv` should shift A by a constant, not by {}",
+                operand
+            ));
+            None
+        }
+        _ => {
+            problems.push(format!(
+                "the loop should contain exactly one `adv`, but has {}",
+                shifts.len()
+            ));
+            None
+        }
+    };
+
+    // B and C must be overwritten (in terms of A) before anything reads them,
+    // so that their values from the previous iteration don't matter
+    let (mut b_written, mut c_written) = (false, false);
+    for op in ops {
+        let (reads_b, reads_c) = match op {
+            Op::Bxl(_) => (true, false),
+            Op::Bxc => (true, true),
+            Op::Adv(operand)
+            | Op::Bst(operand)
+            | Op::Out(operand)
+            | Op::Bdv(operand)
+            | Op::Cdv(operand) => (
+                matches!(operand, ComboOperand::RegB),
+                matches!(operand, ComboOperand::RegC),
+            ),
+            Op::Jnz(_) => (false, false),
+        };
+        if reads_b && !b_written {
+            problems.push(format!(
+                "`{}` reads B before the loop has set it from A",
+                op.pseudo_code()
+            ));
+        }
+        if reads_c && !c_written {
+            problems.push(format!(
+                "`{}` reads C before the loop has set it from A",
+                op.pseudo_code()
+            ));
+        }
+        match op {
+            Op::Bst(_) | Op::Bdv(_) => b_written = true,
+            Op::Cdv(_) => c_written = true,
+            _ => (),
+        }
+    }
+
+    match shift {
+        Some(shift) if problems.is_empty() => Ok(LoopShape { shift }),
+        _ => Err(problems),
+    }
+}
+
+/// Find every value for register A that causes the program to output `expected`,
+/// smallest first. The other registers keep their values from `machine`.
+/// Fails if the symbolic search is needed and gives up before finishing.
+pub fn solve_quine(machine: &Machine, ops: &[Op], expected: &[u64]) -> Result<Vec<u64>, String> {
+    let candidates = match analyze(ops) {
+        Ok(shape) => {
+            info!(
+                "Program is a simple loop shifting A by {} bits per output",
+                shape.shift
+            );
+            let mut found = Vec::new();
+            search_digits(ops, expected, shape.shift, 0, &mut found);
+            found
+        }
+        Err(problems) => {
+            for problem in &problems {
+                warn!("Can't use the quick search: {}", problem);
+            }
+            info!("Falling back to a symbolic search over the bits of A");
+            symbolic_search(machine, ops, expected, MAX_TRIALS)?
+        }
+    };
+
+    // both searches can over-approximate at the edges (e.g. the quick search doesn't know
+    // that a leading 0 would end the loop early), so confirm each candidate for real
+    let mut solutions = candidates
+        .into_iter()
+        .filter(|a| {
+            let (_, out) = run_full(Machine { a: *a, ..*machine }, ops);
+            out == expected
+        })
+        .collect::<Vec<_>>();
+    solutions.sort();
+    solutions.dedup();
+    Ok(solutions)
+}
+
+/// Like `reverse_engineer`, but with a configurable shift, and collecting every solution
+/// instead of stopping at the first.
+fn search_digits(ops: &[Op], expected: &[u64], shift: u64, accum: u64, found: &mut Vec<u64>) {
+    let Some((last_output, rest)) = expected.split_last() else {
+        found.push(accum);
+        return;
+    };
+    if accum.leading_zeros() < shift as u32 {
+        // no room left in A for another digit
+        return;
+    }
+    for n in 0..(1 << shift) {
+        let candidate = (accum << shift) | n;
+        let (_, out) = run_single(
+            Machine {
+                a: candidate,
+                b: 0,
+                c: 0,
+            },
+            ops,
+        );
+        if out == [*last_output] {
+            debug!(
+                "{}th output may be satisfied by {}",
+                expected.len(),
+                candidate
+            );
+            search_digits(ops, rest, shift, candidate, found);
+        }
+    }
+}
+
+/// A 64-bit value where only some of the bits are known
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct PartialBits {
+    /// Which bits are known
+    known: u64,
+    /// Value of the known bits; unknown bits are always 0 here
+    value: u64,
+}
+
+impl PartialBits {
+    fn exact(value: u64) -> Self {
+        PartialBits {
+            known: u64::MAX,
+            value,
+        }
+    }
+
+    fn get(&self) -> Option<u64> {
+        (self.known == u64::MAX).then_some(self.value)
+    }
+
+    /// The value as a shift amount, where anything from 64 up shifts everything out.
+    /// That's already decided once any bit from 64 up is known to be set, so unlike `get`
+    /// this doesn't always need every bit to be known.
+    fn shift_amount(&self) -> Option<u64> {
+        if self.value >= 64 {
+            Some(64)
+        } else {
+            self.get()
+        }
+    }
+
+    fn shr(&self, n: u64) -> Self {
+        // bits shifted in from the top are known zeros
+        PartialBits {
+            known: shr(self.known, n) | !shr(u64::MAX, n),
+            value: shr(self.value, n),
+        }
+    }
+
+    fn xor(&self, other: &PartialBits) -> Self {
+        let known = self.known & other.known;
+        PartialBits {
+            known,
+            value: (self.value ^ other.value) & known,
+        }
+    }
+
+    fn mod8(&self) -> Self {
+        PartialBits {
+            known: self.known | !7,
+            value: self.value & 7,
+        }
+    }
+
+    /// Lowest bit position that isn't known yet
+    fn lowest_unknown(&self) -> Option<u32> {
+        (self.known != u64::MAX).then(|| (!self.known).trailing_zeros())
+    }
+}
+
+/// A register machine where each register is only partially known
+#[derive(Debug, Copy, Clone)]
+struct PartialMachine {
+    a: PartialBits,
+    b: PartialBits,
+    c: PartialBits,
+}
+
+impl PartialMachine {
+    fn combo(&self, operand: &ComboOperand) -> PartialBits {
+        match operand {
+            ComboOperand::Const0 => PartialBits::exact(0),
+            ComboOperand::Const1 => PartialBits::exact(1),
+            ComboOperand::Const2 => PartialBits::exact(2),
+            ComboOperand::Const3 => PartialBits::exact(3),
+            ComboOperand::RegA => self.a,
+            ComboOperand::RegB => self.b,
+            ComboOperand::RegC => self.c,
+        }
+    }
+
+    /// Same as `Op::run`, except it returns `None` when the result depends on an unknown bit
+    fn run(&mut self, op: &Op) -> Option<PartialStep> {
+        match op {
+            Op::Adv(operand) => self.a = self.a.shr(self.combo(operand).shift_amount()?),
+            Op::Bxl(value) => self.b = self.b.xor(&PartialBits::exact(*value)),
+            Op::Bst(operand) => self.b = self.combo(operand).mod8(),
+            Op::Jnz(address) => {
+                return if self.a.value != 0 {
+                    Some(PartialStep::Step(Step::JumpTo(*address)))
+                } else if self.a.known == u64::MAX {
+                    Some(PartialStep::Step(Step::Continue))
+                } else {
+                    None
+                };
+            }
+            Op::Bxc => self.b = self.b.xor(&self.c),
+            Op::Out(operand) => return Some(PartialStep::Output(self.combo(operand).mod8())),
+            Op::Bdv(operand) => self.b = self.a.shr(self.combo(operand).shift_amount()?),
+            Op::Cdv(operand) => self.c = self.a.shr(self.combo(operand).shift_amount()?),
+        }
+        Some(PartialStep::Step(Step::Continue))
+    }
+}
+
+/// What one pass through a loop body outputs, given only the lowest `known_bits` bits of A.
+/// B and C start out unknown, so this only works for loops that set them from A before
+/// reading them, as `analyze` checks. Returns `None` if the output depends on any other bits.
+pub(super) fn loop_output(body: &[Op], known_bits: u32, low_a: u64) -> Option<u64> {
+    let unknown = PartialBits { known: 0, value: 0 };
+    let mut state = PartialMachine {
+        a: PartialBits {
+            known: (1 << known_bits) - 1,
+            value: low_a,
+        },
+        b: unknown,
+        c: unknown,
+    };
+    for op in body {
+        if let PartialStep::Output(value) = state.run(op)? {
+            return value.get();
+        }
+    }
+    None
+}
+
+/// Outcome of running one op on a `PartialMachine`
+enum PartialStep {
+    Step(Step),
+    /// Output a value whose bits may not all be known yet
+    Output(PartialBits),
+}
+
+/// Outcome of running a program with a partially-known A
+enum Trial {
+    /// The program halted with the expected output
+    Success,
+    /// The output can't match, no matter what the unknown bits are
+    Mismatch,
+    /// Execution reached something that depends on this (original) bit of A
+    NeedsBit(u32),
+}
+
+/// Give up on programs that run this long without producing the right output, e.g. infinite loops
+const MAX_TRIAL_STEPS: usize = 100_000;
+
+/// Give up on the symbolic search after this many trials. Each trial can branch on another bit
+/// of A, so programs whose output depends on many bits at once (e.g. shifting by a register
+/// whose bits are still unknown) could otherwise take practically forever.
+const MAX_TRIALS: usize = 1_000_000;
+
+/// Depth-first search over A's bits, from the lowest up. Each node runs the program from
+/// the start with the bits of A decided so far, pruning as soon as an output is known to be
+/// wrong, and otherwise branching on whichever bit of A execution got stuck on.
+/// Fails with a description of how far it got if it runs more than `max_trials` trials.
+fn symbolic_search(
+    machine: &Machine,
+    ops: &[Op],
+    expected: &[u64],
+    max_trials: usize,
+) -> Result<Vec<u64>, String> {
+    let mut found = Vec::new();
+    let mut stack = vec![PartialBits { known: 0, value: 0 }];
+    let mut trials = 0;
+    while let Some(a) = stack.pop() {
+        if trials == max_trials {
+            return Err(format!(
+                "Symbolic search gave up after {} trials, with {} branches still open \
+                 (the deepest knowing {} bits of A) and {} solution(s) found so far",
+                trials,
+                stack.len() + 1,
+                stack
+                    .iter()
+                    .chain([&a])
+                    .map(|partial| partial.known.count_ones())
+                    .max()
+                    .unwrap_or(0),
+                found.len()
+            ));
+        }
+        trials += 1;
+        match run_partial(machine, a, ops, expected) {
+            Trial::Mismatch => (),
+            Trial::Success => {
+                // any bits still unknown didn't affect the output; leaving them as zero gives the smallest A
+                debug!(
+                    "Symbolic search found {} (known bits {:#x})",
+                    a.value, a.known
+                );
+                found.push(a.value);
+            }
+            Trial::NeedsBit(bit) => {
+                let mask = 1 << bit;
+                // push 1 first so that the 0 branch (i.e. smaller values) is explored first
+                stack.push(PartialBits {
+                    known: a.known | mask,
+                    value: a.value | mask,
+                });
+                stack.push(PartialBits {
+                    known: a.known | mask,
+                    value: a.value,
+                });
+            }
+        }
+    }
+    info!("Symbolic search ran {} trials", trials);
+    Ok(found)
+}
+
+fn run_partial(machine: &Machine, a: PartialBits, ops: &[Op], expected: &[u64]) -> Trial {
+    let mut state = PartialMachine {
+        a,
+        b: PartialBits::exact(machine.b),
+        c: PartialBits::exact(machine.c),
+    };
+    let mut pointer = 0;
+    let mut out_count = 0;
+    let needs_more_of_a = || match a.lowest_unknown() {
+        Some(bit) => Trial::NeedsBit(bit),
+        // every bit of A is known, but something still couldn't be evaluated; can't happen
+        None => Trial::Mismatch,
+    };
+
+    for _ in 0..MAX_TRIAL_STEPS {
+        if pointer >= ops.len() {
+            return if out_count == expected.len() {
+                Trial::Success
+            } else {
+                Trial::Mismatch
+            };
+        }
+        match state.run(&ops[pointer]) {
+            None => return needs_more_of_a(),
+            Some(PartialStep::Step(Step::JumpTo(address))) => pointer = address / 2,
+            Some(PartialStep::Step(_)) => pointer += 1,
+            Some(PartialStep::Output(value)) => {
+                let Some(want) = expected.get(out_count) else {
+                    return Trial::Mismatch;
+                };
+                if (value.value ^ want) & value.known != 0 {
+                    return Trial::Mismatch;
+                }
+                if value.get().is_none() {
+                    return needs_more_of_a();
+                }
+                out_count += 1;
+                pointer += 1;
+            }
+        }
+    }
+    Trial::Mismatch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{reverse_engineer, Op};
+    use super::*;
+
+    fn parse_ops(program: &[u64]) -> Vec<Op> {
+        program
+            .chunks(2)
+            .map(|pair| Op::try_from((pair[0], pair[1])).unwrap())
+            .collect()
+    }
+
+    fn output_for(a: u64, ops: &[Op]) -> Vec<u64> {
+        run_full(Machine { a, b: 0, c: 0 }, ops).1
+    }
+
+    const EXAMPLE: [u64; 6] = [0, 3, 5, 4, 3, 0];
+
+    /// bst A, bxl 5, cdv B, bxl 6, adv 3, bxc, out B, jnz 0: the usual shape of a puzzle input
+    const TYPICAL: [u64; 16] = [2, 4, 1, 5, 7, 5, 1, 6, 0, 3, 4, 0, 5, 5, 3, 0];
+
+    /// Like `TYPICAL`, but shifting A by B each iteration, so the quick search can't be used
+    const REGISTER_SHIFT: [u64; 14] = [2, 4, 1, 3, 7, 5, 4, 0, 5, 5, 0, 5, 3, 0];
+
+    const ZEROS: Machine = Machine { a: 0, b: 0, c: 0 };
+
+    #[test]
+    fn example_quine() {
+        let ops = parse_ops(&EXAMPLE);
+        assert_eq!(analyze(&ops), Ok(LoopShape { shift: 3 }));
+        // the first `adv 3` throws away the lowest 3 bits before anything uses them
+        let expected = (117440..117448).collect::<Vec<_>>();
+        assert_eq!(solve_quine(&ZEROS, &ops, &EXAMPLE), Ok(expected));
+        assert_eq!(reverse_engineer(&ops, &EXAMPLE, 0), Some(117440));
+    }
+
+    #[test]
+    fn searches_find_the_a_that_made_an_output() {
+        let ops = parse_ops(&TYPICAL);
+        assert_eq!(analyze(&ops), Ok(LoopShape { shift: 3 }));
+        for a in [1, 7, 8, 0o1234, 0o7654321, 0o1_0000_0000_0001] {
+            let expected = output_for(a, &ops);
+            let solutions = solve_quine(&ZEROS, &ops, &expected).unwrap();
+            assert!(solutions.contains(&a), "{} not in {:?}", a, solutions);
+            assert!(solutions.iter().all(|s| output_for(*s, &ops) == expected));
+
+            let symbolic = symbolic_search(&ZEROS, &ops, &expected, MAX_TRIALS).unwrap();
+            assert!(symbolic.contains(&a), "{} not in {:?}", a, symbolic);
+        }
+    }
+
+    #[test]
+    fn symbolic_search_handles_register_shifts() {
+        let ops = parse_ops(&REGISTER_SHIFT);
+        assert!(analyze(&ops).is_err());
+        for a in [1, 0o17, 0o7654321, 1 << 40] {
+            let expected = output_for(a, &ops);
+            let solutions = solve_quine(&ZEROS, &ops, &expected).unwrap();
+            // bits of A that don't affect the output are left as 0, so `a` itself may not be
+            // listed, but something at most `a` with the same output must be
+            assert!(solutions.first().is_some_and(|s| *s <= a), "{:?}", solutions);
+            assert!(solutions.iter().all(|s| output_for(*s, &ops) == expected));
+        }
+    }
+
+    #[test]
+    fn symbolic_search_gives_up_after_max_trials() {
+        let ops = parse_ops(&TYPICAL);
+        let expected = output_for(0o7654321, &ops);
+        let err = symbolic_search(&ZEROS, &ops, &expected, 5).unwrap_err();
+        assert!(
+            err.starts_with("Symbolic search gave up after 5 trials"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn shift_amount_only_needs_one_high_bit() {
+        let partial = PartialBits {
+            known: 1 << 10,
+            value: 1 << 10,
+        };
+        assert_eq!(partial.shift_amount(), Some(64));
+        assert_eq!(
+            PartialBits {
+                known: 63,
+                value: 5
+            }
+            .shift_amount(),
+            None
+        );
+        assert_eq!(PartialBits::exact(5).shift_amount(), Some(5));
+    }
+}
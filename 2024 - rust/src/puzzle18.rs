@@ -1,6 +1,8 @@
-use crate::geometry::{Cardinal, CardinalSet, GridAddress};
-use crate::helper::GenResult;
-use colored::Colorize;
+use crate::geometry::{Cardinal, CardinalSet, Grid, GridAddress, RenderTileChar};
+use crate::helper::{parse_arg, AocError, GenResult, ParseError};
+use aoc_core::parse::InputSpan;
+use crate::viewer::{run_viewer, Simulation};
+use colored::{ColoredString, Colorize};
 use log::{debug, info, warn};
 use pathfinding::directed::astar::astar;
 use std::collections::{HashMap, HashSet};
@@ -8,33 +10,52 @@ use std::fmt::{Display, Formatter, Write};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::time::Instant;
 
-pub fn run(input_path: &Path, is_example: bool) -> GenResult<()> {
+pub fn run(
+    input_path: &Path,
+    is_example: bool,
+    use_viewer: bool,
+    args: &[String],
+) -> GenResult<()> {
     let byte_addresses = {
         let file = File::open(input_path)?;
         let reader = BufReader::new(file);
         let mut addresses = Vec::new();
-        for line in reader.lines() {
+        for (i, line) in reader.lines().enumerate() {
             let line = line?;
-            let (l, r) = line.split_once(',').ok_or("missing comma in input line")?;
-            let addr = GridAddress(l.parse()?, r.parse()?);
+            let addr = parse_byte_address(InputSpan::new(i, &line))?;
             addresses.push(addr);
         }
         addresses
     };
 
-    let grid_size = if is_example { 7 } else { 71 };
-    let part1_length = if is_example { 12 } else { 1024 };
+    let options = parse_options(args, is_example)?;
+    let grid_size = options.grid_size;
+    let part1_length = options.first_batch.min(byte_addresses.len());
     let start = GridAddress(0, 0);
     let goal = GridAddress(grid_size - 1, grid_size - 1);
 
-    let (first_batch, second_batch) = byte_addresses.split_at(part1_length);
+    if use_viewer {
+        run_viewer(ByteFall {
+            grid: Grid::new_default(grid_size, grid_size),
+            bytes: &byte_addresses,
+            num_fallen: 0,
+        })?;
+    }
 
-    let mut fallen_bytes = first_batch.into_iter().cloned().collect::<HashSet<_>>();
+    if options.bench {
+        benchmark(&byte_addresses, part1_length, grid_size);
+        return Ok(());
+    }
 
     // Part 1
-    let (p1_path, p1_cost) = find_path(&GridAddress(0, 0), &goal, &fallen_bytes, grid_size)
-        .ok_or("couldn't find path")?;
+    let fallen_bytes = byte_addresses[..part1_length]
+        .iter()
+        .cloned()
+        .collect::<HashSet<_>>();
+    let (p1_path, p1_cost) =
+        find_path(&start, &goal, &fallen_bytes, grid_size).ok_or("couldn't find path")?;
 
     info!(
         "After {} bytes fallen, best path is {} steps:\n{}",
@@ -47,57 +68,277 @@ pub fn run(input_path: &Path, is_example: bool) -> GenResult<()> {
         }
     );
 
-    let mut part2_positions_itr = second_batch.into_iter().enumerate();
-    let mut current_path = Some((render_path(&p1_path), p1_cost));
+    // Part 2
+    info!("Searching for the blocker with {}", options.search.name());
+    let blocker = options
+        .search
+        .find_blocker(&byte_addresses, part1_length, grid_size);
+
+    match blocker {
+        Some(i) => {
+            let fallen_bytes = byte_addresses[..i].iter().cloned().collect::<HashSet<_>>();
+            if let Some((path, cost)) = find_path(&start, &goal, &fallen_bytes, grid_size) {
+                info!(
+                    "Last possible path has {} steps:\n{}",
+                    cost,
+                    PathGrid {
+                        fallen_bytes: &fallen_bytes,
+                        path_tiles: &render_path(&path),
+                        size: grid_size,
+                    }
+                );
+            }
+            let blocker = byte_addresses[i];
+            info!(
+                "Final blocker found at {} (byte {})",
+                format!("{},{}", blocker.0, blocker.1).bright_blue(),
+                i
+            );
+        }
+        None => warn!("The path is never blocked"),
+    }
+
+    Ok(())
+}
+
+/// Settings that can be overridden with `key=value` arguments, plus `bench`
+struct Options {
+    /// `size=<n>`, the width and height of the memory space
+    grid_size: usize,
+    /// `batch=<n>`, how many bytes have fallen for part 1
+    first_batch: usize,
+    /// `search=astar` or `search=union-find`, how to find the part 2 blocker
+    search: &'static dyn BlockerSearch,
+    /// Compare the part 2 approaches instead of solving
+    bench: bool,
+}
+
+/// Every part 2 approach, with the name used to pick it in the `search=` argument
+const BLOCKER_SEARCHES: [(&str, &dyn BlockerSearch); 2] = [
+    ("union-find", &ReverseUnionFind),
+    ("astar", &IncrementalAStar),
+];
+
+fn parse_options(args: &[String], is_example: bool) -> GenResult<Options> {
+    let mut options = Options {
+        grid_size: if is_example { 7 } else { 71 },
+        first_batch: if is_example { 12 } else { 1024 },
+        search: BLOCKER_SEARCHES[0].1,
+        bench: false,
+    };
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("size", value)) => options.grid_size = parse_arg("size", value)?,
+            Some(("batch", value)) => options.first_batch = parse_arg("batch", value)?,
+            Some(("search", value)) => {
+                options.search = BLOCKER_SEARCHES
+                    .iter()
+                    .find(|(name, _)| *name == value)
+                    .map(|(_, search)| *search)
+                    .ok_or_else(|| {
+                        AocError::Args(format!(
+                            "unknown search {:?} (expected union-find or astar)",
+                            value
+                        ))
+                    })?
+            }
+            None if arg == "bench" => options.bench = true,
+            _ => Err(AocError::Args(format!(
+                "unexpected argument for puzzle 18: {:?} (expected size=N, batch=N, search=NAME or bench)",
+                arg
+            )))?,
+        }
+    }
+    if options.grid_size == 0 {
+        Err(AocError::Args("grid size must be at least 1".to_string()))?
+    }
+    Ok(options)
+}
+
+/// A way of finding which byte first cuts off the exit
+trait BlockerSearch {
+    fn name(&self) -> &'static str;
+
+    /// Index of the first byte (not counting the first `first_batch`, which are assumed
+    /// not to block the path) after which there's no path from the top-left corner to the
+    /// bottom-right, or `None` if there's still a path after every byte has fallen.
+    fn find_blocker(
+        &self,
+        bytes: &[GridAddress],
+        first_batch: usize,
+        grid_size: usize,
+    ) -> Option<usize>;
+}
+
+/// Add bytes one at a time, only re-running A* when a byte lands on the current path
+struct IncrementalAStar;
+
+impl BlockerSearch for IncrementalAStar {
+    fn name(&self) -> &'static str {
+        "Incremental A*"
+    }
 
-    // A binary search for the position of the final blocker byte sounds tempting,
-    // but would involve an O(N) re-building the set of obstacles for each test. Instead,
-    // We'll advance linearly through the list of remaining obstacles in order, adding them
-    // to the mutable obstacles set as we go, and only perform a path-finding test when
-    // an obstacle is added directly in the way of the current path.
-    while let Some((i, next_obstacle)) = part2_positions_itr.next() {
-        if let Some((path, cost)) = &current_path {
+    fn find_blocker(
+        &self,
+        bytes: &[GridAddress],
+        first_batch: usize,
+        grid_size: usize,
+    ) -> Option<usize> {
+        let start = GridAddress(0, 0);
+        let goal = GridAddress(grid_size - 1, grid_size - 1);
+        let mut fallen_bytes = bytes[..first_batch].iter().cloned().collect::<HashSet<_>>();
+        let (path, _) = find_path(&start, &goal, &fallen_bytes, grid_size)?;
+        let mut path = path.into_iter().collect::<HashSet<_>>();
+
+        // A binary search for the position of the final blocker byte sounds tempting,
+        // but would involve an O(N) re-building the set of obstacles for each test. Instead,
+        // We'll advance linearly through the list of remaining obstacles in order, adding them
+        // to the mutable obstacles set as we go, and only perform a path-finding test when
+        // an obstacle is added directly in the way of the current path.
+        for (i, next_obstacle) in bytes.iter().enumerate().skip(first_batch) {
             fallen_bytes.insert(*next_obstacle);
 
             // check if the new obstacle blocks the previously-computed path
-            if path.contains_key(next_obstacle) {
+            if path.contains(next_obstacle) {
                 // search for a new path
-                if let Some((new_path, new_cost)) =
-                    find_path(&start, &goal, &fallen_bytes, grid_size)
-                {
-                    debug!(
-                        "recomputed path due to new obstacle at (index: {}, {:?})",
-                        i,
-                        format!("{:?}", next_obstacle).yellow()
-                    );
-                    current_path = Some((render_path(&new_path), new_cost));
-                } else {
+                let Some((new_path, _)) = find_path(&start, &goal, &fallen_bytes, grid_size) else {
                     // the final blocker was found!
+                    return Some(i);
+                };
+                debug!(
+                    "recomputed path due to new obstacle at (index: {}, {:?})",
+                    i,
+                    format!("{:?}", next_obstacle).yellow()
+                );
+                path = new_path.into_iter().collect();
+            }
+        }
+        None
+    }
+}
+
+/// Start with every byte fallen, then remove them in reverse order, merging each freed tile
+/// with its free neighbors in a union-find. The byte whose removal first connects the start
+/// to the goal is the blocker. This takes one near-linear pass, with no path-finding.
+struct ReverseUnionFind;
+
+impl BlockerSearch for ReverseUnionFind {
+    fn name(&self) -> &'static str {
+        "Reverse union-find"
+    }
+
+    fn find_blocker(
+        &self,
+        bytes: &[GridAddress],
+        first_batch: usize,
+        grid_size: usize,
+    ) -> Option<usize> {
+        let index = |addr: GridAddress| addr.1 * grid_size + addr.0;
+        let in_bounds = |addr: &GridAddress| addr.0 < grid_size && addr.1 < grid_size;
+        let start = index(GridAddress(0, 0));
+        let goal = index(GridAddress(grid_size - 1, grid_size - 1));
+
+        // when each tile gets corrupted, if ever (a tile may be hit by more than one byte)
+        let mut corrupted_at = vec![None; grid_size * grid_size];
+        for (i, addr) in bytes.iter().enumerate().filter(|(_, addr)| in_bounds(addr)) {
+            corrupted_at[index(*addr)].get_or_insert(i);
+        }
 
-                    info!(
-                        "Last possible path has {} steps:\n{}",
-                        cost,
-                        PathGrid {
-                            fallen_bytes: &fallen_bytes,
-                            path_tiles: path,
-                            size: grid_size,
-                        }
-                    );
-
-                    info!(
-                        "Final blocker found at {}",
-                        format!("{},{}", next_obstacle.0, next_obstacle.1).bright_blue()
-                    );
-                    break;
+        let mut sets = UnionFind::new(grid_size * grid_size);
+        let mut is_free = corrupted_at.iter().map(Option::is_none).collect::<Vec<_>>();
+        let free_tile = |addr: GridAddress, sets: &mut UnionFind, is_free: &mut [bool]| {
+            is_free[index(addr)] = true;
+            for neighbor in Cardinal::ALL
+                .iter()
+                .flat_map(|&c| addr.checked_add(c.into()))
+                .filter(in_bounds)
+            {
+                if is_free[index(neighbor)] {
+                    sets.union(index(addr), index(neighbor));
+                }
+            }
+        };
+        for y in 0..grid_size {
+            for x in 0..grid_size {
+                if is_free[index(GridAddress(x, y))] {
+                    free_tile(GridAddress(x, y), &mut sets, &mut is_free);
+                }
+            }
+        }
+        let connected = |sets: &mut UnionFind, is_free: &[bool]| {
+            is_free[start] && is_free[goal] && sets.find(start) == sets.find(goal)
+        };
+
+        if connected(&mut sets, &is_free) {
+            return None;
+        }
+        for (i, addr) in bytes.iter().enumerate().skip(first_batch).rev() {
+            // only the earliest byte to hit a tile actually corrupts it
+            if in_bounds(addr) && corrupted_at[index(*addr)] == Some(i) {
+                free_tile(*addr, &mut sets, &mut is_free);
+                if connected(&mut sets, &is_free) {
+                    return Some(i);
                 }
             }
-        } else {
-            warn!("path no longer available");
-            break;
         }
+        // even without the later bytes, the first batch already blocks the path
+        None
     }
+}
 
-    Ok(())
+/// Disjoint sets of tile indices, with path halving and union by size
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        UnionFind {
+            parent: (0..len).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            (a, b) = (b, a);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// Time each part 2 approach
+fn benchmark(bytes: &[GridAddress], first_batch: usize, grid_size: usize) {
+    for (_, search) in BLOCKER_SEARCHES {
+        let start = Instant::now();
+        let blocker = search.find_blocker(bytes, first_batch, grid_size);
+        info!(
+            "{}: blocker {:?} in {:?}",
+            search.name(),
+            blocker,
+            start.elapsed()
+        );
+    }
+}
+
+/// Parse an `x,y` line from the puzzle input
+fn parse_byte_address(line: InputSpan) -> Result<GridAddress, ParseError> {
+    let (l, r) = line.split_once(",")?;
+    Ok(GridAddress(l.parse()?, r.parse()?))
 }
 
 fn find_path(
@@ -155,6 +396,64 @@ impl Display for PathGrid<'_, '_> {
     }
 }
 
+/// A tile in the memory space, for use with the interactive viewer
+#[derive(Copy, Clone, Debug, Default)]
+enum MemoryTile {
+    #[default]
+    Safe,
+    /// Corrupted by the byte at the given index in the input
+    Corrupted(usize),
+}
+
+/// Bytes falling into the memory space one at a time, for use with the interactive viewer
+#[derive(Clone)]
+struct ByteFall<'a> {
+    grid: Grid<MemoryTile>,
+    bytes: &'a [GridAddress],
+    num_fallen: usize,
+}
+
+impl Simulation for ByteFall<'_> {
+    type Tile = MemoryTile;
+    type Style = LatestByte;
+
+    fn step(&mut self) -> bool {
+        let Some(&addr) = self.bytes.get(self.num_fallen) else {
+            return false;
+        };
+        if let Some(tile) = self.grid.get_mut_at(addr) {
+            *tile = MemoryTile::Corrupted(self.num_fallen);
+        }
+        self.num_fallen += 1;
+        true
+    }
+
+    fn grid(&self) -> &Grid<MemoryTile> {
+        &self.grid
+    }
+
+    fn style(&self) -> LatestByte {
+        LatestByte(self.num_fallen.checked_sub(1))
+    }
+
+    fn caption(&self) -> String {
+        format!("{} bytes fallen", self.num_fallen)
+    }
+}
+
+/// Renders a `MemoryTile` grid, highlighting the byte with the given index
+struct LatestByte(Option<usize>);
+
+impl RenderTileChar<MemoryTile> for LatestByte {
+    fn render_tile_char(&self, tile: &MemoryTile, _: usize, _: usize) -> ColoredString {
+        match tile {
+            MemoryTile::Safe => ".".bright_black(),
+            MemoryTile::Corrupted(i) if Some(*i) == self.0 => "#".red(),
+            MemoryTile::Corrupted(_) => "#".normal(),
+        }
+    }
+}
+
 fn render_path(path: &[GridAddress]) -> HashMap<GridAddress, CardinalSet> {
     let mut out = HashMap::<GridAddress, CardinalSet>::new();
     for i in 1..path.len() {
@@ -181,3 +480,93 @@ fn determine_cardinal(from: &GridAddress, to: &GridAddress) -> Option<Cardinal>
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc_core::rng::XorShift;
+
+    /// The blocker found by re-running A* after every byte
+    fn brute_force_blocker(
+        bytes: &[GridAddress],
+        first_batch: usize,
+        grid_size: usize,
+    ) -> Option<usize> {
+        let start = GridAddress(0, 0);
+        let goal = GridAddress(grid_size - 1, grid_size - 1);
+        (first_batch..bytes.len()).find(|&i| {
+            let fallen = bytes[..=i].iter().cloned().collect::<HashSet<_>>();
+            find_path(&start, &goal, &fallen, grid_size).is_none()
+        })
+    }
+
+    #[test]
+    fn searches_agree_on_the_example() {
+        let input = std::fs::read_to_string("example_inputs/18.txt").unwrap();
+        let bytes = input
+            .lines()
+            .enumerate()
+            .map(|(i, line)| parse_byte_address(InputSpan::new(i, line)).unwrap())
+            .collect::<Vec<_>>();
+        for (name, search) in BLOCKER_SEARCHES {
+            let blocker = search.find_blocker(&bytes, 12, 7);
+            assert_eq!(blocker, Some(20), "{}", name);
+            assert_eq!(bytes[20], GridAddress(6, 1));
+        }
+    }
+
+    #[test]
+    fn searches_agree_on_random_grids() {
+        let mut rng = XorShift::new(0x1405_7b7e_f767_814f);
+        let mut blocked = 0;
+        for _ in 0..50 {
+            let grid_size = 2 + rng.below(10);
+            // every tile except the start and goal falls, in a shuffled order
+            let mut bytes = (0..grid_size * grid_size)
+                .map(|i| GridAddress(i % grid_size, i / grid_size))
+                .filter(|&addr| {
+                    addr != GridAddress(0, 0) && addr != GridAddress(grid_size - 1, grid_size - 1)
+                })
+                .collect::<Vec<_>>();
+            rng.shuffle(&mut bytes);
+            let len = rng.below(bytes.len() + 1);
+            bytes.truncate(len);
+            let first_batch = rng.below(len / 4 + 1);
+            let expected = brute_force_blocker(&bytes, first_batch, grid_size);
+            if expected == Some(first_batch) && first_batch > 0 {
+                // the first batch has to leave a path open
+                let fallen = bytes[..first_batch].iter().cloned().collect::<HashSet<_>>();
+                let goal = GridAddress(grid_size - 1, grid_size - 1);
+                if find_path(&GridAddress(0, 0), &goal, &fallen, grid_size).is_none() {
+                    continue;
+                }
+            }
+            for (name, search) in BLOCKER_SEARCHES {
+                assert_eq!(
+                    search.find_blocker(&bytes, first_batch, grid_size),
+                    expected,
+                    "{} on a {}x{} grid with bytes {:?} (first batch {})",
+                    name,
+                    grid_size,
+                    grid_size,
+                    bytes,
+                    first_batch
+                );
+            }
+            blocked += usize::from(expected.is_some());
+        }
+        assert!(blocked > 10, "only {} grids were ever blocked", blocked);
+    }
+
+    #[test]
+    fn search_is_picked_by_name() {
+        let options = parse_options(&["search=astar".to_string()], true).unwrap();
+        assert_eq!(options.search.name(), IncrementalAStar.name());
+        let options = parse_options(&[], true).unwrap();
+        assert_eq!(options.search.name(), ReverseUnionFind.name());
+        assert!(matches!(
+            parse_options(&["search=dijkstra".to_string()], true),
+            Err(AocError::Args(_))
+        ));
+    }
+}
@@ -1,41 +1,37 @@
-use crate::helper::GenResult;
+use crate::helper::{AocError, GenResult, ParseError};
 use colored::Colorize;
-use log::{debug, info};
+use log::{debug, info, log_enabled, Level};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
-    // Parse input
-    let (tiles, targets) = {
-        let file = File::open(input_path)?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-
-        let tiles = lines
-            .next()
-            .ok_or("missing first line")??
-            .split(", ")
-            .map(str::to_string)
-            .collect::<Vec<_>>();
-        let mut targets = Vec::new();
-        assert!(lines.next().ok_or("missing second line")??.is_empty());
-        while let Some(line) = lines.next() {
-            targets.push(line?);
-        }
-
-        (tiles, targets)
+pub fn run(input_path: &Path, args: &[String]) -> GenResult<()> {
+    let use_automaton = match args {
+        [] => true,
+        [arg] if arg == "aho-corasick" => true,
+        [arg] if arg == "trie" => false,
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 19: {:?} (expected `trie` or `aho-corasick`)",
+            args
+        )))?,
     };
 
+    let (tiles, targets) = parse_input(BufReader::new(File::open(input_path)?))?;
+
     debug!("tiles: {:?}", tiles);
     debug!("targets: {:?}", targets);
 
-    let tile_index = towels::TileIndex::from_iter(tiles);
+    let matcher = if use_automaton {
+        towels::TileMatcher::aho_corasick(&tiles)
+    } else {
+        towels::TileMatcher::trie(&tiles)
+    };
+    info!("Matching tiles with {}", matcher.name());
 
     let mut fit_count = 0; // for part 1
     let mut total_paths = 0; // for part 2
     for target in targets {
-        let d = towels::decompose(target, &tile_index);
+        let d = towels::decompose(target, &matcher);
         debug!("Decomposition: {:?}", d);
         if let Some(segments) = d.to_tiles() {
             fit_count += 1;
@@ -49,6 +45,13 @@ pub fn run(input_path: &Path) -> GenResult<()> {
                 format!("{:?}", segments).green(),
                 num_distinct_paths.to_string().bright_blue()
             );
+
+            // few enough to list them all
+            if num_distinct_paths <= MAX_LISTED_DECOMPOSITIONS && log_enabled!(Level::Debug) {
+                for tiles in d.decompositions() {
+                    debug!("  {:?}", tiles);
+                }
+            }
         } else {
             info!("Fit '{}':\n{}", d.goal, "no path".red());
         }
@@ -65,24 +68,92 @@ pub fn run(input_path: &Path) -> GenResult<()> {
     Ok(())
 }
 
+/// Parse the comma-separated towel patterns on the first line, and the designs to make after
+/// the blank line that follows them
+fn parse_input<R: BufRead>(reader: R) -> GenResult<(Vec<String>, Vec<String>)> {
+    let mut lines = reader.lines();
+
+    let tiles = lines
+        .next()
+        .ok_or_else(|| ParseError::new("missing the list of towel patterns"))??
+        .split(", ")
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let separator = lines
+        .next()
+        .ok_or_else(|| ParseError::new("missing the blank line after the towel patterns"))??;
+    if !separator.is_empty() {
+        Err(
+            ParseError::new("expected a blank line after the towel patterns")
+                .at_line(1, &separator),
+        )?
+    }
+    let targets = lines.collect::<Result<Vec<_>, _>>()?;
+
+    Ok((tiles, targets))
+}
+
+/// Goals with at most this many decompositions get them all listed (at debug level)
+const MAX_LISTED_DECOMPOSITIONS: u128 = 1000;
+
 mod towels {
-    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use aoc_core::trie::{AhoCorasick, Trie};
+    use std::collections::btree_set;
+    use std::collections::BTreeSet;
     use std::fmt::{Debug, Formatter};
-    use std::str::Chars;
+
+    /// How to find the places where tiles occur in a goal string
+    pub enum TileMatcher {
+        /// Look up the tiles that start at each index of the goal, one index at a time
+        Trie(Trie<()>),
+        /// Find every occurrence of every tile in a single pass over the goal
+        AhoCorasick(AhoCorasick<()>),
+    }
+
+    impl TileMatcher {
+        pub fn trie(tiles: &[String]) -> Self {
+            TileMatcher::Trie(tiles.iter().map(|tile| (tile.as_str(), ())).collect())
+        }
+
+        pub fn aho_corasick(tiles: &[String]) -> Self {
+            let trie = tiles.iter().map(|tile| (tile.as_str(), ())).collect();
+            TileMatcher::AhoCorasick(AhoCorasick::new(trie))
+        }
+
+        pub fn name(&self) -> &'static str {
+            match self {
+                TileMatcher::Trie(_) => "a trie",
+                TileMatcher::AhoCorasick(_) => "an Aho-Corasick automaton",
+            }
+        }
+
+        /// `(start, end)` index pairs for every (non-empty) tile that occurs in `goal`
+        fn occurrences(&self, goal: &str) -> Vec<(usize, usize)> {
+            match self {
+                TileMatcher::Trie(trie) => goal
+                    .char_indices()
+                    .flat_map(|(offset, _)| {
+                        trie.prefixes_of(&goal[offset..])
+                            .filter(|(len, _)| *len > 0)
+                            .map(move |(len, _)| (offset, offset + len))
+                    })
+                    .collect(),
+                TileMatcher::AhoCorasick(automaton) => automaton
+                    .find_all(goal)
+                    .into_iter()
+                    .map(|m| (m.start, m.end))
+                    .collect(),
+            }
+        }
+    }
 
     /// Detect all substrings of `goal` that have corresponding tiles from the puzzle input.
     /// Result is effectively a `Set<(start_index, end_index)>`, represented by the `Decomposition` struct.
-    pub fn decompose(goal: String, tiles: &TileIndex) -> Decomposition {
-        let edges_by_index = (0..goal.len())
-            .map(|offset| {
-                let inner_goal = &goal[offset..];
-                tiles
-                    .available_prefix_lengths(inner_goal)
-                    .into_iter()
-                    .map(|len| offset + len)
-                    .collect::<BTreeSet<_>>()
-            })
-            .collect::<Vec<_>>();
+    pub fn decompose(goal: String, tiles: &TileMatcher) -> Decomposition {
+        let mut edges_by_index = vec![BTreeSet::new(); goal.len()];
+        for (start, end) in tiles.occurrences(&goal) {
+            edges_by_index[start].insert(end);
+        }
 
         Decomposition {
             goal,
@@ -94,6 +165,7 @@ mod towels {
     /// Each entry at index `i` in the `edges_by_index` Vec represents a set of outgoing edges
     /// from `i` to the corresponding indexes. An edge from `i` to `j` represents the presence
     /// of a tile (from the puzzle input) matching the characters `&goal[i..j]`
+    #[derive(Eq, PartialEq)]
     pub struct Decomposition {
         pub goal: String,
         edges_by_index: Vec<BTreeSet<usize>>,
@@ -114,122 +186,169 @@ mod towels {
         /// where the path can be interpreted as a series of tiles that could
         /// be concatenated to form the `goal`.
         pub fn to_tiles(&self) -> Option<Vec<&str>> {
-            let (path, _) = pathfinding::directed::astar::astar(
-                &0usize,
-                |i| {
-                    self.edges_by_index
-                        .get(*i)
-                        .iter()
-                        .flat_map(|set| set.iter())
-                        .map(|dest| (*dest, *dest - *i))
-                        .collect::<Vec<_>>()
-                },
-                |i| self.goal.len() - i,
-                |&i| i == self.goal.len(),
-            )?;
-
-            let mut here = 0;
-            let mut out = Vec::new();
-            for &next in path[1..].iter() {
-                let segment = &self.goal[here..next];
-                here = next;
-                out.push(segment);
-            }
-            Some(out)
+            self.decompositions().next()
         }
 
         /// Count the number of distinct paths from 0 to `self.goal.len()`.
         /// Since there can be many ways to reach any given index of the goal string,
         /// and many ways to reach the final goal from any given index, the total count
         /// ends up being exponentially large with respect to the goal length.
-        /// A cache is used to avoid re-computing the path counts for any given index.
-        pub fn how_many_ways(&self) -> u64 {
-            self.how_many_ways_to(self.goal.len(), &mut HashMap::new())
-        }
-
-        fn how_many_ways_to(&self, destination: usize, cache: &mut HashMap<usize, u64>) -> u64 {
-            if destination == 0 {
-                1
-            } else {
-                match cache.get(&destination) {
-                    Some(num_ways) => *num_ways,
-                    None => {
-                        // find edges in the decomposition that lead to `destination`
-                        let mut count = 0;
-                        for (from_idx, edges) in self.edges_by_index.iter().enumerate() {
-                            if edges.contains(&destination) {
-                                count += self.how_many_ways_to(from_idx, cache);
-                            }
-                        }
-                        cache.insert(destination, count);
-                        count
+        /// Counts are accumulated front to back, so each index is visited once,
+        /// and kept as `u128` since they can outgrow a `u64` for long goals.
+        pub fn how_many_ways(&self) -> u128 {
+            let mut ways = vec![0u128; self.goal.len() + 1];
+            ways[0] = 1;
+            for (from_idx, edges) in self.edges_by_index.iter().enumerate() {
+                let here = ways[from_idx];
+                if here > 0 {
+                    for &to_idx in edges {
+                        ways[to_idx] += here;
                     }
                 }
             }
+            ways[self.goal.len()]
         }
-    }
-
-    /// Trie structure used to for fast prefix lookups quickly check whether a given goal string has
-    /// prefixes represented in tiles from the puzzle input.
-    #[derive(Debug)]
-    pub(crate) struct TileIndex {
-        /// Children. If this node represents "foo", then `m.get("d")` would represent "food".
-        m: BTreeMap<char, TileIndex>,
-        /// Whether this node is representative of an actual value in the set. If false,
-        /// it is implied that `m` contains nodes which have `has_value: true`.
-        has_value: bool,
-    }
 
-    impl TileIndex {
-        pub fn new() -> Self {
-            TileIndex {
-                m: BTreeMap::new(),
-                has_value: false,
+        /// Lazily list every way of building the goal from tiles, as with `to_tiles`.
+        /// Dead ends are pruned up front, so each item takes at most one step per tile to find,
+        /// but there may be exponentially many items; see `how_many_ways` before collecting them.
+        pub fn decompositions(&self) -> Decompositions<'_> {
+            // whether the goal can be finished from each index, working back from the end
+            let mut reaches_goal = vec![false; self.goal.len() + 1];
+            reaches_goal[self.goal.len()] = true;
+            for (from_idx, edges) in self.edges_by_index.iter().enumerate().rev() {
+                reaches_goal[from_idx] = edges.iter().any(|to_idx| reaches_goal[*to_idx]);
             }
-        }
-
-        fn insert(&mut self, value: String) {
-            let value_copy = value.clone();
-            let chars = value_copy.chars();
-            self.insert_r(value, chars);
-        }
 
-        fn insert_r(&mut self, value: String, mut path: Chars) {
-            if let Some(c) = path.next() {
-                let child = self.m.entry(c).or_insert_with(|| TileIndex::new());
-                child.insert_r(value, path);
+            let stack = if reaches_goal[0] {
+                vec![(0, self.edges_from(0))]
             } else {
-                self.has_value = true;
+                Vec::new()
+            };
+            Decompositions {
+                decomposition: self,
+                reaches_goal,
+                stack,
             }
         }
 
-        pub fn available_prefix_lengths(&self, target: &str) -> Vec<usize> {
-            let mut out = Vec::new();
-            self.collect_prefix_lengths(target.chars(), 0, &mut out);
-            out
+        fn edges_from(&self, idx: usize) -> btree_set::Iter<'_, usize> {
+            match self.edges_by_index.get(idx) {
+                Some(edges) => edges.iter(),
+                // only the end of the goal has no entry, and it has no outgoing edges
+                None => btree_set::Iter::default(),
+            }
         }
+    }
+
+    /// Iterator returned by `Decomposition::decompositions`
+    pub struct Decompositions<'d> {
+        decomposition: &'d Decomposition,
+        reaches_goal: Vec<bool>,
+        /// Indexes where the current partial path's tiles start, each with the edges from
+        /// that index that haven't been tried yet
+        stack: Vec<(usize, btree_set::Iter<'d, usize>)>,
+    }
 
-        fn collect_prefix_lengths(&self, mut target: Chars, accum: usize, out: &mut Vec<usize>) {
-            if self.has_value {
-                if accum > 0 {
-                    out.push(accum);
+    impl<'d> Iterator for Decompositions<'d> {
+        type Item = Vec<&'d str>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let goal = &self.decomposition.goal;
+            loop {
+                let (idx, edges) = self.stack.last_mut()?;
+                if *idx == goal.len() {
+                    let out = self
+                        .stack
+                        .windows(2)
+                        .map(|pair| &goal[pair[0].0..pair[1].0])
+                        .collect();
+                    self.stack.pop();
+                    return Some(out);
                 }
-            }
-            if let Some(c) = target.next() {
-                if let Some(child) = self.m.get(&c) {
-                    child.collect_prefix_lengths(target, accum + 1, out);
+                match edges.find(|to_idx| self.reaches_goal[**to_idx]) {
+                    Some(&to_idx) => self
+                        .stack
+                        .push((to_idx, self.decomposition.edges_from(to_idx))),
+                    None => {
+                        self.stack.pop();
+                    }
                 }
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_input;
+    use super::towels::{decompose, TileMatcher};
+    use std::io::Cursor;
+
+    const TILES: [&str; 8] = ["r", "wr", "b", "g", "bwu", "rb", "gb", "br"];
+
+    fn matchers(tiles: &[String]) -> [TileMatcher; 2] {
+        [TileMatcher::trie(tiles), TileMatcher::aho_corasick(tiles)]
+    }
 
-    impl FromIterator<String> for TileIndex {
-        fn from_iter<T: IntoIterator<Item = String>>(iter: T) -> Self {
-            let mut root = TileIndex::new();
-            for item in iter {
-                root.insert(item);
+    #[test]
+    fn matchers_agree_on_the_example() {
+        let tiles = TILES.map(str::to_string);
+        let goals = [
+            "brwrr", "bggr", "gbbr", "rrbgbr", "ubwu", "bwurrg", "brgr", "bbrgwb",
+        ];
+        for matcher in matchers(&tiles) {
+            let ways = goals
+                .iter()
+                .map(|goal| decompose(goal.to_string(), &matcher).how_many_ways())
+                .collect::<Vec<_>>();
+            assert_eq!(ways, [2, 1, 4, 6, 0, 1, 2, 0], "{}", matcher.name());
+        }
+        let [trie, automaton] = matchers(&tiles);
+        for goal in goals {
+            assert_eq!(
+                decompose(goal.to_string(), &trie),
+                decompose(goal.to_string(), &automaton),
+                "{}",
+                goal
+            );
+        }
+    }
+
+    #[test]
+    fn listed_decompositions_match_the_count() {
+        let tiles = TILES.map(str::to_string);
+        let matcher = TileMatcher::aho_corasick(&tiles);
+        for goal in ["brwrr", "rrbgbr", "ubwu", "bbrgwb", "rbrbrbrbgbgb"] {
+            let d = decompose(goal.to_string(), &matcher);
+            let listed = d.decompositions().collect::<Vec<_>>();
+            assert_eq!(listed.len() as u128, d.how_many_ways(), "{}", goal);
+            for tiles in listed {
+                assert_eq!(tiles.concat(), goal);
+                assert!(tiles.iter().all(|tile| TILES.contains(tile)));
             }
-            root
         }
     }
+
+    #[test]
+    fn input_is_parsed() {
+        let (tiles, targets) = parse_input(Cursor::new("r, wr, b\n\nbrwrr\nbggr\n")).unwrap();
+        assert_eq!(tiles, ["r", "wr", "b"]);
+        assert_eq!(targets, ["brwrr", "bggr"]);
+    }
+
+    #[test]
+    fn missing_blank_line_is_reported_with_its_location() {
+        let err = parse_input(Cursor::new("r, wr, b\nbrwrr\nbggr\n")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: expected a blank line after the towel patterns\n \
+             --> <input>:2:1\n  |\n2 | brwrr\n  | ^^^^^"
+        );
+        let err = parse_input(Cursor::new("r, wr, b\n")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: missing the blank line after the towel patterns"
+        );
+    }
 }
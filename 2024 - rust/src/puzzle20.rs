@@ -1,5 +1,5 @@
 use crate::geometry::{Cardinal, Grid, GridAddress};
-use crate::helper::GenResult;
+use crate::helper::{GenResult, ParseError};
 use colored::Colorize;
 use log::{debug, info, trace};
 use pathfinding::prelude::dfs;
@@ -10,65 +10,108 @@ use std::io::{BufRead, BufReader};
 use std::path::Path;
 
 pub fn run(input_path: &Path, is_example: bool) -> GenResult<()> {
-    let track = {
-        let file = File::open(input_path)?;
-        let reader = BufReader::new(file);
-        let mut start = GridAddress::default();
-        let mut end = GridAddress::default();
-        let mut rows = Vec::new();
-        for (y, line) in reader.lines().enumerate() {
-            let mut row = Vec::new();
-            for (x, c) in line?.chars().enumerate() {
-                match c {
-                    '#' => row.push(TrackTile::Wall),
-                    '.' => row.push(TrackTile::Open),
-                    'S' => {
-                        start = GridAddress(x, y);
-                        row.push(TrackTile::Open);
-                    }
-                    'E' => {
-                        end = GridAddress(x, y);
-                        row.push(TrackTile::Open);
-                    }
-                    _ => Err("unexpected tile character")?,
-                }
-            }
-            rows.push(row);
-        }
+    let [p1_report, p2_report] = solve(input_path, is_example)?;
 
-        Track {
-            start,
-            end,
-            tiles: Grid { rows },
-        }
-    };
+    info!(
+        "Cheats lasting up to {}ps:\n{}",
+        p1_report.max_duration,
+        histogram_listing(&p1_report.histogram)
+    );
+    info!(
+        "Part 1: found {} skips that save at least {}ps",
+        p1_report.total().to_string().green(),
+        p1_report.threshold
+    );
+
+    info!(
+        "Cheats lasting up to {}ps:\n{}",
+        p2_report.max_duration,
+        histogram_listing(&p2_report.histogram)
+    );
+    info!(
+        "Part 2: found {} skips that save at least {}ps",
+        p2_report.total().to_string().bright_blue(),
+        p2_report.threshold
+    );
 
-    let p1_threshold = if is_example { 2 } else { 100 };
-    let p2_threshold = if is_example { 50 } else { 100 };
+    Ok(())
+}
 
-    let p1_duration = 2;
-    let p2_duration = 20;
+/// The cheats found for one part of the puzzle
+pub struct CheatReport {
+    /// The longest a cheat may last, in picoseconds
+    pub max_duration: usize,
+    /// Cheats saving less time than this aren't counted
+    pub threshold: usize,
+    /// How many cheats save each amount of time
+    pub histogram: BTreeMap<usize, usize>,
+}
+
+impl CheatReport {
+    /// How many cheats save at least `threshold` picoseconds
+    pub fn total(&self) -> usize {
+        self.histogram.values().sum()
+    }
+}
+
+/// Find the cheats for both parts of the puzzle
+pub fn solve(input_path: &Path, is_example: bool) -> GenResult<[CheatReport; 2]> {
+    let track = parse_track(BufReader::new(File::open(input_path)?))?;
 
     info!("Track:\n{}", track);
     let path = get_path(&track).ok_or("Couldn't find path through track")?;
     info!("Path steps: {}", path.len() - 1);
     debug!("Path: {:?}", path);
 
-    let p1_skips = count_skips(&path, p1_threshold, p1_duration);
-    info!(
-        "Part 1: found {} skips that save at least {}ps",
-        p1_skips.to_string().green(),
-        p1_threshold
-    );
+    let steps = step_counts(&track, &path);
 
-    let p2_skips = count_skips(&path, p2_threshold, p2_duration);
-    info!(
-        "Part 2: found {} skips that save at least {}ps",
-        p2_skips.to_string().bright_blue(),
-        p2_threshold
-    );
+    // (max duration, threshold) for each part
+    let settings = if is_example {
+        [(2, 2), (20, 50)]
+    } else {
+        [(2, 100), (20, 100)]
+    };
+    Ok(settings.map(|(max_duration, threshold)| CheatReport {
+        max_duration,
+        threshold,
+        histogram: savings_histogram(cheats(&path, &steps, max_duration), threshold),
+    }))
+}
 
-    Ok(())
+/// Parse the racetrack map, where `S` and `E` mark the (open) start and end tiles
+fn parse_track<R: BufRead>(reader: R) -> GenResult<Track> {
+    let mut start = GridAddress::default();
+    let mut end = GridAddress::default();
+    let mut rows = Vec::new();
+    for (y, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut row = Vec::new();
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '#' => row.push(TrackTile::Wall),
+                '.' => row.push(TrackTile::Open),
+                'S' => {
+                    start = GridAddress(x, y);
+                    row.push(TrackTile::Open);
+                }
+                'E' => {
+                    end = GridAddress(x, y);
+                    row.push(TrackTile::Open);
+                }
+                _ => Err(
+                    ParseError::new(format!("unexpected tile character '{}'", c))
+                        .at_span(y, &line, x, 1),
+                )?,
+            }
+        }
+        rows.push(row);
+    }
+
+    Ok(Track {
+        start,
+        end,
+        tiles: Grid { rows },
+    })
 }
 
 enum TrackTile {
@@ -126,35 +169,154 @@ fn get_path(track: &Track) -> Option<Vec<GridAddress>> {
     )
 }
 
+/// One way to cheat: passing through walls from `start` to `end`, both on the main path
+#[derive(Debug, Copy, Clone)]
+struct Cheat {
+    start: GridAddress,
+    end: GridAddress,
+    /// How many picoseconds the cheat lasts, i.e. the manhattan distance from `start` to `end`
+    duration: usize,
+    /// How many picoseconds quicker the race is than without the cheat
+    saved: usize,
+}
+
+/// For each open tile, how many picoseconds it takes to reach along the main path
+fn step_counts(track: &Track, path: &[GridAddress]) -> Grid<Option<usize>> {
+    let mut steps = Grid {
+        rows: vec![vec![None; track.tiles.width()]; track.tiles.height()],
+    };
+    for (i, addr) in path.iter().enumerate() {
+        steps[*addr] = Some(i);
+    }
+    steps
+}
+
 /// This puzzle is *not* a pathfinding problem.
 /// A "skip" is just a direct path of cardinal-direction steps between two addresses along the path
 /// through the track. The skip duration restricts how far the skip can go, but since collision is
 /// "off" during the skip, we don't actually have to search for a path, only check the distance.
-/// Since there is only a single main path through the track, we can establish the search space for
-/// skips by taking any two addresses in the path that are sufficiently far apart, index-wise.
-fn count_skips(path: &Vec<GridAddress>, threshold: usize, max_skip_duration: usize) -> usize {
-    let mut count = 0;
-    let mut counts_by_savings = BTreeMap::<usize, usize>::new();
-    for i in 0..path.len() {
-        for j in (i + threshold)..path.len() {
-            let start = &path[i];
-            let end = &path[j];
-            let abs_dx = start.0.abs_diff(end.0);
-            let abs_dy = start.1.abs_diff(end.1);
-            let dist = abs_dx + abs_dy;
-            let time_saved = j - i - dist;
-            if dist <= max_skip_duration && time_saved >= threshold {
-                trace!(
-                    "Found skip from {:?} to {:?}, saving {} ps",
+/// So rather than comparing every pair of addresses along the path, each address only needs to look
+/// at the diamond of tiles within `max_duration` steps of it, and check which of those are further
+/// along the path than the skip would take.
+fn cheats<'a>(
+    path: &'a [GridAddress],
+    steps: &'a Grid<Option<usize>>,
+    max_duration: usize,
+) -> impl Iterator<Item = Cheat> + 'a {
+    let reach = max_duration as isize;
+    path.iter().enumerate().flat_map(move |(i, &start)| {
+        (-reach..=reach).flat_map(move |dy| {
+            let reach_x = reach - dy.abs();
+            (-reach_x..=reach_x).filter_map(move |dx| {
+                let end = GridAddress(
+                    start.0.checked_add_signed(dx)?,
+                    start.1.checked_add_signed(dy)?,
+                );
+                let j = (*steps.get_at(end)?)?;
+                let duration = dx.unsigned_abs() + dy.unsigned_abs();
+                let saved = j.checked_sub(i + duration).filter(|saved| *saved > 0)?;
+                Some(Cheat {
                     start,
                     end,
-                    time_saved
-                );
-                count += 1;
-                *counts_by_savings.entry(time_saved).or_default() += 1;
-            }
-        }
+                    duration,
+                    saved,
+                })
+            })
+        })
+    })
+}
+
+/// How many cheats save each amount of time, for cheats saving at least `threshold`
+fn savings_histogram(
+    cheats: impl Iterator<Item = Cheat>,
+    threshold: usize,
+) -> BTreeMap<usize, usize> {
+    let mut counts_by_savings = BTreeMap::<usize, usize>::new();
+    for cheat in cheats.filter(|cheat| cheat.saved >= threshold) {
+        trace!(
+            "Found skip from {:?} to {:?} lasting {} ps, saving {} ps",
+            cheat.start,
+            cheat.end,
+            cheat.duration,
+            cheat.saved
+        );
+        *counts_by_savings.entry(cheat.saved).or_default() += 1;
+    }
+    counts_by_savings
+}
+
+/// Render the histogram the way the puzzle text does, e.g. "- There are 14 cheats that save 2 picoseconds."
+fn histogram_listing(counts_by_savings: &BTreeMap<usize, usize>) -> String {
+    counts_by_savings
+        .iter()
+        .map(|(saved, count)| match count {
+            1 => format!("- There is one cheat that saves {} picoseconds.", saved),
+            _ => format!(
+                "- There are {} cheats that save {} picoseconds.",
+                count, saved
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The breakdown of part 1's example cheats, as given in the puzzle text
+    const EXAMPLE_PART1_LISTING: &str = "\
+- There are 14 cheats that save 2 picoseconds.
+- There are 14 cheats that save 4 picoseconds.
+- There are 2 cheats that save 6 picoseconds.
+- There are 4 cheats that save 8 picoseconds.
+- There are 2 cheats that save 10 picoseconds.
+- There are 3 cheats that save 12 picoseconds.
+- There is one cheat that saves 20 picoseconds.
+- There is one cheat that saves 36 picoseconds.
+- There is one cheat that saves 38 picoseconds.
+- There is one cheat that saves 40 picoseconds.
+- There is one cheat that saves 64 picoseconds.";
+
+    /// The breakdown of part 2's example cheats (saving at least 50ps), as given in the puzzle text
+    const EXAMPLE_PART2_LISTING: &str = "\
+- There are 32 cheats that save 50 picoseconds.
+- There are 31 cheats that save 52 picoseconds.
+- There are 29 cheats that save 54 picoseconds.
+- There are 39 cheats that save 56 picoseconds.
+- There are 25 cheats that save 58 picoseconds.
+- There are 23 cheats that save 60 picoseconds.
+- There are 20 cheats that save 62 picoseconds.
+- There are 19 cheats that save 64 picoseconds.
+- There are 12 cheats that save 66 picoseconds.
+- There are 14 cheats that save 68 picoseconds.
+- There are 12 cheats that save 70 picoseconds.
+- There are 22 cheats that save 72 picoseconds.
+- There are 4 cheats that save 74 picoseconds.
+- There are 3 cheats that save 76 picoseconds.";
+
+    #[test]
+    fn example_histograms_match_the_puzzle_text() {
+        let [p1_report, p2_report] = solve(Path::new("example_inputs/20.txt"), true).unwrap();
+        assert_eq!(
+            histogram_listing(&p1_report.histogram),
+            EXAMPLE_PART1_LISTING
+        );
+        assert_eq!(p1_report.total(), 44);
+        assert_eq!(
+            histogram_listing(&p2_report.histogram),
+            EXAMPLE_PART2_LISTING
+        );
+        assert_eq!(p2_report.total(), 285);
+    }
+
+    #[test]
+    fn unexpected_tiles_are_reported_with_their_location() {
+        let input = "#####\n#S.E#\n##?##\n";
+        let err = parse_track(std::io::Cursor::new(input)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "parse error: unexpected tile character '?'\n --> <input>:3:3\n  |\n3 | ##?##\n  |   ^"
+        );
     }
-    debug!("Skips summary: {:?}", counts_by_savings);
-    count
 }
@@ -1,88 +1,148 @@
 use crate::geometry::{Cardinal, GridAddress};
-use crate::helper::GenResult;
+use crate::helper::{AocError, GenResult, ParseError};
+use aoc_core::parse;
 use colored::Colorize;
 use log::{debug, info};
-use pathfinding::prelude::{astar, astar_bag, astar_bag_collect};
+use pathfinding::prelude::astar_bag;
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter, Write};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::rc::Rc;
-use std::vec::IntoIter;
 
-pub fn run(input_path: &Path) -> GenResult<()> {
+/// Custom keypads can be given as files containing box art like `NUMERIC_LAYOUT`, as in
+/// `cargo run -- 21 <numeric layout file> [<directional layout file>]`
+pub fn run(input_path: &Path, args: &[String]) -> GenResult<()> {
     let input_sequences = {
         let file = File::open(input_path)?;
         let reader = BufReader::new(file);
         reader.lines().collect::<Result<Vec<_>, _>>()?
     };
 
-    let arrow_pad = Keypad::new_directional();
-    let numpad = Keypad::new_numeric();
-
-    for sequence in input_sequences {
-        info!("Goal: {}", sequence);
-
-        // moves that must be input by the first keypad robot in order to get the numpad robot to input the `sequence`
-        let bot1_moves = solve_moves(sequence.chars(), &numpad, numpad.inv_map[&'A']).ok_or(
-            format!("Couldn't determine moves for {} in num pad", sequence),
-        )?;
-        let bot1_keys = bot1_moves.to_string();
-        debug!("  Sequence: {}", bot1_keys);
-
-        // moves that must be input by the second keypad robot in order to get the first keypad robot to input its required sequence
-        let bot2_moves = solve_moves(bot1_keys.chars(), &arrow_pad, arrow_pad.inv_map[&'A'])
-            .ok_or(format!(
-                "Couldn't determine moves for {} in arrow pad",
-                bot1_keys
-            ))?;
-        let bot2_keys = bot2_moves.to_string();
-        debug!("  Sequence 2: {}", bot2_keys);
-
-        // moves that must be input by the protagonist in order for the second keypad robot to input its required sequence
-        let solution_moves = solve_moves(bot2_keys.chars(), &arrow_pad, arrow_pad.inv_map[&'A'])
-            .ok_or(format!(
-                "Couldn't determine moves for {} in second arrow pad",
-                bot2_keys
-            ))?;
-        let solution_keys = solution_moves.to_string();
-        info!("  Sequence 3: {}", solution_keys);
+    let (numpad, arrow_pad) = match args {
+        [] => (Keypad::new_numeric(), Keypad::new_directional()),
+        [numpad_path] => (load_keypad(numpad_path)?, Keypad::new_directional()),
+        [numpad_path, arrows_path] => (load_keypad(numpad_path)?, load_keypad(arrows_path)?),
+        _ => Err(AocError::Args(format!(
+            "unexpected arguments for puzzle 21: {:?}",
+            args
+        )))?,
+    };
+    arrow_pad.validate_controls()?;
+    info!("Numeric keypad:\n{}", numpad.render(Some('A')));
+    info!("Directional keypad:\n{}", arrow_pad.render(Some('A')));
+
+    let part1 = total_complexity(&input_sequences, &numpad, &arrow_pad, 2)?;
+    let part2 = total_complexity(&input_sequences, &numpad, &arrow_pad, 25)?;
+
+    info!("Part 1 total complexity: {}", part1.to_string().green());
+    info!(
+        "Part 2 total complexity: {}",
+        part2.to_string().bright_blue()
+    );
+
+    Ok(())
+}
 
+/// Sum of each code's numeric part times the number of button presses needed to type it,
+/// when there are `robot_depth` robots using directional keypads between you and the numpad robot.
+fn total_complexity(
+    sequences: &[String],
+    numpad: &Keypad,
+    arrow_pad: &Keypad,
+    robot_depth: usize,
+) -> GenResult<usize> {
+    let mut chain = KeypadChain::new(numpad.clone(), arrow_pad.clone(), robot_depth);
+    info!("With {} directional robots:", robot_depth);
+
+    let mut total = 0;
+    for sequence in sequences {
+        let presses = chain
+            .sequence_cost(0, sequence)
+            .ok_or_else(|| format!("Couldn't determine moves for {}", sequence))?;
         let num = sequence
             .chars()
             .filter(|c| c.is_numeric())
             .collect::<String>()
             .parse::<usize>()?;
-        let score = num * solution_moves.0.len();
-        info!(
-            "  Score: {} * {} = {}",
-            num,
-            solution_moves.0.len(),
-            score.to_string().green()
-        );
+        let score = num * presses;
+        info!("  {}: {} * {} = {}", sequence, num, presses, score);
+        total += score;
     }
+    Ok(total)
+}
 
-    Ok(())
+fn load_keypad(path: &str) -> GenResult<Keypad> {
+    let path = Path::new(path);
+    let layout = std::fs::read_to_string(path)?;
+    Ok(Keypad::from_ascii(layout).map_err(|err| err.in_file(path))?)
+}
+
+/// A series of keypads, each one operated by a robot whose arm is controlled by the next keypad.
+/// The last keypad in the chain is pressed directly by you.
+struct KeypadChain {
+    keypads: Vec<Keypad>,
+    /// Memoized `(from_key, to_key, level) -> cost`, where the cost is the number of presses
+    /// on the last keypad needed to move the arm over `keypads[level]` from one key to the other
+    /// and press it, starting (and ending) with every arm further down the chain pointing at `A`.
+    costs: HashMap<(char, char, usize), Option<usize>>,
 }
 
-fn solve_moves<G: IntoIterator<Item = char>>(
-    goal: G,
-    keypad: &Keypad,
-    start: GridAddress,
-) -> Option<RoboMoves> {
-    let mut pos = start;
-    let mut moves = Vec::new();
-    for key in goal.into_iter() {
-        let key_pos = keypad.inv_map.get(&key)?;
-        let moves_to_key = keypad.get_moves(&pos, key_pos)?;
-        for c in moves_to_key {
-            moves.push(RoboMove::Shift(c));
+impl KeypadChain {
+    /// A chain with `target` first, followed by `robot_depth` robot-operated copies of `controls`,
+    /// and finally one more copy of `controls` for you to press.
+    fn new(target: Keypad, controls: Keypad, robot_depth: usize) -> Self {
+        let mut keypads = vec![target];
+        keypads.extend((0..=robot_depth).map(|_| controls.clone()));
+        KeypadChain {
+            keypads,
+            costs: HashMap::new(),
         }
-        moves.push(RoboMove::Press);
-        pos = *key_pos;
     }
-    Some(RoboMoves(moves))
+
+    /// Number of presses needed on the last keypad to type `sequence` on the keypad at `level`,
+    /// starting from `A`. `None` if the sequence contains a key that can't be reached.
+    fn sequence_cost(&mut self, level: usize, sequence: &str) -> Option<usize> {
+        let mut from = 'A';
+        let mut total = 0;
+        for to in sequence.chars() {
+            total += self.key_cost(from, to, level)?;
+            from = to;
+        }
+        Some(total)
+    }
+
+    fn key_cost(&mut self, from: char, to: char, level: usize) -> Option<usize> {
+        if level == self.keypads.len() - 1 {
+            // you can just press the key
+            return Some(1);
+        }
+        if let Some(cost) = self.costs.get(&(from, to, level)) {
+            return *cost;
+        }
+
+        let keypad = &self.keypads[level];
+        let from_addr = *keypad.inv_map.get(&from)?;
+        let to_addr = *keypad.inv_map.get(&to)?;
+        let candidates = keypad
+            .get_all_moves(&from_addr, &to_addr)
+            .into_iter()
+            .map(|moves| {
+                moves
+                    .into_iter()
+                    .map(|dir| RoboMove::Shift(dir).keypad_char())
+                    .chain(std::iter::once(RoboMove::Press.keypad_char()))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+
+        let cost = candidates
+            .iter()
+            .filter_map(|moves| self.sequence_cost(level + 1, moves))
+            .min();
+        debug!("  {} -> {} on level {} costs {:?}", from, to, level, cost);
+        self.costs.insert((from, to, level), cost);
+        cost
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -91,18 +151,6 @@ enum RoboMove {
     Press,
 }
 
-#[derive(Debug, Clone)]
-struct RoboMoves(Vec<RoboMove>);
-
-impl Display for RoboMoves {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for m in &self.0 {
-            f.write_char(m.keypad_char())?;
-        }
-        Ok(())
-    }
-}
-
 impl RoboMove {
     fn keypad_char(&self) -> char {
         match self {
@@ -115,25 +163,32 @@ impl RoboMove {
     }
 }
 
+#[derive(Clone)]
 struct Keypad {
     map: HashMap<GridAddress, char>,
     inv_map: HashMap<char, GridAddress>,
 }
 
-impl FromIterator<(GridAddress, char)> for Keypad {
-    fn from_iter<I: IntoIterator<Item = (GridAddress, char)>>(iter: I) -> Self {
-        let mut map = HashMap::new();
-        let mut inv_map = HashMap::new();
-        for (addr, c) in iter.into_iter() {
-            map.insert(addr, c);
-            inv_map.insert(c, addr);
-        }
-        Self { map, inv_map }
-    }
-}
+/// Key layout of the numeric keypad on the door
+const NUMERIC_LAYOUT: &str = "+---+---+---+
+| 7 | 8 | 9 |
++---+---+---+
+| 4 | 5 | 6 |
++---+---+---+
+| 1 | 2 | 3 |
++---+---+---+
+    | 0 | A |
+    +---+---+";
+
+/// Key layout of the directional keypads used to control the robots
+const DIRECTIONAL_LAYOUT: &str = "    +---+---+
+    | ^ | A |
++---+---+---+
+| < | v | > |
++---+---+---+";
 
 impl Keypad {
-    /// ```
+    /// ```text
     /// +---+---+---+
     /// | 7 | 8 | 9 |
     /// +---+---+---+
@@ -145,25 +200,10 @@ impl Keypad {
     ///     +---+---+
     /// ```
     fn new_numeric() -> Self {
-        Self::from_iter(vec![
-            // first column
-            (GridAddress(0, 0), '7'),
-            (GridAddress(0, 1), '4'),
-            (GridAddress(0, 2), '1'),
-            // second column
-            (GridAddress(1, 0), '8'),
-            (GridAddress(1, 1), '5'),
-            (GridAddress(1, 2), '2'),
-            (GridAddress(1, 3), '0'),
-            // third column
-            (GridAddress(2, 0), '9'),
-            (GridAddress(2, 1), '6'),
-            (GridAddress(2, 2), '3'),
-            (GridAddress(2, 3), 'A'),
-        ])
+        Self::from_ascii(NUMERIC_LAYOUT).expect("built-in numeric layout should be valid")
     }
 
-    /// ```
+    /// ```text
     ///     +---+---+
     ///     | ^ | A |
     /// +---+---+---+
@@ -171,15 +211,150 @@ impl Keypad {
     /// +---+---+---+
     /// ```
     fn new_directional() -> Self {
-        Self::from_iter(vec![
-            // top row
-            (GridAddress(1, 0), '^'),
-            (GridAddress(2, 0), 'A'),
-            // bottom row
-            (GridAddress(0, 1), '<'),
-            (GridAddress(1, 1), 'v'),
-            (GridAddress(2, 1), '>'),
-        ])
+        Self::from_ascii(DIRECTIONAL_LAYOUT).expect("built-in directional layout should be valid")
+    }
+
+    /// Build a keypad from its keys' positions, rejecting duplicate keys or positions.
+    /// Every keypad needs an `A` key, since that's where each robot's arm starts.
+    fn from_keys<I: IntoIterator<Item = (GridAddress, char)>>(keys: I) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        let mut inv_map = HashMap::new();
+        for (addr, c) in keys.into_iter() {
+            if let Some(prev) = inv_map.insert(c, addr) {
+                return Err(format!(
+                    "Duplicate key {:?} at {:?} and {:?}",
+                    c, prev, addr
+                ));
+            }
+            if let Some(prev) = map.insert(addr, c) {
+                return Err(format!(
+                    "Keys {:?} and {:?} are both at {:?}",
+                    prev, c, addr
+                ));
+            }
+        }
+        if !inv_map.contains_key(&'A') {
+            return Err("Keypad has no 'A' key".to_string());
+        }
+        Ok(Self { map, inv_map })
+    }
+
+    /// Parse a keypad drawn as box art like `NUMERIC_LAYOUT`, where each key is a single char
+    /// centered in a 3-char wide cell. Cells left blank (or not drawn at all) are gaps.
+    fn from_ascii<S: AsRef<str>>(layout: S) -> Result<Self, ParseError> {
+        let lines = layout.as_ref().lines().collect::<Vec<_>>();
+        let mut keys = Vec::new();
+        let mut seen = HashMap::new();
+        // only the lines with `|` in them have keys; the rest are borders
+        let key_lines = parse::spans(&lines).filter(|span| span.text().contains('|'));
+        for (y, span) in key_lines.enumerate() {
+            let chars = span.text().chars().collect::<Vec<_>>();
+            for (col, c) in chars.iter().enumerate() {
+                let error_at = |message: String| {
+                    ParseError::new(message).at_span(span.line_index(), span.text(), col, 1)
+                };
+                // cells are 4 columns wide, with walls at multiples of 4 and keys in between
+                match (col % 4, c) {
+                    (_, c) if c.is_whitespace() => continue,
+                    (0, '|') => continue,
+                    (2, _) => (),
+                    (_, '|') => Err(error_at(format!(
+                        "wall in column {} isn't lined up with the cell walls, which are every 4 columns",
+                        col + 1
+                    )))?,
+                    (_, c) => Err(error_at(format!(
+                        "key {:?} in column {} isn't lined up with the middle of a `| {} |` cell",
+                        c,
+                        col + 1,
+                        c
+                    )))?,
+                }
+                let walled = chars.get(col - 2) == Some(&'|') && chars.get(col + 2) == Some(&'|');
+                if !walled {
+                    Err(error_at(format!(
+                        "key {:?} isn't inside a `| {} |` cell",
+                        c, c
+                    )))?
+                }
+                if let Some(prev_row) = seen.insert(*c, y) {
+                    Err(error_at(format!(
+                        "duplicate key {:?}, which already appears in key row {}",
+                        c,
+                        prev_row + 1
+                    )))?
+                }
+                keys.push((GridAddress(col / 4, y), *c));
+            }
+        }
+        Self::from_keys(keys).map_err(ParseError::new)
+    }
+
+    /// Reject keypads that are missing any of the keys needed to control another robot
+    fn validate_controls(&self) -> Result<(), String> {
+        let missing = "^v<>A"
+            .chars()
+            .filter(|c| !self.inv_map.contains_key(c))
+            .collect::<String>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Directional keypad is missing keys: {}", missing))
+        }
+    }
+
+    /// Draw the keypad in the same box art style that `from_ascii` reads,
+    /// highlighting the key that the robot's arm is `pointing_at`.
+    fn render(&self, pointing_at: Option<char>) -> String {
+        let width = self.map.keys().map(|a| a.0 + 1).max().unwrap_or(0);
+        let height = self.map.keys().map(|a| a.1 + 1).max().unwrap_or(0);
+        // whether there's a key at (x, y), where x and y may be one past either edge
+        let has_key = |x: isize, y: isize| {
+            x >= 0 && y >= 0 && self.map.contains_key(&GridAddress(x as usize, y as usize))
+        };
+
+        let mut lines = Vec::new();
+        for y in 0..=height as isize {
+            // border above row `y`
+            let mut border = String::new();
+            for x in 0..=width as isize {
+                let corner = has_key(x - 1, y - 1)
+                    || has_key(x, y - 1)
+                    || has_key(x - 1, y)
+                    || has_key(x, y);
+                border.push(if corner { '+' } else { ' ' });
+                if x < width as isize {
+                    border.push_str(if has_key(x, y - 1) || has_key(x, y) {
+                        "---"
+                    } else {
+                        "   "
+                    });
+                }
+            }
+            lines.push(border.trim_end().to_string());
+
+            if y == height as isize {
+                break;
+            }
+            let mut row = String::new();
+            for x in 0..=width as isize {
+                row.push(if has_key(x - 1, y) || has_key(x, y) {
+                    '|'
+                } else {
+                    ' '
+                });
+                if x < width as isize {
+                    match self.map.get(&GridAddress(x as usize, y as usize)) {
+                        Some(c) if Some(*c) == pointing_at => {
+                            row.push_str(&format!(" {} ", c).reversed().to_string())
+                        }
+                        Some(c) => row.push_str(&format!(" {} ", c)),
+                        None => row.push_str("   "),
+                    }
+                }
+            }
+            lines.push(row.trim_end().to_string());
+        }
+        lines.join("\n")
     }
 
     fn get_all_moves(&self, from: &GridAddress, to: &GridAddress) -> Vec<Vec<Cardinal>> {
@@ -222,45 +397,6 @@ impl Keypad {
         }
     }
 
-    fn get_moves(&self, from: &GridAddress, to: &GridAddress) -> Option<Vec<Cardinal>> {
-        // let (path, _) = astar(
-        //     from,
-        //     |here| {
-        //         self.get_adjacent(here)
-        //             .iter()
-        //             .map(|there| (*there, 1))
-        //             .collect::<Vec<_>>()
-        //     },
-        //     |here| here.0.abs_diff(to.0) + here.1.abs_diff(to.1),
-        //     |&here| here == *to,
-        // )?;
-
-        let (path, _) = astar(
-            &RoboPos::new(*from),
-            |here| here.successors_in(self),
-            |here| here.addr.manhatten_distance_to(to),
-            |here| here.addr == *to,
-        )?;
-
-        let mut out = Vec::new();
-        for i in 1..path.len() {
-            let GridAddress(x1, y1) = path[i - 1].addr;
-            let GridAddress(x2, y2) = path[i].addr;
-            out.push(if x1 < x2 {
-                Cardinal::East
-            } else if x1 > x2 {
-                Cardinal::West
-            } else if y1 < y2 {
-                Cardinal::South
-            } else if y1 > y2 {
-                Cardinal::North
-            } else {
-                panic!("0-distance step in path? {},{} -> {},{}", x1, y1, x2, y2);
-            });
-        }
-        Some(out)
-    }
-
     fn get_adjacent(&self, here: &GridAddress) -> Vec<GridAddress> {
         if self.map.contains_key(here) {
             Cardinal::ALL
@@ -274,84 +410,55 @@ impl Keypad {
     }
 }
 
-struct Robot {
-    pos: GridAddress,
-    keypad: Rc<Keypad>,
-    parent: Option<Box<Robot>>,
-}
-
-fn example() {
-    let numpad = Rc::new(Keypad::new_numeric());
-    let arrows = Rc::new(Keypad::new_directional());
-
-    let outer_arrows_bot = Box::new(Robot {
-        pos: arrows.inv_map[&'A'],
-        keypad: arrows.clone(),
-        parent: None,
-    });
-
-    let inner_arrows_bot = Box::new(Robot {
-        pos: arrows.inv_map[&'A'],
-        keypad: arrows,
-        parent: Some(outer_arrows_bot),
-    });
-
-    let numpad_bot = Robot {
-        pos: numpad.inv_map[&'A'],
-        keypad: numpad,
-        parent: Some(inner_arrows_bot),
-    };
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Robot {
-    fn root(&self) -> &Robot {
-        match &self.parent {
-            Some(parent) => parent.root(),
-            None => self
-        }
+    #[test]
+    fn numeric_layout_round_trips() {
+        assert_eq!(Keypad::new_numeric().render(None), NUMERIC_LAYOUT);
     }
 
-    fn path_to(&self, goal: &GridAddress) {
-        
+    #[test]
+    fn directional_layout_round_trips() {
+        assert_eq!(Keypad::new_directional().render(None), DIRECTIONAL_LAYOUT);
     }
-}
 
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
-struct RoboPos {
-    addr: GridAddress,
-    last_dir: Option<Cardinal>,
-}
+    #[test]
+    fn custom_layout_round_trips() {
+        let layout = "+---+   +---+\n| 1 |   | 2 |\n+---+---+---+\n    | A |\n    +---+";
+        let keypad = Keypad::from_ascii(layout).unwrap();
+        assert_eq!(keypad.render(None), layout);
+    }
 
-impl RoboPos {
-    fn new(addr: GridAddress) -> Self {
-        Self {
-            addr,
-            last_dir: None,
+    fn parse_error(layout: &str) -> String {
+        match Keypad::from_ascii(layout) {
+            Ok(_) => panic!("{:?} should not parse", layout),
+            Err(err) => err.to_string(),
         }
     }
 
-    fn successors_in(&self, keypad: &Keypad) -> Vec<(RoboPos, usize)> {
-        let mut out = Vec::new();
-        if keypad.map.contains_key(&self.addr) {
-            for dir in Cardinal::ALL {
-                if let Some(neighbor) = self.addr.checked_add(dir.into()) {
-                    if keypad.map.contains_key(&neighbor) {
-                        let cost = match self.last_dir {
-                            Some(d) if d == dir => 1,
-                            None => 1,
-                            Some(_) => 2, // penalize changing direction
-                        };
-                        out.push((
-                            RoboPos {
-                                addr: neighbor,
-                                last_dir: Some(dir),
-                            },
-                            cost,
-                        ));
-                    }
-                }
-            }
-        }
-        out
+    #[test]
+    fn misaligned_keys_are_rejected() {
+        let err = parse_error("+---+---+\n| 1 |2  |\n+---+---+");
+        assert!(
+            err.contains("key '2' in column 6 isn't lined up"),
+            "{}",
+            err
+        );
+        let err = parse_error("+---+---+\n| 1 | 2 ||\n+---+---+");
+        assert!(err.contains("wall in column 10"), "{}", err);
+    }
+
+    #[test]
+    fn duplicate_keys_are_rejected() {
+        let err = parse_error("+---+---+\n| 1 | 1 |\n+---+---+");
+        assert!(err.contains("duplicate key '1'"), "{}", err);
+    }
+
+    #[test]
+    fn keys_outside_cells_are_rejected() {
+        let err = parse_error("+---+---+\n| 1 | 2\n+---+---+");
+        assert!(err.contains("isn't inside a `| 2 |` cell"), "{}", err);
     }
 }
@@ -0,0 +1,440 @@
+use crate::geometry::{Grid, GridAddress, RenderTileChar};
+use crate::helper::GenResult;
+use colored::Colorize;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// A puzzle state that can be advanced one step at a time, and rendered as a `Grid`.
+///
+/// Implementors are driven by the interactive `--viewer` mode via `run_viewer`.
+pub trait Simulation: Clone {
+    type Tile: Debug;
+    type Style: RenderTileChar<Self::Tile>;
+
+    /// Advance the simulation by one step.
+    /// Returns `false` (leaving the state unchanged) if the simulation has already finished.
+    fn step(&mut self) -> bool;
+
+    /// The grid representing the current state
+    fn grid(&self) -> &Grid<Self::Tile>;
+
+    /// The style used to render the current state's grid
+    fn style(&self) -> Self::Style;
+
+    /// Short description of the current state, shown in the viewer's status line
+    fn caption(&self) -> String {
+        String::new()
+    }
+}
+
+/// How many steps to take between snapshots of the simulation state.
+/// Stepping backwards restores the nearest snapshot and replays forward from there.
+const CHECKPOINT_INTERVAL: usize = 64;
+
+/// Frames per second cap while playing; faster speeds take several steps per frame.
+const MAX_FPS: u32 = 60;
+
+const MAX_SPEED: u32 = 4096;
+
+const HELP: &str =
+    "space:play/pause  n/p:step  [ ]:speed  +/-:zoom  arrows:cursor  wasd:pan  home:restart  q:quit";
+
+/// Open the interactive terminal viewer for the given simulation, returning once the user quits.
+pub fn run_viewer<S: Simulation>(simulation: S) -> GenResult<()> {
+    let mut viewer = Viewer::new(simulation);
+    let mut stdout = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    // restores the terminal when dropped, even if the event loop fails or panics
+    let _guard = TerminalGuard;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+
+    viewer.event_loop(&mut stdout)
+}
+
+/// Puts the terminal back the way it was before `run_viewer` took it over
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // errors are ignored, since there's nowhere to report them, and raw mode has to be
+        // turned off regardless, or the shell is left unusable
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Viewer<S> {
+    /// `checkpoints[k]` is the simulation state after `k * CHECKPOINT_INTERVAL` steps
+    checkpoints: Vec<S>,
+    current: S,
+    step_index: usize,
+    /// Set once the simulation reports that it can't advance any further
+    last_step: Option<usize>,
+    playing: bool,
+    /// Steps per second while playing
+    speed: u32,
+    /// Number of grid cells (in each dimension) represented by one terminal character
+    zoom: usize,
+    cursor: GridAddress,
+    /// Grid address shown at the top-left corner of the viewport
+    origin: GridAddress,
+    /// Size of the viewport in terminal characters, as of the last draw
+    view_size: (usize, usize),
+}
+
+impl<S: Simulation> Viewer<S> {
+    fn new(simulation: S) -> Self {
+        Viewer {
+            checkpoints: vec![simulation.clone()],
+            current: simulation,
+            step_index: 0,
+            last_step: None,
+            playing: false,
+            speed: 8,
+            zoom: 1,
+            cursor: GridAddress(0, 0),
+            origin: GridAddress(0, 0),
+            view_size: (0, 0),
+        }
+    }
+
+    fn event_loop<W: Write>(&mut self, out: &mut W) -> GenResult<()> {
+        let mut next_tick = Instant::now();
+        loop {
+            self.draw(out)?;
+
+            let timeout = if self.playing {
+                next_tick.saturating_duration_since(Instant::now())
+            } else {
+                Duration::from_secs(60)
+            };
+
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        if !self.handle_key(key) {
+                            return Ok(());
+                        }
+                        next_tick = Instant::now() + self.tick_interval();
+                    }
+                    _ => (),
+                }
+            } else if self.playing {
+                for _ in 0..self.steps_per_tick() {
+                    if !self.step_forward() {
+                        self.playing = false;
+                        break;
+                    }
+                }
+                next_tick += self.tick_interval();
+            }
+        }
+    }
+
+    fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.speed.min(MAX_FPS) as f64)
+    }
+
+    fn steps_per_tick(&self) -> u32 {
+        (self.speed / MAX_FPS).max(1)
+    }
+
+    /// Handle a key press, returning `false` if the viewer should exit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let (view_w, view_h) = self.view_size;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char(' ') => self.playing = !self.playing,
+            KeyCode::Char('n') | KeyCode::Char('.') => {
+                self.playing = false;
+                self.step_forward();
+            }
+            KeyCode::Char('p') | KeyCode::Char(',') => {
+                self.playing = false;
+                self.step_back();
+            }
+            KeyCode::Home => {
+                self.playing = false;
+                self.current = self.checkpoints[0].clone();
+                self.step_index = 0;
+            }
+            KeyCode::Char(']') => self.speed = (self.speed * 2).min(MAX_SPEED),
+            KeyCode::Char('[') => self.speed = (self.speed / 2).max(1),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.set_zoom(self.zoom / 2),
+            KeyCode::Char('-') => self.set_zoom(self.zoom * 2),
+            KeyCode::Up => self.move_cursor(0, -1),
+            KeyCode::Down => self.move_cursor(0, 1),
+            KeyCode::Left => self.move_cursor(-1, 0),
+            KeyCode::Right => self.move_cursor(1, 0),
+            KeyCode::Char('w') => self.pan(0, -(view_h as isize / 4).max(1)),
+            KeyCode::Char('s') => self.pan(0, (view_h as isize / 4).max(1)),
+            KeyCode::Char('a') => self.pan(-(view_w as isize / 4).max(1), 0),
+            KeyCode::Char('d') => self.pan((view_w as isize / 4).max(1), 0),
+            _ => (),
+        }
+        true
+    }
+
+    fn step_forward(&mut self) -> bool {
+        if !self.current.step() {
+            self.last_step = Some(self.step_index);
+            return false;
+        }
+        self.step_index += 1;
+        if self.step_index.is_multiple_of(CHECKPOINT_INTERVAL)
+            && self.step_index / CHECKPOINT_INTERVAL == self.checkpoints.len()
+        {
+            self.checkpoints.push(self.current.clone());
+        }
+        true
+    }
+
+    fn step_back(&mut self) {
+        if self.step_index == 0 {
+            return;
+        }
+        let target = self.step_index - 1;
+        self.current = self.checkpoints[target / CHECKPOINT_INTERVAL].clone();
+        for _ in 0..(target % CHECKPOINT_INTERVAL) {
+            self.current.step();
+        }
+        self.step_index = target;
+    }
+
+    fn set_zoom(&mut self, zoom: usize) {
+        self.zoom = zoom.clamp(1, 16);
+        // keep the viewport aligned to the zoom level, so blocks don't shift around
+        self.origin = GridAddress(
+            self.origin.0 - self.origin.0 % self.zoom,
+            self.origin.1 - self.origin.1 % self.zoom,
+        );
+        self.scroll_to_cursor();
+    }
+
+    /// Move the cursor by the given number of terminal characters (i.e. `zoom` cells each)
+    fn move_cursor(&mut self, dx: isize, dy: isize) {
+        self.cursor = self.offset_within_grid(self.cursor, dx, dy);
+        self.scroll_to_cursor();
+    }
+
+    /// Move the viewport by the given number of terminal characters, dragging the cursor along
+    fn pan(&mut self, dx: isize, dy: isize) {
+        let GridAddress(ox, oy) = self.offset_within_grid(self.origin, dx, dy);
+        self.origin = GridAddress(ox - ox % self.zoom, oy - oy % self.zoom);
+        self.move_cursor(dx, dy);
+    }
+
+    /// Offset `addr` by the given number of terminal characters, clamped to the grid bounds
+    fn offset_within_grid(&self, addr: GridAddress, dx: isize, dy: isize) -> GridAddress {
+        let grid = self.current.grid();
+        let step = self.zoom as isize;
+        GridAddress(
+            addr.0
+                .saturating_add_signed(dx * step)
+                .min(grid.width().saturating_sub(1)),
+            addr.1
+                .saturating_add_signed(dy * step)
+                .min(grid.height().saturating_sub(1)),
+        )
+    }
+
+    /// Adjust the viewport origin so that the cursor is visible
+    fn scroll_to_cursor(&mut self) {
+        let (view_w, view_h) = self.view_size;
+        let span_x = (view_w * self.zoom).max(self.zoom);
+        let span_y = (view_h * self.zoom).max(self.zoom);
+        let align = |n: usize| n - n % self.zoom;
+
+        if self.cursor.0 < self.origin.0 {
+            self.origin.0 = align(self.cursor.0);
+        } else if self.cursor.0 >= self.origin.0 + span_x {
+            self.origin.0 = align(self.cursor.0 + self.zoom - span_x);
+        }
+        if self.cursor.1 < self.origin.1 {
+            self.origin.1 = align(self.cursor.1);
+        } else if self.cursor.1 >= self.origin.1 + span_y {
+            self.origin.1 = align(self.cursor.1 + self.zoom - span_y);
+        }
+    }
+
+    fn draw<W: Write>(&mut self, out: &mut W) -> GenResult<()> {
+        let (cols, rows) = terminal::size()?;
+        let cols = cols as usize;
+        // one line for the status, two for the inspector and help text
+        self.view_size = (cols, (rows as usize).saturating_sub(3));
+        self.scroll_to_cursor();
+
+        let grid = self.current.grid();
+        let style = self.current.style();
+        let zoom = self.zoom;
+
+        let status = format!(
+            "step {}{}  {}  {} steps/s  zoom 1:{}  {}",
+            self.step_index,
+            match self.last_step {
+                Some(last) => format!("/{}", last),
+                None => String::new(),
+            },
+            if self.playing { "playing" } else { "paused" },
+            self.speed,
+            zoom,
+            self.current.caption(),
+        );
+        queue!(
+            out,
+            MoveTo(0, 0),
+            Print(truncate(&status, cols).bold()),
+            Clear(ClearType::UntilNewLine)
+        )?;
+
+        for sy in 0..self.view_size.1 {
+            queue!(out, MoveTo(0, sy as u16 + 1))?;
+            let y = self.origin.1 + sy * zoom;
+            for sx in 0..cols {
+                let x = self.origin.0 + sx * zoom;
+                // when zoomed out, each character shows the top-left tile of its block
+                let Some(tile) = grid.get(x, y) else { break };
+                let under_cursor = (x..x + zoom).contains(&self.cursor.0)
+                    && (y..y + zoom).contains(&self.cursor.1);
+                let c = style.render_tile_char(tile, x, y);
+                if under_cursor {
+                    queue!(out, Print(c.reversed()))?;
+                } else {
+                    queue!(out, Print(c))?;
+                }
+            }
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+        }
+
+        let inspector = match grid.get_at(self.cursor) {
+            Some(tile) => format!("{:?}: {:?}", self.cursor, tile),
+            None => format!("{:?}: <none>", self.cursor),
+        };
+        let bottom = self.view_size.1 as u16 + 1;
+        queue!(
+            out,
+            MoveTo(0, bottom),
+            Print(truncate(&inspector, cols)),
+            Clear(ClearType::UntilNewLine),
+            MoveTo(0, bottom + 1),
+            Print(truncate(HELP, cols).bright_black()),
+            Clear(ClearType::UntilNewLine),
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    s.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts up to `limit`, showing the last digit of the count in a one-tile grid
+    #[derive(Clone)]
+    struct Counter {
+        count: usize,
+        limit: usize,
+        grid: Grid<char>,
+    }
+
+    impl Counter {
+        fn new(limit: usize) -> Self {
+            Counter {
+                count: 0,
+                limit,
+                grid: Grid {
+                    rows: vec![vec!['0']],
+                },
+            }
+        }
+    }
+
+    impl Simulation for Counter {
+        type Tile = char;
+        type Style = ();
+
+        fn step(&mut self) -> bool {
+            if self.count == self.limit {
+                return false;
+            }
+            self.count += 1;
+            self.grid.rows[0][0] = char::from_digit((self.count % 10) as u32, 10).unwrap();
+            true
+        }
+
+        fn grid(&self) -> &Grid<char> {
+            &self.grid
+        }
+
+        fn style(&self) {}
+    }
+
+    /// Check the viewer's position, and that the state there is what stepping from 0 gives
+    fn assert_at_step(viewer: &Viewer<Counter>, step: usize) {
+        assert_eq!(viewer.step_index, step);
+        assert_eq!(viewer.current.count, step);
+        assert_eq!(
+            viewer.current.grid.rows[0][0],
+            char::from_digit((step % 10) as u32, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn stepping_back_replays_from_the_last_checkpoint() {
+        let mut viewer = Viewer::new(Counter::new(200));
+        for step in 1..=2 * CHECKPOINT_INTERVAL + 10 {
+            assert!(viewer.step_forward());
+            assert_at_step(&viewer, step);
+        }
+        let counts = viewer
+            .checkpoints
+            .iter()
+            .map(|c| c.count)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![0, CHECKPOINT_INTERVAL, 2 * CHECKPOINT_INTERVAL]
+        );
+
+        // back across both checkpoint boundaries
+        for step in (CHECKPOINT_INTERVAL - 3..2 * CHECKPOINT_INTERVAL + 10).rev() {
+            viewer.step_back();
+            assert_at_step(&viewer, step);
+        }
+
+        // stepping forward again doesn't add duplicate checkpoints
+        for step in CHECKPOINT_INTERVAL - 2..=2 * CHECKPOINT_INTERVAL + 1 {
+            assert!(viewer.step_forward());
+            assert_at_step(&viewer, step);
+        }
+        assert_eq!(viewer.checkpoints.len(), 3);
+    }
+
+    #[test]
+    fn stepping_stops_at_both_ends() {
+        let mut viewer = Viewer::new(Counter::new(70));
+        viewer.step_back();
+        assert_at_step(&viewer, 0);
+
+        while viewer.step_forward() {}
+        assert_at_step(&viewer, 70);
+        assert_eq!(viewer.last_step, Some(70));
+        assert!(!viewer.step_forward());
+        assert_at_step(&viewer, 70);
+
+        viewer.step_back();
+        assert_at_step(&viewer, 69);
+    }
+}
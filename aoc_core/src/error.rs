@@ -0,0 +1,162 @@
+//! The error type shared by every puzzle, and helpers for saying where an error came from.
+
+use crate::parse::ParseError;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::num::{ParseIntError, TryFromIntError};
+use std::path::Path;
+use std::str::FromStr;
+
+pub type GenError = AocError;
+pub type GenResult<A> = Result<A, GenError>;
+
+/// Error type shared by all of the puzzles
+#[derive(Debug)]
+pub enum AocError {
+    /// Failure reading a file
+    Io(io::Error),
+    /// Failure downloading the puzzle input
+    Download(Box<dyn Error + Send + Sync>),
+    /// The puzzle input didn't have the expected format
+    Parse(ParseError),
+    /// The command-line arguments weren't understood
+    Args(String),
+    /// The puzzle input was well-formed, but the solution couldn't be found
+    Logic(String),
+}
+
+impl Display for AocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AocError::Io(err) => write!(f, "I/O error: {}", err),
+            AocError::Download(err) => write!(f, "couldn't download puzzle input: {}", err),
+            AocError::Parse(err) => write!(f, "{}", err),
+            AocError::Args(message) => write!(f, "invalid arguments: {}", message),
+            AocError::Logic(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for AocError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AocError::Io(err) => Some(err),
+            AocError::Download(err) => Some(err.as_ref()),
+            AocError::Parse(_) | AocError::Args(_) | AocError::Logic(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for AocError {
+    fn from(value: io::Error) -> Self {
+        AocError::Io(value)
+    }
+}
+
+impl From<ParseError> for AocError {
+    fn from(value: ParseError) -> Self {
+        AocError::Parse(value)
+    }
+}
+
+// Number-parsing failures without any location info; callers that know
+// where the number came from should prefer constructing a `ParseError`,
+// or use `parse_arg` for numbers from the command line.
+impl From<ParseIntError> for AocError {
+    fn from(value: ParseIntError) -> Self {
+        AocError::Parse(ParseError::new(value.to_string()))
+    }
+}
+
+impl From<TryFromIntError> for AocError {
+    fn from(value: TryFromIntError) -> Self {
+        AocError::Logic(value.to_string())
+    }
+}
+
+impl From<String> for AocError {
+    fn from(value: String) -> Self {
+        AocError::Logic(value)
+    }
+}
+
+impl From<&str> for AocError {
+    fn from(value: &str) -> Self {
+        AocError::Logic(value.to_string())
+    }
+}
+
+/// Parse a command-line argument, reporting failure as an `AocError::Args`
+/// so it isn't mistaken for a problem with the puzzle input.
+/// `name` says what the argument was for, e.g. `"grid width"`.
+pub fn parse_arg<T>(name: &str, value: &str) -> GenResult<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| AocError::Args(format!("bad {} {:?}: {}", name, value, err)))
+}
+
+/// Helpers for attaching input locations to the errors in a `GenResult`
+pub trait InputContext {
+    /// Attach the input file path to any `ParseError` that doesn't already name a file
+    fn with_input_file(self, path: &Path) -> Self;
+
+    /// Treat a non-I/O error as a problem with the given line of input, pointing at the
+    /// whole line unless the error already has a location.
+    /// `line_index` is 0-based, as from `lines().enumerate()`.
+    fn at_input_line(self, line_index: usize, line: &str) -> Self;
+}
+
+impl<A> InputContext for GenResult<A> {
+    fn with_input_file(self, path: &Path) -> Self {
+        self.map_err(|err| match err {
+            AocError::Parse(parse_err) if parse_err.file.is_none() => {
+                AocError::Parse(parse_err.in_file(path))
+            }
+            other => other,
+        })
+    }
+
+    fn at_input_line(self, line_index: usize, line: &str) -> Self {
+        self.map_err(|err| match err {
+            AocError::Parse(parse_err) if parse_err.location.is_none() => {
+                AocError::Parse(parse_err.at_line(line_index, line))
+            }
+            AocError::Logic(message) => {
+                AocError::Parse(ParseError::new(message).at_line(line_index, line))
+            }
+            other => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_arguments_are_not_blamed_on_the_input_file() {
+        let result = parse_arg::<usize>("width", "bench").with_input_file(Path::new("15.txt"));
+        let message = result.unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "invalid arguments: bad width \"bench\": invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn input_errors_get_the_input_file() {
+        let result: GenResult<u32> = "x".parse::<u32>().map_err(AocError::from);
+        let err = result.with_input_file(Path::new("15.txt")).unwrap_err();
+        match err {
+            AocError::Parse(parse_err) => {
+                assert_eq!(parse_err.file.as_deref(), Some(Path::new("15.txt")))
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Piecewise shifts of the number line, like the "x-to-y maps" of 2023 day 5.
+//!
+//! An `IntervalMap` is a set of disjoint ranges, each of which adds its own offset to the numbers
+//! in it; numbers outside every range map to themselves. Maps can be composed into a single map,
+//! inverted (when no two numbers map to the same place), and applied to whole ranges at once,
+//! so the work depends on the number of ranges rather than the number of numbers in them.
+
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Add, Neg, Range, Sub};
+
+/// The arithmetic an `IntervalMap` needs; implemented by all the signed integer types.
+/// `Default` is expected to be zero.
+pub trait Offset:
+    Copy + Ord + Default + Add<Output = Self> + Sub<Output = Self> + Neg<Output = Self>
+{
+}
+
+impl<T> Offset for T where
+    T: Copy + Ord + Default + Add<Output = T> + Sub<Output = T> + Neg<Output = T>
+{
+}
+
+/// A function on numbers that shifts each of a set of disjoint ranges by its own offset
+#[derive(Clone, PartialEq, Eq)]
+pub struct IntervalMap<T> {
+    /// Non-empty, disjoint ranges, sorted by start, with the offset for each
+    pieces: Vec<(Range<T>, T)>,
+}
+
+impl<T: Offset> Default for IntervalMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Offset> IntervalMap<T> {
+    /// The identity map, which leaves every number where it is
+    pub fn new() -> Self {
+        IntervalMap { pieces: Vec::new() }
+    }
+
+    /// Shift the numbers in `range` by `offset`.
+    /// Panics if `range` overlaps a range that was already inserted; empty ranges are ignored.
+    pub fn insert(&mut self, range: Range<T>, offset: T) {
+        let inserted = self.try_insert(range, offset);
+        assert!(inserted.is_ok(), "inserted range overlaps an existing one");
+    }
+
+    /// Shift the numbers in `range` by `offset`, unless `range` overlaps a range that was
+    /// already inserted, in which case the map is left as it was. Empty ranges are ignored.
+    pub fn try_insert(&mut self, range: Range<T>, offset: T) -> Result<(), OverlapError<T>> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let index = self.pieces.partition_point(|(r, _)| r.start < range.start);
+        let prev = index.checked_sub(1).map(|i| &self.pieces[i].0);
+        let next = self.pieces.get(index).map(|(r, _)| r);
+        let overlapped = prev
+            .filter(|prev| prev.end > range.start)
+            .or(next.filter(|next| next.start < range.end));
+        if let Some(existing) = overlapped {
+            return Err(OverlapError {
+                existing: existing.clone(),
+                inserted: range,
+            });
+        }
+        self.pieces.insert(index, (range, offset));
+        Ok(())
+    }
+
+    /// Each shifted range, in order, with its offset
+    pub fn pieces(&self) -> impl Iterator<Item = (&Range<T>, T)> {
+        self.pieces.iter().map(|(range, offset)| (range, *offset))
+    }
+
+    /// Where the map sends `x`
+    pub fn get(&self, x: T) -> T {
+        let index = self.pieces.partition_point(|(r, _)| r.start <= x);
+        match index.checked_sub(1).map(|i| &self.pieces[i]) {
+            Some((range, offset)) if range.contains(&x) => x + *offset,
+            _ => x,
+        }
+    }
+
+    /// Cut `range` into the parts that get shifted by the same amount, in order.
+    /// Parts outside every piece have an offset of `None`.
+    fn split(&self, range: Range<T>) -> Vec<(Range<T>, Option<T>)> {
+        let mut out = Vec::new();
+        let mut index = self.pieces.partition_point(|(r, _)| r.end <= range.start);
+        let mut cursor = range.start;
+        while cursor < range.end {
+            match self.pieces.get(index) {
+                Some((piece, offset)) if piece.start <= cursor => {
+                    let end = piece.end.min(range.end);
+                    out.push((cursor..end, Some(*offset)));
+                    cursor = end;
+                    index += 1;
+                }
+                next => {
+                    let end = next.map_or(range.end, |(piece, _)| piece.start.min(range.end));
+                    out.push((cursor..end, None));
+                    cursor = end;
+                }
+            }
+        }
+        out
+    }
+
+    /// Where the map sends every number in `range`, as sorted, disjoint, non-adjacent ranges
+    pub fn image_of(&self, range: Range<T>) -> Vec<Range<T>> {
+        let shifted = self.split(range).into_iter().map(|(part, offset)| {
+            let offset = offset.unwrap_or_default();
+            part.start + offset..part.end + offset
+        });
+        normalize_ranges(shifted)
+    }
+
+    /// The map that applies `self` and then `next`, i.e. `x -> next.get(self.get(x))`
+    pub fn compose(&self, next: &IntervalMap<T>) -> IntervalMap<T> {
+        let mut out = IntervalMap::new();
+        // numbers that `self` moves end up wherever `next` sends their new positions
+        for (range, offset) in &self.pieces {
+            let moved = range.start + *offset..range.end + *offset;
+            for (part, next_offset) in next.split(moved) {
+                out.insert(
+                    part.start - *offset..part.end - *offset,
+                    *offset + next_offset.unwrap_or_default(),
+                );
+            }
+        }
+        // numbers that `self` leaves alone are only moved by `next`
+        for (range, next_offset) in &next.pieces {
+            for (part, offset) in self.split(range.clone()) {
+                if offset.is_none() {
+                    out.insert(part, *next_offset);
+                }
+            }
+        }
+        out.normalize();
+        out
+    }
+
+    /// The map that undoes this one, or `None` if two numbers are sent to the same place.
+    ///
+    /// That happens unless the shifted ranges land on exactly the numbers they were moved from,
+    /// since every number outside them stays where it is.
+    pub fn invert(&self) -> Option<IntervalMap<T>> {
+        let shifted = |(range, offset): &(Range<T>, T)| range.start + *offset..range.end + *offset;
+        let mut images = self.pieces.iter().map(shifted).collect::<Vec<_>>();
+        images.sort_by_key(|r| r.start);
+        if images.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return None;
+        }
+        let domain = self.pieces.iter().map(|(range, _)| range.clone());
+        if normalize_ranges(images) != normalize_ranges(domain) {
+            return None;
+        }
+        let mut out = IntervalMap::new();
+        for piece in &self.pieces {
+            out.insert(shifted(piece), -piece.1);
+        }
+        Some(out)
+    }
+
+    /// Drop pieces that don't move anything, and merge neighboring pieces with the same offset,
+    /// so that equal maps have the same pieces.
+    pub fn normalize(&mut self) {
+        let mut merged: Vec<(Range<T>, T)> = Vec::with_capacity(self.pieces.len());
+        for (range, offset) in self.pieces.drain(..) {
+            if offset == T::default() {
+                continue;
+            }
+            match merged.last_mut() {
+                Some((prev, prev_offset)) if prev.end == range.start && *prev_offset == offset => {
+                    prev.end = range.end;
+                }
+                _ => merged.push((range, offset)),
+            }
+        }
+        self.pieces = merged;
+    }
+}
+
+/// Error returned by `IntervalMap::try_insert` when the new range overlaps an existing one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlapError<T> {
+    /// The range that was already in the map
+    pub existing: Range<T>,
+    /// The range that couldn't be inserted
+    pub inserted: Range<T>,
+}
+
+impl<T: Debug> Display for OverlapError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "range {:?} overlaps the existing range {:?}",
+            self.inserted, self.existing
+        )
+    }
+}
+
+impl<T: Debug> std::error::Error for OverlapError<T> {}
+
+impl<T: Offset> FromIterator<(Range<T>, T)> for IntervalMap<T> {
+    fn from_iter<I: IntoIterator<Item = (Range<T>, T)>>(iter: I) -> Self {
+        let mut out = IntervalMap::new();
+        for (range, offset) in iter {
+            out.insert(range, offset);
+        }
+        out
+    }
+}
+
+impl<T: Debug> Debug for IntervalMap<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.pieces.iter().map(|(range, offset)| (range, offset)))
+            .finish()
+    }
+}
+
+/// Sort the ranges and merge any that overlap or touch, dropping empty ones
+pub fn normalize_ranges<T: Copy + Ord>(
+    ranges: impl IntoIterator<Item = Range<T>>,
+) -> Vec<Range<T>> {
+    let mut ranges = ranges
+        .into_iter()
+        .filter(|r| !r.is_empty())
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+    let mut out: Vec<Range<T>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match out.last_mut() {
+            Some(prev) if prev.end >= range.start => prev.end = prev.end.max(range.end),
+            _ => out.push(range),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+    use std::collections::BTreeSet;
+
+    /// Every number that a map could move, or move something to, in the random maps below
+    const WINDOW: Range<i64> = -10..45;
+
+    /// Where the pieces send `x`, found by checking every piece
+    fn reference_get(pieces: &[(Range<i64>, i64)], x: i64) -> i64 {
+        pieces
+            .iter()
+            .find(|(range, _)| range.contains(&x))
+            .map_or(x, |(_, offset)| x + offset)
+    }
+
+    /// Up to 5 disjoint pieces within `0..30`, with offsets from -5 to 5
+    fn random_pieces(rng: &mut XorShift) -> Vec<(Range<i64>, i64)> {
+        let mut pieces = Vec::new();
+        let mut start = rng.below(5) as i64;
+        for _ in 0..rng.below(6) {
+            let end = start + 1 + rng.below(6) as i64;
+            if end > 30 {
+                break;
+            }
+            pieces.push((start..end, rng.below(11) as i64 - 5));
+            // sometimes leave a gap, sometimes make the next piece touch this one
+            start = end + rng.below(3) as i64;
+        }
+        // insertion order shouldn't matter
+        rng.shuffle(&mut pieces);
+        pieces
+    }
+
+    #[test]
+    fn get_matches_the_pieces() {
+        let mut rng = XorShift::new(0xa076_1d64_78bd_642f);
+        for _ in 0..200 {
+            let pieces = random_pieces(&mut rng);
+            let map = pieces.iter().cloned().collect::<IntervalMap<_>>();
+            for x in WINDOW {
+                assert_eq!(map.get(x), reference_get(&pieces, x), "{:?} at {}", map, x);
+            }
+            let mut normalized = map.clone();
+            normalized.normalize();
+            for x in WINDOW {
+                assert_eq!(normalized.get(x), map.get(x), "{:?} at {}", normalized, x);
+            }
+        }
+    }
+
+    #[test]
+    fn compose_applies_both_maps() {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..200 {
+            let first = random_pieces(&mut rng)
+                .into_iter()
+                .collect::<IntervalMap<_>>();
+            let second = random_pieces(&mut rng)
+                .into_iter()
+                .collect::<IntervalMap<_>>();
+            let composed = first.compose(&second);
+            for x in WINDOW {
+                assert_eq!(
+                    composed.get(x),
+                    second.get(first.get(x)),
+                    "{:?} then {:?} at {}",
+                    first,
+                    second,
+                    x
+                );
+            }
+            // normalized, so no piece does nothing and no two neighbors could be merged
+            let pieces = composed.pieces().collect::<Vec<_>>();
+            assert!(pieces.iter().all(|(_, offset)| *offset != 0));
+            assert!(pieces
+                .windows(2)
+                .all(|pair| pair[0].0.end < pair[1].0.start || pair[0].1 != pair[1].1));
+        }
+    }
+
+    #[test]
+    fn invert_undoes_one_to_one_maps() {
+        let mut rng = XorShift::new(0x1234_5678_9abc_def1);
+        let mut inverted = 0;
+        for i in 0..400 {
+            let map = if i % 2 == 0 {
+                random_pieces(&mut rng)
+                    .into_iter()
+                    .collect::<IntervalMap<_>>()
+            } else {
+                // swapping neighboring blocks is always one-to-one
+                let (start, left, right) = (
+                    rng.below(10) as i64,
+                    1 + rng.below(5) as i64,
+                    1 + rng.below(5) as i64,
+                );
+                [
+                    (start..start + left, right),
+                    (start + left..start + left + right, -left),
+                ]
+                .into_iter()
+                .collect()
+            };
+            let outputs = WINDOW.map(|x| map.get(x)).collect::<BTreeSet<_>>();
+            let one_to_one = outputs.len() == WINDOW.count();
+            match map.invert() {
+                Some(inverse) => {
+                    assert!(one_to_one, "{:?} isn't one-to-one", map);
+                    for x in WINDOW {
+                        assert_eq!(inverse.get(map.get(x)), x, "{:?} at {}", map, x);
+                        assert_eq!(map.get(inverse.get(x)), x, "{:?} at {}", map, x);
+                    }
+                    inverted += 1;
+                }
+                None => assert!(!one_to_one, "{:?} should be invertible", map),
+            }
+        }
+        assert!(inverted > 200, "only {} maps were inverted", inverted);
+    }
+
+    #[test]
+    fn image_of_covers_exactly_the_mapped_numbers() {
+        let mut rng = XorShift::new(0x0fed_cba9_8765_4321);
+        for _ in 0..200 {
+            let map = random_pieces(&mut rng)
+                .into_iter()
+                .collect::<IntervalMap<_>>();
+            let start = rng.below(40) as i64 - 5;
+            let range = start..start + rng.below(20) as i64;
+
+            let image = map.image_of(range.clone());
+            assert!(
+                image.windows(2).all(|pair| pair[0].end < pair[1].start),
+                "{:?} isn't sorted, disjoint and non-adjacent",
+                image
+            );
+            let covered = image.iter().cloned().flatten().collect::<BTreeSet<_>>();
+            let expected = range.clone().map(|x| map.get(x)).collect::<BTreeSet<_>>();
+            assert_eq!(covered, expected, "{:?} of {:?}", map, range);
+        }
+    }
+
+    #[test]
+    fn try_insert_rejects_overlaps() {
+        let mut map = IntervalMap::new();
+        assert_eq!(map.try_insert(10..20, 5), Ok(()));
+        assert_eq!(
+            map.try_insert(15..25, 1),
+            Err(OverlapError {
+                existing: 10..20,
+                inserted: 15..25,
+            })
+        );
+        assert_eq!(
+            map.try_insert(0..11, 1),
+            Err(OverlapError {
+                existing: 10..20,
+                inserted: 0..11,
+            })
+        );
+        assert!(map.try_insert(12..13, 1).is_err());
+        assert!(map.try_insert(5..30, 1).is_err());
+        // touching is fine, and empty ranges are ignored
+        assert_eq!(map.try_insert(20..25, 1), Ok(()));
+        assert_eq!(map.try_insert(0..10, -1), Ok(()));
+        assert_eq!(map.try_insert(15..15, 3), Ok(()));
+        assert_eq!(
+            map.pieces().collect::<Vec<_>>(),
+            [(&(0..10), -1), (&(10..20), 5), (&(20..25), 1)]
+        );
+        assert_eq!(
+            map.try_insert(24..26, 1).unwrap_err().to_string(),
+            "range 24..26 overlaps the existing range 20..25"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps an existing one")]
+    fn insert_panics_on_overlaps() {
+        let mut map = IntervalMap::new();
+        map.insert(0..10, 1);
+        map.insert(5..15, 2);
+    }
+
+    #[test]
+    fn normalize_ranges_merges_touching_ranges() {
+        assert_eq!(
+            normalize_ranges([5..8, 0..2, 2..3, 7..10, 4..4, 12..13]),
+            [0..3, 5..10, 12..13]
+        );
+    }
+}
@@ -0,0 +1,8 @@
+//! Helpers shared between the Advent of Code solutions for each year.
+
+pub mod error;
+pub mod interval_map;
+pub mod multiset;
+pub mod parse;
+pub mod rng;
+pub mod trie;
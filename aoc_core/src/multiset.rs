@@ -0,0 +1,262 @@
+//! Counting items that multiply, when only the number of each kind of item matters.
+//!
+//! Puzzles in the "lanternfish" style have every item turn into some number of new items at
+//! each step, so the total grows exponentially. Since identical items always turn into the
+//! same things, it's enough to track how many of each distinct item there are, which stays small.
+//!
+//! `CountedMultiset` does this one step at a time for the whole population. `ExpansionCounter`
+//! instead counts the descendants of one item at a time, depth-first, memoizing on
+//! `(item, remaining steps)`, which suits questions like "how many after N steps" for a few items.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+
+/// A bag of items, stored as a count per distinct item
+#[derive(Clone, PartialEq, Eq)]
+pub struct CountedMultiset<T: Hash + Eq> {
+    counts: HashMap<T, u128>,
+}
+
+impl<T: Hash + Eq> Default for CountedMultiset<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Hash + Eq> CountedMultiset<T> {
+    pub fn new() -> Self {
+        CountedMultiset {
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Add `count` copies of `item`
+    pub fn insert(&mut self, item: T, count: u128) {
+        if count > 0 {
+            *self.counts.entry(item).or_insert(0) += count;
+        }
+    }
+
+    pub fn count_of(&self, item: &T) -> u128 {
+        self.counts.get(item).copied().unwrap_or(0)
+    }
+
+    /// Total number of items, counting duplicates
+    pub fn total(&self) -> u128 {
+        self.counts.values().sum()
+    }
+
+    /// Number of distinct items
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Each distinct item, with how many copies there are
+    pub fn iter(&self) -> impl Iterator<Item = (&T, u128)> {
+        self.counts.iter().map(|(item, count)| (item, *count))
+    }
+
+    /// Replace every item with whatever `rule` turns it into.
+    /// The rule only runs once per distinct item, however many copies there are.
+    pub fn evolve<R, I>(&self, mut rule: R) -> Self
+    where
+        R: FnMut(&T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        let mut next = CountedMultiset::new();
+        for (item, count) in &self.counts {
+            for child in rule(item) {
+                next.insert(child, *count);
+            }
+        }
+        next
+    }
+
+    /// Apply `evolve` for `steps` steps, calling `on_step` with the step number (starting from 1)
+    /// and the resulting multiset after each one.
+    pub fn evolve_n<R, I>(
+        &self,
+        steps: usize,
+        mut rule: R,
+        mut on_step: impl FnMut(usize, &Self),
+    ) -> Self
+    where
+        T: Clone,
+        R: FnMut(&T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        let mut current = self.clone();
+        for step in 1..=steps {
+            current = current.evolve(&mut rule);
+            on_step(step, &current);
+        }
+        current
+    }
+}
+
+impl<T: Hash + Eq> FromIterator<T> for CountedMultiset<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut out = CountedMultiset::new();
+        for item in iter {
+            out.insert(item, 1);
+        }
+        out
+    }
+}
+
+impl<T: Hash + Eq + Debug> Debug for CountedMultiset<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.counts.iter()).finish()
+    }
+}
+
+/// Counts how many items a single item turns into after some number of steps, remembering
+/// the answer for each `(item, remaining steps)` pair so that shared descendants are only
+/// expanded once.
+pub struct ExpansionCounter<T, R> {
+    rule: R,
+    memo: HashMap<(T, usize), u128>,
+}
+
+impl<T, R, I> ExpansionCounter<T, R>
+where
+    T: Hash + Eq + Clone,
+    R: FnMut(&T) -> I,
+    I: IntoIterator<Item = T>,
+{
+    pub fn new(rule: R) -> Self {
+        ExpansionCounter {
+            rule,
+            memo: HashMap::new(),
+        }
+    }
+
+    /// How many items `item` becomes after `steps` steps
+    pub fn count(&mut self, item: &T, steps: usize) -> u128 {
+        if steps == 0 {
+            return 1;
+        }
+        if let Some(count) = self.memo.get(&(item.clone(), steps)) {
+            return *count;
+        }
+        let children = (self.rule)(item).into_iter().collect::<Vec<_>>();
+        let count = children
+            .iter()
+            .map(|child| self.count(child, steps - 1))
+            .sum();
+        self.memo.insert((item.clone(), steps), count);
+        count
+    }
+
+    /// How many items the whole multiset becomes after `steps` steps
+    pub fn count_all(&mut self, items: &CountedMultiset<T>, steps: usize) -> u128 {
+        items
+            .iter()
+            .map(|(item, copies)| copies * self.count(item, steps))
+            .sum()
+    }
+
+    /// Number of `(item, remaining steps)` pairs memoized so far
+    pub fn memo_size(&self) -> usize {
+        self.memo.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each fish's timer counts down, and a fish at 0 resets to 6 and spawns a new fish at 8
+    fn lanternfish(timer: &u8) -> Vec<u8> {
+        match timer {
+            0 => vec![6, 8],
+            t => vec![t - 1],
+        }
+    }
+
+    fn example_school() -> CountedMultiset<u8> {
+        [3, 4, 3, 1, 2].into_iter().collect()
+    }
+
+    #[test]
+    fn evolve_moves_every_copy_at_once() {
+        let school = example_school();
+        assert_eq!(school.total(), 5);
+        assert_eq!(school.distinct(), 4);
+        assert_eq!(school.count_of(&3), 2);
+
+        let next = school.evolve(lanternfish);
+        let expected = [2, 3, 2, 0, 1].into_iter().collect::<CountedMultiset<_>>();
+        assert_eq!(next, expected);
+
+        let spawned = next.evolve(lanternfish);
+        assert_eq!(spawned.count_of(&6), 1);
+        assert_eq!(spawned.count_of(&8), 1);
+        assert_eq!(spawned.total(), 6);
+    }
+
+    #[test]
+    fn evolve_runs_the_rule_once_per_distinct_item() {
+        let mut school = CountedMultiset::new();
+        school.insert(0u8, 1_000_000);
+        school.insert(5, 0);
+        assert_eq!(school.distinct(), 1);
+        let mut calls = 0;
+        let next = school.evolve(|timer| {
+            calls += 1;
+            lanternfish(timer)
+        });
+        assert_eq!(calls, 1);
+        assert_eq!(next.count_of(&6), 1_000_000);
+        assert_eq!(next.count_of(&8), 1_000_000);
+    }
+
+    #[test]
+    fn evolve_n_reports_every_step() {
+        let mut totals = Vec::new();
+        let school = example_school().evolve_n(18, lanternfish, |step, counts| {
+            totals.push((step, counts.total()));
+        });
+        assert_eq!(school.total(), 26);
+        assert_eq!(totals.len(), 18);
+        assert_eq!(totals[0], (1, 5));
+        assert_eq!(totals[17], (18, 26));
+        assert!(totals
+            .iter()
+            .enumerate()
+            .all(|(i, (step, _))| *step == i + 1));
+
+        let after_80 = example_school().evolve_n(80, lanternfish, |_, _| {});
+        assert_eq!(after_80.total(), 5934);
+        let after_256 = example_school().evolve_n(256, lanternfish, |_, _| {});
+        assert_eq!(after_256.total(), 26984457539);
+        assert_eq!(
+            example_school().evolve_n(0, lanternfish, |_, _| {}),
+            example_school()
+        );
+    }
+
+    #[test]
+    fn count_all_agrees_with_evolve_n() {
+        let school = example_school();
+        let mut counter = ExpansionCounter::new(lanternfish);
+        assert_eq!(counter.count_all(&school, 0), 5);
+        assert_eq!(counter.count_all(&school, 18), 26);
+        assert_eq!(counter.count_all(&school, 256), 26984457539);
+        // one entry per timer value and remaining step count, at most
+        assert!(counter.memo_size() <= 9 * 256);
+
+        school.evolve_n(100, lanternfish, |step, counts| {
+            assert_eq!(
+                counter.count_all(&school, step),
+                counts.total(),
+                "step {}",
+                step
+            );
+        });
+        assert_eq!(counter.count(&0, 1), 2);
+        assert_eq!(counter.count(&8, 9), 2);
+        assert_eq!(counter.count_all(&CountedMultiset::new(), 10), 0);
+    }
+}
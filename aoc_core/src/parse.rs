@@ -0,0 +1,495 @@
+//! Helpers for the line-based formats that puzzle inputs tend to use.
+//!
+//! The building block is `InputSpan`, a slice of one input line that remembers where it
+//! came from. Every helper reports problems as a `ParseError` pointing at the part of the
+//! input that couldn't be parsed, rather than relying on fixed offsets or `unwrap`.
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Describes a problem with the puzzle input, optionally pointing at where it happened.
+///
+/// When rendered with a location, it points at the offending line with a caret, e.g.
+/// ```text
+/// parse error: missing comma in input line
+///  --> inputs/18.txt:3:1
+///   |
+/// 3 | 12 34
+///   | ^^^^^
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub location: Option<InputLocation>,
+}
+
+/// A position within the puzzle input, along with the text of the line it was found in
+#[derive(Debug, Clone)]
+pub struct InputLocation {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number, counted in chars
+    pub column: usize,
+    /// Number of chars to underline, starting at `column`
+    pub length: usize,
+    /// The full text of the offending line
+    pub snippet: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        ParseError {
+            message: message.into(),
+            file: None,
+            location: None,
+        }
+    }
+
+    /// Point at the entirety of the given line.
+    /// `line_index` is 0-based, as from `lines().enumerate()`.
+    pub fn at_line(self, line_index: usize, line: &str) -> Self {
+        self.at_span(line_index, line, 0, line.chars().count())
+    }
+
+    /// Point at `length` chars of the given line, starting at the 0-based char index `column_index`
+    pub fn at_span(
+        mut self,
+        line_index: usize,
+        line: &str,
+        column_index: usize,
+        length: usize,
+    ) -> Self {
+        self.location = Some(InputLocation {
+            line: line_index + 1,
+            column: column_index + 1,
+            length: length.max(1),
+            snippet: line.to_string(),
+        });
+        self
+    }
+
+    pub fn in_file(mut self, path: &Path) -> Self {
+        self.file = Some(path.to_path_buf());
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse error: {}", self.message)?;
+        let file = self.file.as_ref().map(|p| p.display().to_string());
+        match (&self.location, file) {
+            (Some(loc), file) => {
+                let file = file.unwrap_or_else(|| "<input>".to_string());
+                let gutter = loc.line.to_string().len();
+                writeln!(f)?;
+                writeln!(f, "{:gutter$}--> {}:{}:{}", "", file, loc.line, loc.column)?;
+                writeln!(f, "{:gutter$} |", "")?;
+                writeln!(f, "{} | {}", loc.line, loc.snippet)?;
+                write!(
+                    f,
+                    "{:gutter$} | {}{}",
+                    "",
+                    " ".repeat(loc.column - 1),
+                    "^".repeat(loc.length)
+                )
+            }
+            (None, Some(file)) => write!(f, " (in {})", file),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A slice of one line of the puzzle input, which remembers where it came from
+/// so that parse errors can point back at it.
+#[derive(Copy, Clone, Debug)]
+pub struct InputSpan<'a> {
+    line_index: usize,
+    line: &'a str,
+    /// byte offsets of this span within `line`
+    start: usize,
+    end: usize,
+}
+
+impl<'a> InputSpan<'a> {
+    /// A span covering the whole line. `line_index` is 0-based.
+    pub fn new(line_index: usize, line: &'a str) -> Self {
+        InputSpan {
+            line_index,
+            line,
+            start: 0,
+            end: line.len(),
+        }
+    }
+
+    pub fn text(&self) -> &'a str {
+        &self.line[self.start..self.end]
+    }
+
+    pub fn line_index(&self) -> usize {
+        self.line_index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Sub-span given by byte offsets relative to the start of this span
+    fn slice(&self, start: usize, end: usize) -> InputSpan<'a> {
+        InputSpan {
+            start: self.start + start,
+            end: self.start + end,
+            ..*self
+        }
+    }
+
+    /// Sub-span for a `&str` that was sliced out of `self.text()`
+    fn slice_of(&self, part: &str) -> InputSpan<'a> {
+        let start = part.as_ptr() as usize - self.text().as_ptr() as usize;
+        self.slice(start, start + part.len())
+    }
+
+    /// Create an error pointing at this span
+    pub fn error<S: Into<String>>(&self, message: S) -> ParseError {
+        let column = self.line[..self.start].chars().count();
+        let length = self.text().chars().count();
+        ParseError::new(message).at_span(self.line_index, self.line, column, length)
+    }
+
+    /// Parse the whole span as a `T`
+    pub fn parse<T>(&self) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.text()
+            .parse::<T>()
+            .map_err(|err| self.error(format!("couldn't parse {:?}: {}", self.text(), err)))
+    }
+
+    pub fn trim(&self) -> InputSpan<'a> {
+        self.slice_of(self.text().trim())
+    }
+
+    /// Require the span to start with `prefix`, returning the rest of it
+    pub fn strip_prefix(&self, prefix: &str) -> Result<InputSpan<'a>, ParseError> {
+        match self.text().strip_prefix(prefix) {
+            Some(rest) => Ok(self.slice_of(rest)),
+            None => Err(self.error(format!("expected {:?}", prefix))),
+        }
+    }
+
+    /// Split around the first occurrence of `separator`, which must be present
+    pub fn split_once(
+        &self,
+        separator: &str,
+    ) -> Result<(InputSpan<'a>, InputSpan<'a>), ParseError> {
+        match self.text().split_once(separator) {
+            Some((l, r)) => Ok((self.slice_of(l), self.slice_of(r))),
+            None => Err(self.error(format!("missing {:?}", separator))),
+        }
+    }
+
+    /// Split a `key: value` line, returning both (trimmed) sides
+    pub fn split_key_value(&self) -> Result<(InputSpan<'a>, InputSpan<'a>), ParseError> {
+        let (key, value) = self.split_once(":")?;
+        Ok((key.trim(), value.trim()))
+    }
+
+    /// For a `key: value` line with the expected `key`, return the (trimmed) value
+    pub fn key_value(&self, key: &str) -> Result<InputSpan<'a>, ParseError> {
+        let (actual_key, value) = self.split_key_value()?;
+        if actual_key.text() == key {
+            Ok(value)
+        } else {
+            Err(actual_key.error(format!("expected key {:?}", key)))
+        }
+    }
+
+    /// Split into the parts between each `separator`, trimming each part.
+    /// A whitespace `separator` splits on runs of any whitespace, ignoring leading and trailing whitespace.
+    pub fn split(&self, separator: char) -> Vec<InputSpan<'a>> {
+        let text = self.text();
+        if separator.is_whitespace() {
+            text.split_whitespace()
+                .map(|part| self.slice_of(part))
+                .collect()
+        } else {
+            text.split(separator)
+                .map(|part| self.slice_of(part).trim())
+                .collect()
+        }
+    }
+
+    /// Parse a `separator`-delimited list of values, e.g. `1,2,3` or `7 13 42`.
+    /// Blank input gives an empty list.
+    pub fn list<T>(&self, separator: char) -> Result<Vec<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if self.text().trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        self.split(separator)
+            .iter()
+            .map(|part| part.parse())
+            .collect()
+    }
+
+    /// Find every integer in the span, ignoring any other text, e.g. `p=0,4 v=3,-3`
+    /// gives `[0, 4, 3, -3]`. A `-` directly before the digits (and not directly after
+    /// another digit) is treated as a sign.
+    pub fn integer_spans(&self) -> Vec<InputSpan<'a>> {
+        let bytes = self.text().as_bytes();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let is_sign = bytes[i] == b'-'
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+                && !(i > 0 && bytes[i - 1].is_ascii_digit());
+            if is_sign || bytes[i].is_ascii_digit() {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                out.push(self.slice(start, i));
+            } else {
+                i += 1;
+            }
+        }
+        out
+    }
+
+    /// Parse every integer in the span (see `integer_spans`)
+    pub fn integers<T>(&self) -> Result<Vec<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.integer_spans()
+            .iter()
+            .map(|span| span.parse())
+            .collect()
+    }
+
+    /// Like `integers`, but requiring exactly `N` of them
+    pub fn integers_n<T, const N: usize>(&self) -> Result<[T; N], ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let values = self.integers::<T>()?;
+        let found = values.len();
+        values
+            .try_into()
+            .map_err(|_| self.error(format!("expected {} numbers, found {}", N, found)))
+    }
+
+    /// Split into fixed-width columns, with widths counted in chars.
+    /// The final column may be cut short by the end of the line, but every column must start within it.
+    pub fn columns(&self, widths: &[usize]) -> Result<Vec<InputSpan<'a>>, ParseError> {
+        let text = self.text();
+        // byte offset of each char boundary, including the end of the text
+        let boundaries = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect::<Vec<_>>();
+        let num_chars = boundaries.len() - 1;
+
+        let mut out = Vec::with_capacity(widths.len());
+        let mut char_pos = 0;
+        for (i, width) in widths.iter().enumerate() {
+            if char_pos >= num_chars && *width > 0 {
+                return Err(self.error(format!(
+                    "expected {} columns, but the line ended after {}",
+                    widths.len(),
+                    i
+                )));
+            }
+            let end = (char_pos + width).min(num_chars);
+            out.push(self.slice(boundaries[char_pos], boundaries[end]));
+            char_pos = end;
+        }
+        Ok(out)
+    }
+}
+
+impl Display for InputSpan<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+/// Pair each line with its index, as `InputSpan`s
+pub fn spans<S: AsRef<str>>(lines: &[S]) -> impl Iterator<Item = InputSpan<'_>> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| InputSpan::new(i, line.as_ref()))
+}
+
+/// Group lines into sections separated by (one or more) blank lines
+pub fn sections<S: AsRef<str>>(lines: &[S]) -> Vec<Vec<InputSpan<'_>>> {
+    let mut out = Vec::new();
+    let mut current = Vec::new();
+    for span in spans(lines) {
+        if span.text().trim().is_empty() {
+            if !current.is_empty() {
+                out.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(span);
+        }
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+/// Require a section to have exactly `N` lines
+pub fn expect_lines<'a, const N: usize>(
+    section: &[InputSpan<'a>],
+) -> Result<[InputSpan<'a>; N], ParseError> {
+    section.try_into().map_err(|_| {
+        let err = ParseError::new(format!(
+            "expected a section of {} lines, found {}",
+            N,
+            section.len()
+        ));
+        match section.first() {
+            Some(first) => ParseError {
+                location: first.error("").location,
+                ..err
+            },
+            None => err,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts<'a>(spans: &[InputSpan<'a>]) -> Vec<&'a str> {
+        spans.iter().map(|span| span.text()).collect()
+    }
+
+    #[test]
+    fn integer_spans_sign_rules() {
+        let line = InputSpan::new(0, "p=0,4 v=3,-3");
+        assert_eq!(line.integers::<i64>().unwrap(), vec![0, 4, 3, -3]);
+        let spans = line.integer_spans();
+        assert_eq!(texts(&spans), vec!["0", "4", "3", "-3"]);
+        assert_eq!(spans[3].error("").location.unwrap().column, 11);
+
+        // a `-` straight after a digit is a separator, not a sign
+        let range = InputSpan::new(0, "1-2");
+        assert_eq!(range.integers::<i64>().unwrap(), vec![1, 2]);
+        let mixed = InputSpan::new(0, "a-3 --7 - 8");
+        assert_eq!(mixed.integers::<i64>().unwrap(), vec![-3, -7, 8]);
+        assert_eq!(
+            InputSpan::new(0, "1 2")
+                .integers_n::<u8, 3>()
+                .unwrap_err()
+                .message,
+            "expected 3 numbers, found 2"
+        );
+    }
+
+    #[test]
+    fn columns_count_chars_and_allow_a_short_last_column() {
+        let line = InputSpan::new(0, "αβγdef");
+        let columns = line.columns(&[2, 1, 5]).unwrap();
+        assert_eq!(texts(&columns), vec!["αβ", "γ", "def"]);
+        assert_eq!(columns[1].error("").location.unwrap().column, 3);
+        assert_eq!(columns[2].error("").location.unwrap().length, 3);
+
+        let err = InputSpan::new(4, "ab").columns(&[1, 1, 3]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "expected 3 columns, but the line ended after 2"
+        );
+        assert_eq!(err.location.unwrap().line, 5);
+        // empty columns past the end are fine
+        let columns = InputSpan::new(0, "ab").columns(&[2, 0]).unwrap();
+        assert_eq!(texts(&columns), vec!["ab", ""]);
+    }
+
+    #[test]
+    fn key_value_errors_point_at_the_problem() {
+        let line = InputSpan::new(0, "Speed: 5");
+        assert_eq!(line.key_value("Speed").unwrap().parse::<u32>().unwrap(), 5);
+
+        let err = line.key_value("Time").unwrap_err();
+        assert_eq!(err.message, "expected key \"Time\"");
+        let loc = err.location.unwrap();
+        assert_eq!((loc.column, loc.length), (1, 5));
+
+        let err = InputSpan::new(0, "Speed 5").key_value("Speed").unwrap_err();
+        assert_eq!(err.message, "missing \":\"");
+        let err = InputSpan::new(0, "Speed: fast")
+            .key_value("Speed")
+            .unwrap()
+            .parse::<u32>();
+        let loc = err.unwrap_err().location.unwrap();
+        assert_eq!((loc.column, loc.length), (8, 4));
+    }
+
+    #[test]
+    fn sections_skip_repeated_blank_lines() {
+        let lines = ["", "a", "b", "", "", "  ", "c", "", ""];
+        let found = sections(&lines);
+        assert_eq!(found.len(), 2);
+        assert_eq!(texts(&found[0]), vec!["a", "b"]);
+        assert_eq!(texts(&found[1]), vec!["c"]);
+        assert_eq!(found[1][0].line_index(), 6);
+        assert!(sections::<&str>(&[]).is_empty());
+    }
+
+    #[test]
+    fn expect_lines_reports_the_section_size() {
+        let lines = ["x", "y", "z"];
+        let section = sections(&lines).remove(0);
+        let [a, b, c] = expect_lines::<3>(&section).unwrap();
+        assert_eq!((a.text(), b.text(), c.text()), ("x", "y", "z"));
+
+        let err = expect_lines::<2>(&section).unwrap_err();
+        assert_eq!(err.message, "expected a section of 2 lines, found 3");
+        let loc = err.location.unwrap();
+        assert_eq!((loc.line, loc.snippet.as_str()), (1, "x"));
+
+        let err = expect_lines::<2>(&[]).unwrap_err();
+        assert_eq!(err.message, "expected a section of 2 lines, found 0");
+        assert!(err.location.is_none());
+    }
+
+    #[test]
+    fn display_puts_the_caret_under_the_column() {
+        let err = ParseError::new("bad word").at_span(11, "abc def", 4, 3);
+        assert_eq!(
+            err.to_string(),
+            "parse error: bad word\n  --> <input>:12:5\n   |\n12 | abc def\n   |     ^^^"
+        );
+
+        let err = ParseError::new("empty")
+            .at_line(0, "")
+            .in_file(Path::new("in.txt"));
+        assert_eq!(
+            err.to_string(),
+            "parse error: empty\n --> in.txt:1:1\n  |\n1 | \n  | ^"
+        );
+
+        let err = ParseError::new("no location");
+        assert_eq!(err.to_string(), "parse error: no location");
+        let err = err.in_file(Path::new("in.txt"));
+        assert_eq!(err.to_string(), "parse error: no location (in in.txt)");
+    }
+}
@@ -0,0 +1,83 @@
+//! A tiny random number generator, for tests that want varied inputs that are the same on every run.
+//!
+//! This is Marsaglia's 64-bit xorshift: fast and simple, but nowhere near good enough for
+//! anything that needs real randomness.
+
+/// A seeded xorshift generator
+#[derive(Debug, Clone)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    /// Start a generator from `seed`, which must not be zero (that would only ever produce zeros)
+    pub fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift seed must be non-zero");
+        XorShift { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in `0..n`, which must not be empty.
+    /// Slightly biased towards small numbers, which doesn't matter for tests.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "can't pick a number below 0");
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// One of `items`, which must not be empty
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    /// Put `items` in a random order
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let mut a = XorShift::new(42);
+        let mut b = XorShift::new(42);
+        let from_a = (0..100).map(|_| a.next_u64()).collect::<Vec<_>>();
+        let from_b = (0..100).map(|_| b.next_u64()).collect::<Vec<_>>();
+        assert_eq!(from_a, from_b);
+        assert!(from_a.iter().all(|n| *n != 0));
+        let mut c = XorShift::new(43);
+        assert_ne!(c.next_u64(), from_a[0]);
+    }
+
+    #[test]
+    fn below_stays_in_range_and_covers_it() {
+        let mut rng = XorShift::new(7);
+        let mut seen = [false; 10];
+        for _ in 0..1000 {
+            seen[rng.below(10)] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+        assert_eq!(rng.below(1), 0);
+    }
+
+    #[test]
+    fn shuffle_keeps_every_item() {
+        let mut rng = XorShift::new(99);
+        let mut items = (0..50).collect::<Vec<_>>();
+        rng.shuffle(&mut items);
+        assert_ne!(items, (0..50).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..50).collect::<Vec<_>>());
+        assert!([1, 2, 3].contains(rng.pick(&[1, 2, 3])));
+    }
+}
@@ -0,0 +1,425 @@
+//! A prefix tree keyed by strings, and an Aho-Corasick automaton built on top of it.
+//!
+//! `Trie` answers "which keys are prefixes of this text?" for one starting point at a time.
+//! `AhoCorasick` answers "where does every key occur in this text?" in a single pass, by
+//! adding failure links to the trie so that a mismatch never needs to back up in the text.
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// A map from strings to values, stored as a tree of chars so that lookups by prefix are cheap
+#[derive(Debug, Clone)]
+pub struct Trie<V> {
+    /// All nodes, with the root at index 0. Children refer to each other by index.
+    nodes: Vec<Node<V>>,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node<V> {
+    children: BTreeMap<char, usize>,
+    /// Set if the path to this node spells out a key
+    value: Option<V>,
+    /// Length in bytes of the path to this node
+    depth: usize,
+}
+
+impl<V> Node<V> {
+    fn new(depth: usize) -> Self {
+        Node {
+            children: BTreeMap::new(),
+            value: None,
+            depth,
+        }
+    }
+}
+
+const ROOT: usize = 0;
+
+impl<V> Default for Trie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Trie<V> {
+    pub fn new() -> Self {
+        Trie {
+            nodes: vec![Node::new(0)],
+            len: 0,
+        }
+    }
+
+    /// Number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a key, returning the value it previously had, if any
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
+        let mut node = ROOT;
+        for c in key.chars() {
+            node = match self.nodes[node].children.get(&c) {
+                Some(child) => *child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes
+                        .push(Node::new(self.nodes[node].depth + c.len_utf8()));
+                    self.nodes[node].children.insert(c, child);
+                    child
+                }
+            };
+        }
+        let previous = self.nodes[node].value.replace(value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let mut node = ROOT;
+        for c in key.chars() {
+            node = *self.nodes[node].children.get(&c)?;
+        }
+        self.nodes[node].value.as_ref()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Iterate over the keys that are prefixes of `text`, shortest first, as
+    /// `(length in bytes, value)` pairs. The empty string counts if it was inserted.
+    pub fn prefixes_of<'t>(&'t self, text: &'t str) -> Prefixes<'t, V> {
+        Prefixes {
+            trie: self,
+            node: Some(ROOT),
+            rest: text.chars(),
+        }
+    }
+}
+
+impl<'k, V> FromIterator<(&'k str, V)> for Trie<V> {
+    fn from_iter<T: IntoIterator<Item = (&'k str, V)>>(iter: T) -> Self {
+        let mut trie = Trie::new();
+        for (key, value) in iter {
+            trie.insert(key, value);
+        }
+        trie
+    }
+}
+
+/// Iterator returned by `Trie::prefixes_of`
+pub struct Prefixes<'t, V> {
+    trie: &'t Trie<V>,
+    /// Node for the part of the text consumed so far, or `None` once it falls off the trie
+    node: Option<usize>,
+    rest: std::str::Chars<'t>,
+}
+
+impl<'t, V> Iterator for Prefixes<'t, V> {
+    type Item = (usize, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.trie.nodes[self.node?];
+            // step to the next node before yielding this one's value, so the next call moves on
+            self.node = self
+                .rest
+                .next()
+                .and_then(|c| node.children.get(&c).copied());
+            if let Some(value) = &node.value {
+                return Some((node.depth, value));
+            }
+        }
+    }
+}
+
+/// One occurrence of a key in the text searched by `AhoCorasick::find_all`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Match<'t, V> {
+    /// Byte offset where the key starts
+    pub start: usize,
+    /// Byte offset just past the end of the key
+    pub end: usize,
+    pub value: &'t V,
+}
+
+/// A trie with extra links for finding every occurrence of every key in a single pass over a text
+#[derive(Debug, Clone)]
+pub struct AhoCorasick<V> {
+    trie: Trie<V>,
+    /// For each node, the node for its longest proper suffix that is also in the trie
+    fail: Vec<usize>,
+    /// For each node, the nearest node along its chain of failure links that holds a key
+    /// (not counting the node itself)
+    output: Vec<Option<usize>>,
+}
+
+impl<V> AhoCorasick<V> {
+    pub fn new(trie: Trie<V>) -> Self {
+        let mut fail = vec![ROOT; trie.nodes.len()];
+        let mut output = vec![None; trie.nodes.len()];
+
+        // breadth-first, so every node's failure link points at an already-finished node
+        let mut queue = trie.nodes[ROOT]
+            .children
+            .values()
+            .copied()
+            .collect::<VecDeque<_>>();
+        while let Some(node) = queue.pop_front() {
+            for (c, child) in &trie.nodes[node].children {
+                // follow the parent's failure links until one can be extended by `c`
+                let mut f = fail[node];
+                let target = loop {
+                    if let Some(next) = trie.nodes[f].children.get(c) {
+                        break *next;
+                    }
+                    if f == ROOT {
+                        break ROOT;
+                    }
+                    f = fail[f];
+                };
+                fail[*child] = target;
+                output[*child] = if trie.nodes[target].value.is_some() {
+                    Some(target)
+                } else {
+                    output[target]
+                };
+                queue.push_back(*child);
+            }
+        }
+
+        AhoCorasick { trie, fail, output }
+    }
+
+    pub fn trie(&self) -> &Trie<V> {
+        &self.trie
+    }
+
+    /// Every occurrence of every key in `text`, including overlapping ones,
+    /// ordered by where they end (and longest first among those ending at the same place).
+    /// Empty keys are never reported.
+    pub fn find_all<'t>(&'t self, text: &str) -> Vec<Match<'t, V>> {
+        let mut out = Vec::new();
+        let mut node = ROOT;
+        for (offset, c) in text.char_indices() {
+            node = loop {
+                if let Some(next) = self.trie.nodes[node].children.get(&c) {
+                    break *next;
+                }
+                if node == ROOT {
+                    break ROOT;
+                }
+                node = self.fail[node];
+            };
+
+            let end = offset + c.len_utf8();
+            let mut found = Some(node)
+                .filter(|n| self.trie.nodes[*n].value.is_some())
+                .or(self.output[node]);
+            while let Some(n) = found {
+                let key = &self.trie.nodes[n];
+                if let (Some(value), true) = (&key.value, key.depth > 0) {
+                    out.push(Match {
+                        start: end - key.depth,
+                        end,
+                        value,
+                    });
+                }
+                found = self.output[n];
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    /// Index of the node reached by spelling out `key`
+    fn node_for<V>(trie: &Trie<V>, key: &str) -> usize {
+        key.chars()
+            .fold(ROOT, |node, c| trie.nodes[node].children[&c])
+    }
+
+    /// Every `(start, end, value)` occurrence of every non-empty key, found by checking each one
+    /// at each position, in the order `find_all` promises
+    fn brute_force_find_all(keys: &[&str], text: &str) -> Vec<(usize, usize, usize)> {
+        let mut out = Vec::new();
+        for start in (0..=text.len()).filter(|i| text.is_char_boundary(*i)) {
+            for (value, key) in keys.iter().enumerate() {
+                if !key.is_empty() && text[start..].starts_with(key) {
+                    out.push((start, start + key.len(), value));
+                }
+            }
+        }
+        out.sort_by_key(|(start, end, _)| (*end, *start));
+        out
+    }
+
+    #[test]
+    fn prefixes_are_listed_shortest_first() {
+        let trie = ["a", "ab", "abc", "b", "abd"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, key)| (key, i))
+            .collect::<Trie<_>>();
+        let found = trie.prefixes_of("abcd").collect::<Vec<_>>();
+        assert_eq!(found, [(1, &0), (2, &1), (3, &2)]);
+        assert_eq!(trie.prefixes_of("b").collect::<Vec<_>>(), [(1, &3)]);
+        assert_eq!(trie.prefixes_of("ca").count(), 0);
+        assert_eq!(trie.prefixes_of("").count(), 0);
+    }
+
+    #[test]
+    fn empty_key_is_a_prefix_of_everything() {
+        let mut trie = Trie::new();
+        assert_eq!(trie.insert("", 'e'), None);
+        trie.insert("x", 'x');
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.prefixes_of("").collect::<Vec<_>>(), [(0, &'e')]);
+        assert_eq!(
+            trie.prefixes_of("xy").collect::<Vec<_>>(),
+            [(0, &'e'), (1, &'x')]
+        );
+        assert_eq!(trie.prefixes_of("y").collect::<Vec<_>>(), [(0, &'e')]);
+    }
+
+    #[test]
+    fn prefix_lengths_are_in_bytes() {
+        let trie = [("é", 1), ("éa", 2), ("日本", 3)]
+            .into_iter()
+            .collect::<Trie<_>>();
+        assert_eq!(
+            trie.prefixes_of("éab").collect::<Vec<_>>(),
+            [(2, &1), (3, &2)]
+        );
+        assert_eq!(trie.prefixes_of("日本語").collect::<Vec<_>>(), [(6, &3)]);
+        assert!(trie.contains_key("日本"));
+        assert!(!trie.contains_key("日"));
+    }
+
+    #[test]
+    fn reinserting_a_key_replaces_its_value() {
+        let mut trie = Trie::new();
+        assert_eq!(trie.insert("ab", 1), None);
+        assert_eq!(trie.insert("ab", 2), Some(1));
+        assert_eq!(trie.len(), 1);
+        assert_eq!(trie.get("ab"), Some(&2));
+        assert_eq!(trie.get("a"), None);
+    }
+
+    #[test]
+    fn failure_links_point_at_the_longest_suffix_in_the_trie() {
+        let keys = ["he", "she", "his", "hers"];
+        let automaton = AhoCorasick::new(keys.iter().map(|key| (*key, ())).collect());
+        let trie = automaton.trie();
+        let fail_of = |key: &str| automaton.fail[node_for(trie, key)];
+        let output_of = |key: &str| automaton.output[node_for(trie, key)];
+
+        assert_eq!(fail_of("h"), ROOT);
+        assert_eq!(fail_of("s"), ROOT);
+        assert_eq!(fail_of("sh"), node_for(trie, "h"));
+        assert_eq!(fail_of("she"), node_for(trie, "he"));
+        assert_eq!(fail_of("his"), node_for(trie, "s"));
+        assert_eq!(fail_of("hers"), node_for(trie, "s"));
+        assert_eq!(fail_of("her"), ROOT);
+
+        // "she" ends with the key "he"; "his" ends with "s", which isn't a key
+        assert_eq!(output_of("she"), Some(node_for(trie, "he")));
+        assert_eq!(output_of("his"), None);
+        assert_eq!(output_of("he"), None);
+    }
+
+    #[test]
+    fn find_all_reports_overlapping_matches() {
+        let keys = ["he", "she", "his", "hers"];
+        let automaton = AhoCorasick::new(keys.iter().map(|key| (*key, ())).collect());
+        let found = automaton
+            .find_all("ushers")
+            .into_iter()
+            .map(|m| (m.start, m.end))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [(1, 4), (2, 4), (2, 6)]);
+    }
+
+    #[test]
+    fn find_all_skips_empty_keys_and_counts_bytes() {
+        let keys = ["", "é", "éé", "aé", "日"];
+        let automaton = AhoCorasick::new(keys.iter().enumerate().map(|(i, k)| (*k, i)).collect());
+        let found = automaton
+            .find_all("aéé日")
+            .into_iter()
+            .map(|m| (m.start, m.end, *m.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [(0, 3, 3), (1, 3, 1), (1, 5, 2), (3, 5, 1), (5, 8, 4)]
+        );
+        assert!(automaton.find_all("").is_empty());
+    }
+
+    #[test]
+    fn find_all_matches_a_brute_force_search() {
+        let mut rng = XorShift::new(0xd1b5_4a32_d192_ed03);
+        let alphabet = ['a', 'b', 'é'];
+        let mut random_string = |max_len: usize| {
+            let len = rng.below(max_len + 1);
+            (0..len)
+                .map(|_| alphabet[rng.below(alphabet.len())])
+                .collect::<String>()
+        };
+        for _ in 0..200 {
+            let mut keys = (0..5).map(|_| random_string(4)).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            let text = random_string(30);
+
+            let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+            let automaton =
+                AhoCorasick::new(keys.iter().enumerate().map(|(i, k)| (*k, i)).collect());
+            let found = automaton
+                .find_all(&text)
+                .into_iter()
+                .map(|m| (m.start, m.end, *m.value))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                brute_force_find_all(&keys, &text),
+                "keys {:?} in {:?}",
+                keys,
+                text
+            );
+
+            // and the trie alone finds the same matches one start at a time
+            for (start, _) in text.char_indices() {
+                let prefixes = automaton
+                    .trie()
+                    .prefixes_of(&text[start..])
+                    .map(|(len, value)| (len, *value))
+                    .collect::<Vec<_>>();
+                let mut expected = keys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, key)| text[start..].starts_with(**key))
+                    .map(|(value, key)| (key.len(), value))
+                    .collect::<Vec<_>>();
+                expected.sort();
+                assert_eq!(
+                    prefixes,
+                    expected,
+                    "keys {:?} in {:?}",
+                    keys,
+                    &text[start..]
+                );
+            }
+        }
+    }
+}